@@ -1,19 +1,22 @@
 use std::{
     borrow::Cow,
     cmp::Ordering,
-    collections::BTreeSet,
+    collections::{BTreeSet, HashMap},
     ops::Range,
     sync::{
         atomic::{self, AtomicU64},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use lsp_types::Position;
+use unicode_width::UnicodeWidthChar;
 use xi_rope::{
     diff::{Diff, LineHashDiff},
     multiset::Subset,
-    Cursor, Delta, DeltaBuilder, Interval, Rope, RopeDelta,
+    Cursor, Delta, DeltaBuilder, DeltaElement, Interval, Rope, RopeDelta,
+    Transformer,
 };
 
 use crate::{
@@ -21,8 +24,9 @@ use crate::{
     editor::EditType,
     indent::{auto_detect_indent_style, IndentStyle},
     mode::Mode,
+    movement::CharSearch,
     selection::Selection,
-    syntax::Syntax,
+    syntax::{matching_pair_direction, Syntax},
     word::WordCursor,
 };
 
@@ -66,6 +70,23 @@ pub struct InvalLines {
     pub new_count: usize,
 }
 
+/// A replacement of a range of the text before an edit, in the shape of a
+/// language server content change event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentChange {
+    pub range: lsp_types::Range,
+    pub new_text: String,
+}
+
+/// The characters that end a line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    CrLf,
+}
+
 #[derive(Clone)]
 pub struct Buffer {
     rev_counter: u64,
@@ -83,14 +104,26 @@ pub struct Buffer {
     tombstones: Rope,
     this_edit_type: EditType,
     last_edit_type: EditType,
+    this_edit_time: Option<Instant>,
+    last_edit_time: Option<Instant>,
+    undo_group_threshold: Duration,
 
     indent_style: IndentStyle,
 
     max_len: usize,
     max_len_line: usize,
+
+    /// Named positions, like the marks of vim, which follow the edits.
+    marks: HashMap<char, usize>,
 }
 
 impl Buffer {
+    /// The pause after which typed characters start a new undo group.
+    pub const DEFAULT_UNDO_GROUP_THRESHOLD: Duration = Duration::from_secs(2);
+
+    /// The mark holding the position before the latest jump.
+    pub const JUMP_MARK: char = '`';
+
     pub fn new(text: &str) -> Self {
         Self {
             text: Rope::from(text),
@@ -119,10 +152,15 @@ impl Buffer {
 
             this_edit_type: EditType::Other,
             last_edit_type: EditType::Other,
+            this_edit_time: None,
+            last_edit_time: None,
+            undo_group_threshold: Self::DEFAULT_UNDO_GROUP_THRESHOLD,
             indent_style: IndentStyle::DEFAULT_INDENT,
 
             max_len: 0,
             max_len_line: 0,
+
+            marks: HashMap::new(),
         }
     }
 
@@ -138,6 +176,15 @@ impl Buffer {
         self.is_equivalent_revision(self.pristine_rev_id, self.rev())
     }
 
+    /// Stores `offset` under the mark `name`, replacing what it held before.
+    pub fn set_mark(&mut self, name: char, offset: usize) {
+        self.marks.insert(name, offset.min(self.len()));
+    }
+
+    pub fn mark(&self, name: char) -> Option<usize> {
+        self.marks.get(&name).copied()
+    }
+
     pub fn set_cursor_before(&mut self, cursor: CursorMode) {
         if let Some(rev) = self.revs.last_mut() {
             rev.cursor_before = Some(cursor);
@@ -150,6 +197,25 @@ impl Buffer {
         }
     }
 
+    /// Records the cursor from before and after the revisions made since
+    /// `rev`, which undo and redo restore.
+    pub fn set_edit_cursors(
+        &mut self,
+        rev: u64,
+        cursor_before: CursorMode,
+        cursor_after: CursorMode,
+    ) {
+        let first = match self.find_rev(rev) {
+            Some(index) => index + 1,
+            None => return,
+        };
+        if first >= self.revs.len() {
+            return;
+        }
+        self.revs[first].cursor_before = Some(cursor_before);
+        self.set_cursor_after(cursor_after);
+    }
+
     fn is_equivalent_revision(&self, base_rev: u64, other_rev: u64) -> bool {
         let base_subset = self
             .find_rev(base_rev)
@@ -170,6 +236,16 @@ impl Buffer {
             .map(|(i, _)| i)
     }
 
+    /// A copy of the buffer to try edits on, which doesn't share its
+    /// revision counter with the original.
+    pub(crate) fn detached(&self) -> Buffer {
+        let mut buffer = self.clone();
+        buffer.atomic_rev = Arc::new(AtomicU64::new(
+            self.atomic_rev.load(atomic::Ordering::Acquire),
+        ));
+        buffer
+    }
+
     pub fn atomic_rev(&self) -> Arc<AtomicU64> {
         self.atomic_rev.clone()
     }
@@ -282,6 +358,30 @@ impl Buffer {
         self.last_edit_type = EditType::Other
     }
 
+    pub fn set_undo_group_threshold(&mut self, threshold: Duration) {
+        self.undo_group_threshold = threshold;
+    }
+
+    /// Like [`Buffer::edit`], but typing at `time` after a long enough pause
+    /// since the previous timed edit starts a new undo group.
+    pub fn edit_at(
+        &mut self,
+        edits: &[(impl AsRef<Selection>, &str)],
+        edit_type: EditType,
+        time: Instant,
+    ) -> (RopeDelta, InvalLines) {
+        self.set_edit_time(Some(time));
+        let result = self.edit(edits, edit_type);
+        self.set_edit_time(None);
+        result
+    }
+
+    /// Makes the following edits count as made at `time`, like with
+    /// [`Buffer::edit_at`], until it gets set back to `None`.
+    pub fn set_edit_time(&mut self, time: Option<Instant>) {
+        self.this_edit_time = time;
+    }
+
     pub fn edit(
         &mut self,
         edits: &[(impl AsRef<Selection>, &str)],
@@ -315,6 +415,7 @@ impl Buffer {
     fn add_delta(&mut self, delta: RopeDelta) -> (RopeDelta, InvalLines) {
         let undo_group = self.calculate_undo_group();
         self.last_edit_type = self.this_edit_type;
+        self.last_edit_time = self.this_edit_time;
 
         let (new_rev, new_text, new_tombstones, new_deletes_from_union) =
             self.mk_new_rev(undo_group, delta.clone());
@@ -343,6 +444,12 @@ impl Buffer {
         let (iv, newlen) = delta.summary();
         let old_logical_end_line = self.text.line_of_offset(iv.end) + 1;
 
+        // Marks in deleted text end up where the deletion was
+        let mut transformer = Transformer::new(delta);
+        for offset in self.marks.values_mut() {
+            *offset = transformer.transform(*offset, false);
+        }
+
         self.revs.push(new_rev);
         self.text = new_text;
         self.tombstones = new_tombstones;
@@ -365,8 +472,15 @@ impl Buffer {
 
     fn calculate_undo_group(&mut self) -> usize {
         let has_undos = !self.live_undos.is_empty();
-        let is_unbroken_group =
-            !self.this_edit_type.breaks_undo_group(self.last_edit_type);
+        let elapsed = match (self.this_edit_time, self.last_edit_time) {
+            (Some(this), Some(last)) => Some(this.saturating_duration_since(last)),
+            _ => None,
+        };
+        let is_unbroken_group = !self.this_edit_type.breaks_undo_group_after(
+            self.last_edit_type,
+            elapsed,
+            self.undo_group_threshold,
+        );
 
         if has_undos && is_unbroken_group {
             *self.live_undos.last().unwrap()
@@ -738,6 +852,22 @@ impl Buffer {
         offset
     }
 
+    /// The offset of the `col`th grapheme of `line`, counting from 0. Lines
+    /// and columns past the end are clamped to it, the end of a line being
+    /// right before its line ending when `caret` is set, or else on its last
+    /// character.
+    pub fn offset_of_line_grapheme(
+        &self,
+        line: usize,
+        col: usize,
+        caret: bool,
+    ) -> usize {
+        let line = line.min(self.last_line());
+        let line_start = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, caret);
+        self.next_grapheme_offset(line_start, col, line_end)
+    }
+
     pub fn line_end_col(&self, line: usize, caret: bool) -> usize {
         let line_start = self.offset_of_line(line);
         let offset = self.line_end_offset(line, caret);
@@ -838,6 +968,105 @@ impl Buffer {
         self.next_grapheme_offset(offset, count, max_offset)
     }
 
+    /// The visual column of `offset` on its line, wide characters such as
+    /// CJK ideographs and most emoji taking two columns, zero width ones none
+    /// and tabs reaching up to the next multiple of `tab_width`.
+    pub fn visual_col_of_offset(&self, offset: usize, tab_width: usize) -> usize {
+        let offset = offset.min(self.len());
+        let mut current = self.offset_of_line(self.line_of_offset(offset));
+        let mut col = 0;
+        while current < offset {
+            let next = self.next_grapheme_offset(current, 1, offset);
+            if next == current {
+                break;
+            }
+            col += grapheme_width(&self.slice_to_cow(current..next), col, tab_width);
+            current = next;
+        }
+        col
+    }
+
+    /// The offset of the grapheme covering visual column `col` of `line`,
+    /// clamped to the end of the line like in
+    /// [`Buffer::offset_of_line_grapheme`].
+    pub fn offset_of_visual_col(
+        &self,
+        line: usize,
+        col: usize,
+        tab_width: usize,
+        caret: bool,
+    ) -> usize {
+        let line = line.min(self.last_line());
+        let mut offset = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, caret);
+        let mut current_col = 0;
+        while offset < line_end {
+            let next = self.next_grapheme_offset(offset, 1, line_end);
+            let width = grapheme_width(
+                &self.slice_to_cow(offset..next),
+                current_col,
+                tab_width,
+            );
+            if next == offset || current_col + width > col {
+                break;
+            }
+            current_col += width;
+            offset = next;
+        }
+        offset
+    }
+
+    /// Where `search` repeated `count` times takes `offset`, staying on its
+    /// line. Returns `None` when the character doesn't occur often enough.
+    pub fn find_char_on_line(
+        &self,
+        offset: usize,
+        search: &CharSearch,
+        count: usize,
+    ) -> Option<usize> {
+        let line = self.line_of_offset(offset);
+        let line_start = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, true);
+        let next = |offset: usize| self.next_grapheme_offset(offset, 1, line_end);
+        let prev = |offset: usize| self.prev_grapheme_offset(offset, 1, line_start);
+        let step = |offset: usize| {
+            if search.forward {
+                next(offset)
+            } else {
+                prev(offset)
+            }
+        };
+        let target = search.c.to_string();
+        let is_target = |offset: usize| {
+            let end = next(offset);
+            end > offset && self.slice_to_cow(offset..end) == target
+        };
+
+        // Stopping before the character skips over one that is right there,
+        // so that repeating the search moves on
+        let mut current = if search.till { step(offset) } else { offset };
+        for _ in 0..count.max(1) {
+            loop {
+                let new = step(current);
+                if new == current {
+                    return None;
+                }
+                current = new;
+                if is_target(current) {
+                    break;
+                }
+            }
+        }
+        if search.till {
+            current = if search.forward {
+                prev(current)
+            } else {
+                next(current)
+            };
+        }
+        Some(current)
+    }
+
     pub fn move_word_forward(&self, offset: usize) -> usize {
         self.move_n_words_forward(offset, 1)
     }
@@ -846,6 +1075,16 @@ impl Buffer {
         self.move_n_words_backward(offset, 1)
     }
 
+    /// The start of the text removed by deleting a word backward from `offset`.
+    pub fn prev_word_deletion_boundary(&self, offset: usize) -> usize {
+        WordCursor::new(&self.text, offset).prev_deletion_boundary()
+    }
+
+    /// The end of the text removed by deleting a word forward from `offset`.
+    pub fn next_word_deletion_boundary(&self, offset: usize) -> usize {
+        WordCursor::new(&self.text, offset).next_deletion_boundary()
+    }
+
     pub fn next_grapheme_offset(
         &self,
         offset: usize,
@@ -903,6 +1142,48 @@ impl Buffer {
         }
     }
 
+    pub fn next_unmatched(
+        &self,
+        syntax: Option<&Syntax>,
+        c: char,
+        offset: usize,
+    ) -> Option<usize> {
+        if let Some(syntax) = syntax {
+            syntax.find_tag(offset, false, &c.to_string())
+        } else {
+            WordCursor::new(&self.text, offset)
+                .next_unmatched(c)
+                .map(|offset| offset - c.len_utf8())
+        }
+    }
+
+    /// The offset of the bracket matching the one at `offset`, or the one
+    /// right before `offset` when there is none at it. Nested pairs of the
+    /// same kind are skipped.
+    pub fn matching_bracket_offset(
+        &self,
+        offset: usize,
+        syntax: Option<&Syntax>,
+    ) -> Option<usize> {
+        let is_bracket =
+            |c: Option<char>| c.and_then(matching_pair_direction).is_some();
+        let offset = if is_bracket(self.char_at_offset(offset)) {
+            offset
+        } else {
+            let prev = self.prev_grapheme_offset(offset, 1, 0);
+            if prev < offset && is_bracket(self.char_at_offset(prev)) {
+                prev
+            } else {
+                return None;
+            }
+        };
+
+        if let Some(offset) = syntax.and_then(|s| s.find_matching_pair(offset)) {
+            return Some(offset);
+        }
+        WordCursor::new(&self.text, offset).match_pairs()
+    }
+
     pub fn slice_to_cow(&self, range: Range<usize>) -> Cow<str> {
         self.text
             .slice_to_cow(range.start.min(self.len())..range.end.min(self.len()))
@@ -963,6 +1244,106 @@ impl Buffer {
     pub fn move_n_words_backward(&self, offset: usize, count: usize) -> usize {
         self.find_nth_word(offset, count, |cursor| cursor.prev_boundary())
     }
+
+    /// Like [`Buffer::move_n_words_forward`], but by WORDs, which only end at
+    /// whitespace.
+    pub fn move_n_big_words_forward(&self, offset: usize, count: usize) -> usize {
+        self.find_nth_word(offset, count, |cursor| cursor.next_big_word_boundary())
+    }
+
+    pub fn move_n_big_wordends_forward(
+        &self,
+        offset: usize,
+        count: usize,
+        inserting: bool,
+    ) -> usize {
+        let mut new_offset = self
+            .find_nth_word(offset, count, |cursor| cursor.big_word_end_boundary());
+        if !inserting && new_offset != self.len() {
+            new_offset = self.prev_grapheme_offset(new_offset, 1, 0);
+        }
+        new_offset
+    }
+
+    pub fn move_n_big_words_backward(&self, offset: usize, count: usize) -> usize {
+        self.find_nth_word(offset, count, |cursor| cursor.prev_big_word_boundary())
+    }
+
+    /// The start of the `count`th blank line below the paragraph of `offset`,
+    /// or the end of the buffer. A run of blank lines counts as one.
+    pub fn move_n_paragraphs_forward(&self, offset: usize, count: usize) -> usize {
+        let is_blank = |line: usize| self.line_content(line).trim().is_empty();
+        let last_line = self.last_line();
+        let mut line = self.line_of_offset(offset);
+        for _ in 0..count {
+            while line < last_line && is_blank(line) {
+                line += 1;
+            }
+            while line < last_line && !is_blank(line) {
+                line += 1;
+            }
+            if !is_blank(line) {
+                return self.len();
+            }
+        }
+        self.offset_of_line(line)
+    }
+
+    /// The start of the `count`th blank line above the paragraph of
+    /// `offset`, or the start of the buffer.
+    pub fn move_n_paragraphs_backward(&self, offset: usize, count: usize) -> usize {
+        let is_blank = |line: usize| self.line_content(line).trim().is_empty();
+        let mut line = self.line_of_offset(offset);
+        for _ in 0..count {
+            while line > 0 && is_blank(line) {
+                line -= 1;
+            }
+            while line > 0 && !is_blank(line) {
+                line -= 1;
+            }
+        }
+        self.offset_of_line(line)
+    }
+}
+
+/// Splits `delta` into the replacements it makes, with their ranges in the
+/// coordinates of `buffer` before the delta got applied to it.
+pub fn delta_to_changes(delta: &RopeDelta, buffer: &Buffer) -> Vec<ContentChange> {
+    let mut changes = Vec::new();
+    let mut change = |start: usize, end: usize, new_text: String| {
+        changes.push(ContentChange {
+            range: lsp_types::Range {
+                start: buffer.offset_to_position(start),
+                end: buffer.offset_to_position(end),
+            },
+            new_text,
+        });
+    };
+
+    // The offset in the old text up to which everything has been handled,
+    // and the text inserted there so far.
+    let mut offset = 0;
+    let mut inserted: Option<String> = None;
+    for el in &delta.els {
+        match el {
+            DeltaElement::Copy(start, end) => {
+                if *start > offset || inserted.is_some() {
+                    change(offset, *start, inserted.take().unwrap_or_default());
+                }
+                offset = *end;
+            }
+            DeltaElement::Insert(rope) => {
+                inserted
+                    .get_or_insert_with(String::new)
+                    .push_str(&rope.slice_to_cow(0..rope.len()));
+            }
+        }
+    }
+    if delta.base_len > offset || inserted.is_some() {
+        change(offset, delta.base_len, inserted.unwrap_or_default());
+    }
+
+    changes
 }
 
 fn shuffle_tombstones(
@@ -1207,5 +1588,26 @@ pub fn rope_diff(
     Some(changes)
 }
 
+/// The number of columns a grapheme starting at column `col` takes up on
+/// screen.
+fn grapheme_width(grapheme: &str, col: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1);
+        return tab_width - col % tab_width;
+    }
+    // The width of a cluster is the one of its base character, unless an
+    // emoji presentation selector makes a narrow symbol wide.
+    let width = grapheme
+        .chars()
+        .next()
+        .map(|c| c.width().unwrap_or(1))
+        .unwrap_or(0);
+    if width == 1 && grapheme.contains('\u{FE0F}') {
+        2
+    } else {
+        width
+    }
+}
+
 #[cfg(test)]
 mod test;
@@ -15,11 +15,190 @@ mod editing {
         buffer.do_undo();
         assert!(buffer.is_pristine());
     }
+
+    #[test]
+    fn undo_group_breaks_after_pause() {
+        use std::time::{Duration, Instant};
+
+        let mut buffer = Buffer::new("");
+        buffer.set_undo_group_threshold(Duration::from_secs(1));
+        let start = Instant::now();
+        let type_at = |buffer: &mut Buffer, s: &str, millis: u64| {
+            let offset = buffer.len();
+            buffer.edit_at(
+                &[(Selection::caret(offset), s)],
+                EditType::InsertChars,
+                start + Duration::from_millis(millis),
+            );
+        };
+
+        type_at(&mut buffer, "a", 0);
+        type_at(&mut buffer, "b", 500);
+        type_at(&mut buffer, "c", 1000);
+        // A pause longer than the threshold
+        type_at(&mut buffer, "d", 3000);
+        type_at(&mut buffer, "e", 3100);
+        assert_eq!("abcde", buffer.slice_to_cow(0..buffer.len()));
+
+        buffer.do_undo();
+        assert_eq!("abc", buffer.slice_to_cow(0..buffer.len()));
+        buffer.do_undo();
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn undo_group_without_time() {
+        let mut buffer = Buffer::new("");
+        buffer.edit(&[(Selection::caret(0), "a")], EditType::InsertChars);
+        buffer.edit(&[(Selection::caret(1), "b")], EditType::InsertChars);
+        buffer.do_undo();
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
+    }
+}
+
+mod changes {
+    use super::*;
+    use crate::{
+        buffer::{delta_to_changes, ContentChange},
+        editor::EditType,
+        selection::{SelRegion, Selection},
+    };
+    use lsp_types::{Position, Range};
+
+    fn change(start: (u32, u32), end: (u32, u32), new_text: &str) -> ContentChange {
+        ContentChange {
+            range: Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn insertion() {
+        let mut buffer = Buffer::new("abc\ndef\n");
+        let before = buffer.clone();
+        let (delta, _) =
+            buffer.edit(&[(Selection::caret(5), "xy\nz")], EditType::InsertChars);
+        assert_eq!(
+            delta_to_changes(&delta, &before),
+            vec![change((1, 1), (1, 1), "xy\nz")]
+        );
+    }
+
+    #[test]
+    fn multiple_regions() {
+        let mut buffer = Buffer::new("abc\ndef\nghi\n");
+        let before = buffer.clone();
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 2, None));
+        selection.add_region(SelRegion::new(3, 5, None));
+        selection.add_region(SelRegion::new(9, 12, None));
+        let (delta, _) = buffer.edit(&[(&selection, "")], EditType::Delete);
+        assert_eq!("acef\ng", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(
+            delta_to_changes(&delta, &before),
+            vec![
+                change((0, 1), (0, 2), ""),
+                change((0, 3), (1, 1), ""),
+                change((2, 1), (3, 0), ""),
+            ]
+        );
+
+        // Replacing a selection in two places, one of them at the end
+        let mut buffer = Buffer::new("abc");
+        let before = buffer.clone();
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 1, None));
+        selection.add_region(SelRegion::caret(3));
+        let (delta, _) = buffer.edit(&[(&selection, "X")], EditType::Other);
+        assert_eq!("XbcX", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(
+            delta_to_changes(&delta, &before),
+            vec![change((0, 0), (0, 1), "X"), change((0, 3), (0, 3), "X")]
+        );
+    }
 }
 
 mod motion {
     use super::*;
 
+    #[test]
+    fn find_unmatched_pairs() {
+        let buffer = Buffer::new("(a [b] (c) d)");
+        //                      ->0123456789012<-
+        assert_eq!(buffer.previous_unmatched(None, '(', 5), Some(0));
+        assert_eq!(buffer.next_unmatched(None, ')', 5), Some(12));
+        assert_eq!(buffer.previous_unmatched(None, '[', 5), Some(3));
+        assert_eq!(buffer.next_unmatched(None, ']', 5), Some(5));
+        assert_eq!(buffer.next_unmatched(None, ']', 6), None);
+    }
+
+    #[test]
+    fn find_matching_brackets() {
+        let buffer = Buffer::new("f(a, [b, (c)], d) ]");
+        //                      ->0123456789012345678<-
+        assert_eq!(buffer.matching_bracket_offset(1, None), Some(16));
+        assert_eq!(buffer.matching_bracket_offset(16, None), Some(1));
+        assert_eq!(buffer.matching_bracket_offset(5, None), Some(12));
+        assert_eq!(buffer.matching_bracket_offset(9, None), Some(11));
+        // Right after a bracket
+        assert_eq!(buffer.matching_bracket_offset(17, None), Some(1));
+        assert_eq!(buffer.matching_bracket_offset(3, None), None);
+        // Unmatched
+        assert_eq!(buffer.matching_bracket_offset(18, None), None);
+        let buffer = Buffer::new("((a)");
+        assert_eq!(buffer.matching_bracket_offset(0, None), None);
+    }
+
+    #[cfg(feature = "lang-rust")]
+    #[test]
+    fn find_matching_brackets_with_syntax() {
+        use crate::{language::LapceLanguage, syntax::Syntax};
+
+        let buffer = Buffer::new("fn f() { let a = [(1), 2]; }");
+        //                      ->0123456789012345678901234567<-
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        assert_eq!(buffer.matching_bracket_offset(7, Some(&syntax)), Some(27));
+        assert_eq!(buffer.matching_bracket_offset(27, Some(&syntax)), Some(7));
+        assert_eq!(buffer.matching_bracket_offset(17, Some(&syntax)), Some(24));
+        assert_eq!(buffer.matching_bracket_offset(18, Some(&syntax)), Some(20));
+    }
+
+    #[test]
+    fn move_over_wide_characters() {
+        use crate::mode::Mode;
+
+        let buffer = Buffer::new("a😀b中c\nabcdefg\n");
+        // Each wide grapheme is a single step
+        assert_eq!(buffer.move_right(1, Mode::Normal, 1), 5);
+        assert_eq!(buffer.move_left(9, Mode::Normal, 1), 6);
+        assert_eq!(buffer.visual_col_of_offset(5, 4), 3);
+        assert_eq!(buffer.visual_col_of_offset(10, 4), 7);
+
+        // Down from after the emoji and back up lands at the same place
+        let down = buffer.offset_of_visual_col(1, 3, 4, false);
+        assert_eq!(down, 11 + 3);
+        assert_eq!(buffer.offset_of_visual_col(0, 3, 4, false), 5);
+
+        // A column in the middle of a wide character lands on it
+        assert_eq!(buffer.visual_col_of_offset(11 + 5, 4), 5);
+        assert_eq!(buffer.offset_of_visual_col(0, 5, 4, false), 6);
+
+        // Zero width graphemes take no column, tabs reach the next tab stop
+        let buffer = Buffer::new("a\u{200B}b\n\tx\nab\tx\n");
+        assert_eq!(buffer.visual_col_of_offset(4, 4), 1);
+        assert_eq!(buffer.visual_col_of_offset(7, 4), 4);
+        assert_eq!(buffer.visual_col_of_offset(12, 4), 4);
+        assert_eq!(buffer.offset_of_visual_col(1, 4, 4, false), 7);
+        assert_eq!(buffer.offset_of_visual_col(2, 4, 4, false), 12);
+    }
+
     #[test]
     fn cannot_move_in_empty_buffer() {
         let buffer = Buffer::new("");
@@ -78,6 +257,42 @@ mod motion {
         }
     }
 
+    #[test]
+    fn by_word_and_by_big_word() {
+        let buffer = Buffer::new("foo.bar-baz qux");
+        //                      ->012345678901234<-
+
+        assert_eq!(buffer.move_n_words_forward(0, 1), 3);
+        assert_eq!(buffer.move_n_big_words_forward(0, 1), 12);
+        assert_eq!(buffer.move_n_words_forward(0, 3), 7);
+
+        assert_eq!(buffer.move_n_wordends_forward(0, 1, false), 2);
+        assert_eq!(buffer.move_n_big_wordends_forward(0, 1, false), 10);
+        assert_eq!(buffer.move_n_big_wordends_forward(0, 2, false), 15);
+
+        assert_eq!(buffer.move_n_words_backward(12, 1), 8);
+        assert_eq!(buffer.move_n_big_words_backward(12, 1), 0);
+        assert_eq!(buffer.move_n_big_words_backward(14, 1), 12);
+    }
+
+    #[test]
+    fn by_paragraph() {
+        // Lines start at 0, 2, 4, 5, 6, 8, 9 and 11
+        let buffer = Buffer::new("a\nb\n\n\nc\n\nd\ne");
+
+        // Blank lines in a row are a single gap
+        assert_eq!(buffer.move_n_paragraphs_forward(0, 1), 4);
+        assert_eq!(buffer.move_n_paragraphs_forward(4, 1), 8);
+        assert_eq!(buffer.move_n_paragraphs_forward(0, 2), 8);
+        assert_eq!(buffer.move_n_paragraphs_forward(8, 1), buffer.len());
+        assert_eq!(buffer.move_n_paragraphs_forward(0, 5), buffer.len());
+
+        assert_eq!(buffer.move_n_paragraphs_backward(12, 1), 8);
+        assert_eq!(buffer.move_n_paragraphs_backward(8, 1), 5);
+        assert_eq!(buffer.move_n_paragraphs_backward(12, 2), 5);
+        assert_eq!(buffer.move_n_paragraphs_backward(2, 1), 0);
+    }
+
     mod on_word_end_forward {
         use super::*;
 
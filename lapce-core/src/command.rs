@@ -17,14 +17,59 @@ pub enum EditCommand {
     MoveLineUp,
     #[strum(serialize = "move_line_down")]
     MoveLineDown,
+    #[strum(message = "Duplicate Line Up")]
+    #[strum(serialize = "duplicate_line_up")]
+    DuplicateLineUp,
+    #[strum(message = "Duplicate Line Down")]
+    #[strum(serialize = "duplicate_line_down")]
+    DuplicateLineDown,
     #[strum(serialize = "insert_new_line")]
     InsertNewLine,
     #[strum(serialize = "insert_tab")]
     InsertTab,
+    #[strum(message = "Insert Back Tab")]
+    #[strum(serialize = "insert_back_tab")]
+    InsertBackTab,
     #[strum(serialize = "new_line_above")]
     NewLineAbove,
     #[strum(serialize = "new_line_below")]
     NewLineBelow,
+    #[strum(message = "Insert Line Above")]
+    #[strum(serialize = "insert_line_above")]
+    InsertLineAbove,
+    #[strum(message = "Insert Line Below")]
+    #[strum(serialize = "insert_line_below")]
+    InsertLineBelow,
+    #[strum(message = "Split Line")]
+    #[strum(serialize = "split_line")]
+    SplitLine,
+    #[strum(message = "Exchange Regions")]
+    #[strum(serialize = "exchange_regions")]
+    ExchangeRegions,
+    #[strum(message = "Rotate Selections Forward")]
+    #[strum(serialize = "rotate_selections_forward")]
+    RotateSelectionsForward,
+    #[strum(message = "Rotate Selections Backward")]
+    #[strum(serialize = "rotate_selections_backward")]
+    RotateSelectionsBackward,
+    #[strum(message = "Toggle List Layout")]
+    #[strum(serialize = "toggle_list_layout")]
+    ToggleListLayout,
+    #[strum(message = "Retab Selection To Spaces")]
+    #[strum(serialize = "retab_selection_to_spaces")]
+    RetabSelectionToSpaces,
+    #[strum(message = "Retab Selection To Tabs")]
+    #[strum(serialize = "retab_selection_to_tabs")]
+    RetabSelectionToTabs,
+    #[strum(message = "Trim Trailing Whitespace")]
+    #[strum(serialize = "trim_trailing_whitespace")]
+    TrimTrailingWhitespace,
+    #[strum(message = "Trim Final Newlines")]
+    #[strum(serialize = "trim_final_newlines")]
+    TrimFinalNewlines,
+    #[strum(message = "Toggle Overtype")]
+    #[strum(serialize = "toggle_overtype")]
+    ToggleOvertype,
     #[strum(serialize = "delete_backward")]
     DeleteBackward,
     #[strum(serialize = "delete_forward")]
@@ -37,9 +82,66 @@ pub enum EditCommand {
     DeleteWordBackward,
     #[strum(serialize = "delete_to_beginning_of_line")]
     DeleteToBeginningOfLine,
+    #[strum(message = "Delete to End of Line")]
+    #[strum(serialize = "delete_to_line_end")]
+    DeleteToLineEnd,
+    #[strum(message = "Delete to Start of Line")]
+    #[strum(serialize = "delete_to_line_start")]
+    DeleteToLineStart,
+    #[strum(message = "Delete Line")]
+    #[strum(serialize = "delete_line")]
+    DeleteLine,
+    #[strum(message = "Sort Lines")]
+    #[strum(serialize = "sort_lines")]
+    SortLines,
+    #[strum(message = "Sort Lines Descending")]
+    #[strum(serialize = "sort_lines_descending")]
+    SortLinesDescending,
+    #[strum(message = "Sort Lines Numerically")]
+    #[strum(serialize = "sort_lines_numeric")]
+    SortLinesNumeric,
+    #[strum(message = "Sort Lines Case Insensitive")]
+    #[strum(serialize = "sort_lines_case_insensitive")]
+    SortLinesCaseInsensitive,
+    #[strum(message = "Sort Lines Unique")]
+    #[strum(serialize = "sort_lines_unique")]
+    SortLinesUnique,
+    #[strum(message = "Sort Lines Unique Case Insensitive")]
+    #[strum(serialize = "sort_lines_unique_case_insensitive")]
+    SortLinesUniqueCaseInsensitive,
+    #[strum(message = "Increment Number")]
+    #[strum(serialize = "increment_number")]
+    IncrementNumber,
+    #[strum(message = "Decrement Number")]
+    #[strum(serialize = "decrement_number")]
+    DecrementNumber,
+    #[strum(message = "Uppercase Selection")]
+    #[strum(serialize = "uppercase_selection")]
+    UppercaseSelection,
+    #[strum(message = "Lowercase Selection")]
+    #[strum(serialize = "lowercase_selection")]
+    LowercaseSelection,
+    #[strum(message = "Toggle Case of Selection")]
+    #[strum(serialize = "toggle_case_selection")]
+    ToggleCaseSelection,
+    #[strum(message = "Title Case Selection")]
+    #[strum(serialize = "title_case_selection")]
+    TitleCaseSelection,
+    #[strum(message = "Sentence Case Selection")]
+    #[strum(serialize = "sentence_case_selection")]
+    SentenceCaseSelection,
+    #[strum(message = "Transpose Characters")]
+    #[strum(serialize = "transpose_characters")]
+    TransposeCharacters,
+    #[strum(message = "Reverse Regions")]
+    #[strum(serialize = "reverse_regions")]
+    ReverseRegions,
     #[strum(message = "Join Lines")]
     #[strum(serialize = "join_lines")]
     JoinLines,
+    #[strum(message = "Join Lines Without Separator")]
+    #[strum(serialize = "join_lines_raw")]
+    JoinLinesRaw,
     #[strum(message = "Indent Line")]
     #[strum(serialize = "indent_line")]
     IndentLine,
@@ -49,6 +151,21 @@ pub enum EditCommand {
     #[strum(message = "Toggle Line Comment")]
     #[strum(serialize = "toggle_line_comment")]
     ToggleLineComment,
+    #[strum(message = "Toggle Block Comment")]
+    #[strum(serialize = "toggle_block_comment")]
+    ToggleBlockComment,
+    #[strum(message = "Copy Line As Comment")]
+    #[strum(serialize = "copy_line_as_comment")]
+    CopyLineAsComment,
+    #[strum(message = "Comment And Open Below")]
+    #[strum(serialize = "comment_and_open_below")]
+    CommentAndOpenBelow,
+    #[strum(message = "Collapse To Primary Cursor")]
+    #[strum(serialize = "collapse_to_primary")]
+    CollapseToPrimary,
+    #[strum(message = "Toggle Quotes")]
+    #[strum(serialize = "toggle_quotes")]
+    ToggleQuotes,
     #[strum(serialize = "undo")]
     Undo,
     #[strum(serialize = "redo")]
@@ -110,6 +227,16 @@ pub enum MoveCommand {
     WordForward,
     #[strum(serialize = "word_end_forward")]
     WordEndForward,
+    #[strum(serialize = "big_word_backward")]
+    BigWordBackward,
+    #[strum(serialize = "big_word_forward")]
+    BigWordForward,
+    #[strum(serialize = "big_word_end_forward")]
+    BigWordEndForward,
+    #[strum(serialize = "paragraph_forward")]
+    ParagraphForward,
+    #[strum(serialize = "paragraph_backward")]
+    ParagraphBackward,
     #[strum(message = "Document Start")]
     #[strum(serialize = "document_start")]
     DocumentStart,
@@ -162,6 +289,11 @@ impl MoveCommand {
             WordBackward => Movement::WordBackward,
             WordForward => Movement::WordForward,
             WordEndForward => Movement::WordEndForward,
+            BigWordBackward => Movement::BigWordBackward,
+            BigWordForward => Movement::BigWordForward,
+            BigWordEndForward => Movement::BigWordEndForward,
+            ParagraphForward => Movement::ParagraphForward,
+            ParagraphBackward => Movement::ParagraphBackward,
             MatchPairs => Movement::MatchPairs,
             NextUnmatchedRightBracket => Movement::NextUnmatched(')'),
             PreviousUnmatchedLeftBracket => Movement::PreviousUnmatched('('),
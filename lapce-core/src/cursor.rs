@@ -2,7 +2,9 @@ use serde::{Deserialize, Serialize};
 use xi_rope::{RopeDelta, Transformer};
 
 use crate::buffer::Buffer;
+use crate::editor::RepeatableEdit;
 use crate::mode::{Mode, MotionMode, VisualMode};
+use crate::movement::CharSearch;
 use crate::register::RegisterData;
 use crate::selection::{InsertDrift, SelRegion, Selection};
 
@@ -20,6 +22,36 @@ pub struct Cursor {
     pub horiz: Option<ColPosition>,
     pub motion_mode: Option<MotionMode>,
     pub history_selections: Vec<Selection>,
+    /// The selections before and after each expand selection, so that
+    /// shrinking can step back through them.
+    pub expand_history: Vec<(Selection, Selection)>,
+    /// The last edit, so that it can be repeated at another position.
+    #[serde(skip)]
+    pub last_edit: Option<RepeatableEdit>,
+    /// Whether typed text still belongs to the insert in `last_edit`.
+    #[serde(skip)]
+    pub insert_session: bool,
+    /// The offsets of the closing characters the latest edit inserted
+    /// automatically, along with the revision of the buffer they are in.
+    #[serde(skip)]
+    pub pending_pairs: Option<(u64, Vec<usize>)>,
+    /// Whether text is being pasted through [`crate::editor::Editor::insert`],
+    /// so it should be inserted as it is.
+    #[serde(skip)]
+    pub paste_session: bool,
+    /// Groups of regions edited together, such as the occurrences of a
+    /// snippet placeholder. Typing in one region of a group types in all of
+    /// them.
+    #[serde(skip)]
+    pub linked_regions: Vec<Selection>,
+    /// Whether typed characters replace the ones after the carets instead
+    /// of being inserted before them.
+    #[serde(skip)]
+    pub overtype: bool,
+    /// The last search for a character on the line, which `;` and `,`
+    /// repeat.
+    #[serde(skip)]
+    pub last_char_search: Option<CharSearch>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -54,6 +86,14 @@ impl Cursor {
             horiz,
             motion_mode,
             history_selections: Vec::new(),
+            expand_history: Vec::new(),
+            last_edit: None,
+            insert_session: false,
+            pending_pairs: None,
+            paste_session: false,
+            linked_regions: Vec::new(),
+            overtype: false,
+            last_char_search: None,
         }
     }
 
@@ -61,6 +101,17 @@ impl Cursor {
         self.mode.offset()
     }
 
+    /// Whether the closing character at the offset was inserted
+    /// automatically by the latest edit of the buffer.
+    pub fn is_pending_pair(&self, buffer: &Buffer, offset: usize) -> bool {
+        match &self.pending_pairs {
+            Some((rev, offsets)) => {
+                *rev == buffer.rev() && offsets.contains(&offset)
+            }
+            None => false,
+        }
+    }
+
     pub fn is_normal(&self) -> bool {
         matches!(&self.mode, CursorMode::Normal(_))
     }
@@ -183,6 +234,23 @@ impl Cursor {
         self.horiz = None;
     }
 
+    /// Moves the linked regions along with an edit. Text inserted at either
+    /// end of a region becomes part of it.
+    pub fn apply_delta_to_linked(&mut self, delta: &RopeDelta) {
+        let mut transformer = Transformer::new(delta);
+        for group in self.linked_regions.iter_mut() {
+            let mut new_group = Selection::new();
+            for region in group.regions() {
+                new_group.add_region(SelRegion::new(
+                    transformer.transform(region.min(), false),
+                    transformer.transform(region.max(), true),
+                    None,
+                ));
+            }
+            *group = new_group;
+        }
+    }
+
     pub fn yank(&self, buffer: &Buffer) -> RegisterData {
         let (content, mode) = match &self.mode {
             CursorMode::Insert(selection) => {
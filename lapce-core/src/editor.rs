@@ -1,20 +1,30 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Write},
+    ops::Range,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
+use chrono::{DateTime, Local, TimeZone};
 use itertools::Itertools;
-use xi_rope::RopeDelta;
+use thiserror::Error;
+use xi_rope::{
+    diff::{Diff, LineHashDiff},
+    DeltaElement, RopeDelta, Transformer,
+};
 
 use crate::{
-    buffer::{Buffer, InvalLines},
+    buffer::{Buffer, InvalLines, LineEnding},
     command::EditCommand,
-    cursor::{get_first_selection_after, Cursor, CursorMode},
+    cursor::{get_first_selection_after, ColPosition, Cursor, CursorMode},
     mode::{Mode, MotionMode, VisualMode},
-    register::{Clipboard, Register, RegisterData, RegisterKind},
+    movement::{CharSearch, LinePosition, Movement},
+    register::{Clipboard, Register, RegisterData, RegisterKind, RegisterSelector},
     selection::{InsertDrift, SelRegion, Selection},
-    syntax::{
-        has_unmatched_pair, matching_char, matching_pair_direction,
-        str_is_pair_left, str_matching_pair, Syntax,
-    },
-    word::{get_word_property, WordProperty},
+    snippet::Snippet,
+    syntax::{has_unmatched_pair, matching_char, matching_pair_direction, Syntax},
+    word::{get_word_property, WordCursor, WordProperty},
 };
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -32,6 +42,216 @@ impl EditType {
     pub fn breaks_undo_group(self, previous: EditType) -> bool {
         self == EditType::Other || self != previous
     }
+
+    /// Like [`EditType::breaks_undo_group`], but also breaks when characters
+    /// get typed after a pause longer than `threshold`. Without the `elapsed`
+    /// time since the previous edit only the edit types are considered.
+    pub fn breaks_undo_group_after(
+        self,
+        previous: EditType,
+        elapsed: Option<Duration>,
+        threshold: Duration,
+    ) -> bool {
+        self.breaks_undo_group(previous)
+            || (self == EditType::InsertChars
+                && matches!(elapsed, Some(elapsed) if elapsed > threshold))
+    }
+}
+
+/// Controls which characters get closed automatically when typed.
+///
+/// The pairs in here are consulted before the built-in brackets.
+#[derive(Clone, Debug, Default)]
+pub struct AutoPairOptions {
+    /// Additional opening to closing character pairs.
+    pub pairs: HashMap<char, char>,
+    /// Opening characters that should never be paired.
+    pub disabled: HashSet<char>,
+    /// Closing sequences, like `*/`, that typing moves over instead of
+    /// inserting them again when they are right after the caret.
+    pub skip_closing: Vec<String>,
+    /// Opening and closing sequences, like `>` and `</` around the content
+    /// of an HTML tag, that pressing Enter between opens up like a pair.
+    pub newline_pairs: Vec<(String, String)>,
+    /// Opening characters of pairs that pressing Enter between leaves as a
+    /// plain line break.
+    pub no_newline_expansion: HashSet<char>,
+    /// Whether backspace inside of an empty pair only removes the closing
+    /// character when the previous edit inserted it automatically, rather
+    /// than for any empty pair.
+    pub delete_only_auto_inserted_pairs: bool,
+}
+
+impl AutoPairOptions {
+    /// The character closing `c`, if `c` opens a pair.
+    pub fn closing_char(&self, c: char) -> Option<char> {
+        if self.disabled.contains(&c) {
+            return None;
+        }
+        if let Some(close) = self.pairs.get(&c) {
+            return Some(*close);
+        }
+        if matching_pair_direction(c) == Some(true) {
+            return matching_char(c);
+        }
+        None
+    }
+
+    /// The character opening `c`, if `c` closes a pair.
+    pub fn opening_char(&self, c: char) -> Option<char> {
+        let open = self
+            .pairs
+            .iter()
+            .find(|(_, close)| **close == c)
+            .map(|(open, _)| *open)
+            .or_else(|| {
+                if matching_pair_direction(c) == Some(false) {
+                    matching_char(c)
+                } else {
+                    None
+                }
+            })?;
+        if self.disabled.contains(&open) {
+            return None;
+        }
+        Some(open)
+    }
+
+    /// Whether typing `c` between `before` and `after` continues one of the
+    /// closing sequences to skip. The part of the sequence before `c` has to
+    /// end `before`, and the rest has to start `after`.
+    fn skips_closing(&self, before: &str, after: &str, c: char) -> bool {
+        self.skip_closing.iter().any(|seq| {
+            seq.char_indices().any(|(i, ch)| {
+                ch == c
+                    && before.ends_with(&seq[..i])
+                    && after.starts_with(&seq[i..])
+            })
+        })
+    }
+
+    /// Whether a line break between `before` and `after` sits between the
+    /// two halves of a pair, so that the closing half should get a line of
+    /// its own.
+    fn expands_newline(&self, before: &str, after: &str) -> bool {
+        let before = before.trim_end();
+        let after = after.trim_start();
+        let close = match before.chars().last() {
+            Some(c) if !self.no_newline_expansion.contains(&c) => {
+                self.pairs.get(&c).copied().or_else(|| {
+                    if matching_pair_direction(c) == Some(true) {
+                        matching_char(c)
+                    } else {
+                        None
+                    }
+                })
+            }
+            _ => None,
+        };
+        let closes_pair = match close {
+            Some(close) => after.starts_with(close),
+            None => false,
+        };
+        closes_pair
+            || self.newline_pairs.iter().any(|(open, close)| {
+                before.ends_with(open.as_str()) && after.starts_with(close.as_str())
+            })
+    }
+
+    /// The character closing `c` when wrapping a selection with it.
+    fn surround_char(&self, c: char) -> Option<char> {
+        match c {
+            '"' | '\'' | '`' if !self.disabled.contains(&c) => {
+                self.pairs.get(&c).copied().or(Some(c))
+            }
+            _ => self.closing_char(c),
+        }
+    }
+}
+
+/// Settings of the editor that edit commands depend on.
+#[derive(Clone, Debug)]
+pub struct EditOptions {
+    pub auto_pairs: AutoPairOptions,
+    /// How many columns a tab takes up.
+    pub tab_width: usize,
+}
+
+impl Default for EditOptions {
+    fn default() -> Self {
+        Self {
+            auto_pairs: AutoPairOptions::default(),
+            tab_width: 4,
+        }
+    }
+}
+
+/// How [`Editor::sort_lines`] compares lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+    /// Ascending by the number at the start of each line. Lines without a
+    /// number come first.
+    Numeric,
+}
+
+/// How [`Editor::transform_selection`] transforms the selected text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransformKind {
+    Base64Encode,
+    Base64Decode,
+    /// Percent-encodes everything but the unreserved characters of RFC 3986.
+    UrlEncode,
+    UrlDecode,
+    /// Escapes `&`, `<`, `>`, `"` and `'`.
+    HtmlEncode,
+    /// Unescapes the named entities of [`TransformKind::HtmlEncode`] as well
+    /// as numeric character references.
+    HtmlDecode,
+}
+
+/// Represents the reason why a selection couldn't be transformed.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TransformError {
+    #[error("Invalid base64")]
+    InvalidBase64,
+    #[error("Invalid percent encoding")]
+    InvalidPercentEncoding,
+    #[error("Invalid HTML entity")]
+    InvalidHtmlEntity,
+    #[error("Decoded text is not valid UTF-8")]
+    InvalidUtf8,
+}
+
+impl TransformKind {
+    fn apply(self, s: &str) -> Result<String, TransformError> {
+        match self {
+            TransformKind::Base64Encode => Ok(base64::encode(s)),
+            TransformKind::Base64Decode => {
+                let bytes = base64::decode(s.trim())
+                    .map_err(|_| TransformError::InvalidBase64)?;
+                String::from_utf8(bytes).map_err(|_| TransformError::InvalidUtf8)
+            }
+            TransformKind::UrlEncode => Ok(url_encode(s)),
+            TransformKind::UrlDecode => url_decode(s),
+            TransformKind::HtmlEncode => Ok(html_encode(s)),
+            TransformKind::HtmlDecode => html_decode(s),
+        }
+    }
+}
+
+/// An edit recorded by the editor, which can be made again at another
+/// cursor position.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RepeatableEdit {
+    Command(EditCommand),
+    /// The text typed during an insert session.
+    Insert(String),
+    Paste {
+        data: RegisterData,
+        reindent: bool,
+    },
 }
 
 pub struct Editor {}
@@ -43,9 +263,230 @@ impl Editor {
         s: &str,
         syntax: Option<&Syntax>,
     ) -> Vec<(RopeDelta, InvalLines)> {
+        Self::insert_with_options(
+            cursor,
+            buffer,
+            s,
+            syntax,
+            &AutoPairOptions::default(),
+        )
+    }
+
+    /// Makes an edit with every region mirrored into the regions linked to
+    /// the one it is in. The linkage ends once a region is outside of all the
+    /// linked regions, or the edit would change text outside of them.
+    fn edit_linked<F>(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        mut edit: F,
+    ) -> Vec<(RopeDelta, InvalLines)>
+    where
+        F: FnMut(&mut Cursor, &mut Buffer) -> Vec<(RopeDelta, InvalLines)>,
+    {
+        let mut groups = std::mem::take(&mut cursor.linked_regions);
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.clone(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                return edit(cursor, buffer);
+            }
+        };
+
+        // The group and the linked region each region is in
+        let mut hosts = Vec::new();
+        for region in selection.regions() {
+            let host = groups.iter().enumerate().find_map(|(group, regions)| {
+                regions
+                    .regions()
+                    .iter()
+                    .position(|linked| {
+                        linked.min() <= region.min() && region.max() <= linked.max()
+                    })
+                    .map(|linked| (group, linked))
+            });
+            match host {
+                Some(host) => hosts.push(host),
+                None => return edit(cursor, buffer),
+            }
+        }
+
+        // Deleting backward at the start of a region, for one, would delete
+        // the text before the others too
+        let mut preview_cursor = cursor.clone();
+        let mut preview = buffer.detached();
+        let mut host_regions = hosts
+            .iter()
+            .map(|(group, linked)| groups[*group].regions()[*linked])
+            .collect::<Vec<_>>();
+        for (delta, _) in edit(&mut preview_cursor, &mut preview) {
+            if !Self::delta_within(&delta, &host_regions) {
+                return edit(cursor, buffer);
+            }
+            let mut transformer = Transformer::new(&delta);
+            for region in host_regions.iter_mut() {
+                *region = SelRegion::new(
+                    transformer.transform(region.min(), false),
+                    transformer.transform(region.max(), true),
+                    None,
+                );
+            }
+        }
+
+        let mut mirrored = Selection::new();
+        for (region, (group, linked)) in selection.regions().iter().zip(&hosts) {
+            let host_start = groups[*group].regions()[*linked].min();
+            for other in groups[*group].regions() {
+                mirrored.add_region(SelRegion::new(
+                    other.min() + region.start - host_start,
+                    other.min() + region.end - host_start,
+                    None,
+                ));
+            }
+        }
+        cursor.mode = CursorMode::Insert(mirrored);
+        let deltas = edit(cursor, buffer);
+
+        cursor.linked_regions = std::mem::take(&mut groups);
+        for (delta, _) in &deltas {
+            cursor.apply_delta_to_linked(delta);
+        }
+        // Only the regions that were typed in keep their carets
+        if let CursorMode::Insert(selection) = &cursor.mode {
+            let mut new_selection = Selection::new();
+            for region in selection.regions() {
+                let typed_in = hosts.iter().any(|(group, linked)| {
+                    let host = cursor.linked_regions[*group].regions()[*linked];
+                    host.min() <= region.min() && region.max() <= host.max()
+                });
+                if typed_in {
+                    new_selection.add_region(*region);
+                }
+            }
+            cursor.mode = CursorMode::Insert(new_selection);
+        }
+        deltas
+    }
+
+    /// Whether every change the delta makes is within one of the regions.
+    fn delta_within(delta: &RopeDelta, regions: &[SelRegion]) -> bool {
+        let within = |start: usize, end: usize| {
+            regions
+                .iter()
+                .any(|region| region.min() <= start && end <= region.max())
+        };
+        let mut last_end = 0;
+        for el in &delta.els {
+            match el {
+                DeltaElement::Copy(start, end) => {
+                    if *start > last_end && !within(last_end, *start) {
+                        return false;
+                    }
+                    last_end = *end;
+                }
+                DeltaElement::Insert(_) => {
+                    if !within(last_end, last_end) {
+                        return false;
+                    }
+                }
+            }
+        }
+        last_end == delta.base_len || within(last_end, delta.base_len)
+    }
+
+    /// The delta that [`Editor::insert`] would make, leaving the buffer and
+    /// the cursor as they are. When the insert takes several edits, the
+    /// delta covers all of them.
+    pub fn preview_insert(
+        cursor: &Cursor,
+        buffer: &Buffer,
+        s: &str,
+        syntax: Option<&Syntax>,
+    ) -> RopeDelta {
+        let mut cursor = cursor.clone();
+        let mut preview = buffer.detached();
+        let mut deltas = Self::insert(&mut cursor, &mut preview, s, syntax);
+        if deltas.len() == 1 {
+            deltas.pop().unwrap().0
+        } else {
+            LineHashDiff::compute_delta(buffer.text(), preview.text())
+        }
+    }
+
+    /// Inserts text typed at `time`, so that typing after a pause longer than
+    /// the undo group threshold of the buffer starts a new undo group.
+    pub fn insert_at(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        s: &str,
+        syntax: Option<&Syntax>,
+        options: &AutoPairOptions,
+        time: Instant,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        buffer.set_edit_time(Some(time));
+        let deltas = Self::insert_with_options(cursor, buffer, s, syntax, options);
+        buffer.set_edit_time(None);
+        deltas
+    }
+
+    pub fn insert_with_options(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        s: &str,
+        syntax: Option<&Syntax>,
+        options: &AutoPairOptions,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        if !cursor.linked_regions.is_empty() {
+            return Self::edit_linked(cursor, buffer, |cursor, buffer| {
+                Self::insert_with_options(cursor, buffer, s, syntax, options)
+            });
+        }
+        cursor.pending_pairs = None;
+        if cursor.is_insert() {
+            match &mut cursor.last_edit {
+                Some(RepeatableEdit::Insert(text)) if cursor.insert_session => {
+                    text.push_str(s);
+                }
+                _ => cursor.last_edit = Some(RepeatableEdit::Insert(s.to_string())),
+            }
+            cursor.insert_session = true;
+        }
+        if cursor.paste_session {
+            return Self::insert_raw(cursor, buffer, s);
+        }
+        if cursor.overtype && s != "\n" && s.chars().count() == 1 {
+            if let CursorMode::Insert(selection) = &cursor.mode {
+                return Self::overtype(buffer, cursor, selection.clone(), s);
+            }
+        }
+
         let mut deltas = Vec::new();
         if let CursorMode::Insert(selection) = &cursor.mode {
-            if s.chars().count() != 1 {
+            if s == "\n" || s == "\r\n" {
+                return Self::insert_new_line(
+                    buffer,
+                    cursor,
+                    selection.clone(),
+                    options,
+                );
+            } else if s.chars().count() != 1 {
+                if options.skip_closing.iter().any(|seq| seq == s)
+                    && selection.regions().iter().all(|region| {
+                        region.is_caret()
+                            && buffer
+                                .text()
+                                .iter_chunks(region.end..)
+                                .flat_map(str::chars)
+                                .take(s.chars().count())
+                                .eq(s.chars())
+                    })
+                {
+                    // The whole closing sequence is already there
+                    let mut selection = selection.clone();
+                    for region in selection.regions_mut() {
+                        *region = SelRegion::caret(region.end + s.len());
+                    }
+                    cursor.mode = CursorMode::Insert(selection);
+                    return deltas;
+                }
                 let (delta, inval_lines) =
                     buffer.edit(&[(selection, s)], EditType::InsertChars);
                 let selection =
@@ -54,7 +495,8 @@ impl Editor {
                 cursor.mode = CursorMode::Insert(selection);
             } else {
                 let c = s.chars().next().unwrap();
-                let matching_pair_type = matching_pair_direction(c);
+                let closing_char = options.closing_char(c);
+                let opening_char = options.opening_char(c);
 
                 // The main edit operations
                 let mut edits = vec![];
@@ -62,12 +504,65 @@ impl Editor {
                 // "Late edits" - characters to be inserted after particular regions
                 let mut edits_after = vec![];
 
+                // Typing an opening character with a selection wraps it instead
+                let surround_char = options.surround_char(c);
+                let mut wrapped = vec![];
+
                 let mut selection = selection.clone();
                 for (idx, region) in selection.regions_mut().iter_mut().enumerate() {
+                    if !region.is_caret() {
+                        let (min, max) = (region.min(), region.max());
+                        let wrapped_in = |open: char, close: char| {
+                            let before = buffer.prev_grapheme_offset(min, 1, 0);
+                            before < min
+                                && buffer.char_at_offset(before) == Some(open)
+                                && buffer.char_at_offset(max) == Some(close)
+                        };
+                        // A selection that is already wrapped doesn't get a
+                        // second layer, and its closing character gets skipped
+                        if matches!(surround_char, Some(close) if wrapped_in(c, close))
+                        {
+                            continue;
+                        }
+                        if matches!(opening_char, Some(open) if wrapped_in(open, c))
+                        {
+                            *region = SelRegion::caret(max + c.len_utf8());
+                            continue;
+                        }
+                    }
+
+                    if let Some(close) = surround_char {
+                        if !region.is_caret() {
+                            edits.push((
+                                Selection::caret(region.min()),
+                                c.to_string(),
+                            ));
+                            edits.push((
+                                Selection::caret(region.max()),
+                                close.to_string(),
+                            ));
+                            wrapped.push((idx, close.len_utf8()));
+                            continue;
+                        }
+                    }
+
                     let offset = region.end;
                     let cursor_char = buffer.char_at_offset(offset);
 
-                    if matching_pair_type == Some(false) {
+                    if region.is_caret() && !options.skip_closing.is_empty() {
+                        let line = buffer.line_of_offset(offset);
+                        let before =
+                            buffer.slice_to_cow(buffer.offset_of_line(line)..offset);
+                        let after = buffer.slice_to_cow(
+                            offset..buffer.line_end_offset(line, true),
+                        );
+                        if options.skips_closing(&before, &after, c) {
+                            *region = SelRegion::caret(offset + c.len_utf8());
+                            continue;
+                        }
+                    }
+
+                    if let Some(opening_char) = opening_char {
                         if cursor_char == Some(c) {
                             // Skip the closing character
                             let new_offset =
@@ -79,11 +574,12 @@ impl Editor {
 
                         let line = buffer.line_of_offset(offset);
                         let line_start = buffer.offset_of_line(line);
-                        if buffer.slice_to_cow(line_start..offset).trim() == "" {
-                            let opening_character = matching_char(c).unwrap();
+                        if opening_char != c
+                            && buffer.slice_to_cow(line_start..offset).trim() == ""
+                        {
                             if let Some(previous_offset) = buffer.previous_unmatched(
                                 syntax,
-                                opening_character,
+                                opening_char,
                                 offset,
                             ) {
                                 // Auto-indent closing character to the same level as the opening.
@@ -104,7 +600,7 @@ impl Editor {
                         }
                     }
 
-                    if matching_pair_type == Some(true) {
+                    if let Some(closing_char) = closing_char {
                         // Create a late edit to insert the closing pair, if allowed.
                         let is_whitespace_or_punct = cursor_char
                             .map(|c| {
@@ -114,10 +610,12 @@ impl Editor {
                                     || prop == WordProperty::Punctuation
                             })
                             .unwrap_or(true);
+                        let is_string_or_comment = syntax
+                            .map(|syntax| syntax.is_inside_string_or_comment(offset))
+                            .unwrap_or(false);
 
-                        if is_whitespace_or_punct {
-                            let insert_after = matching_char(c).unwrap();
-                            edits_after.push((idx, insert_after));
+                        if is_whitespace_or_punct && !is_string_or_comment {
+                            edits_after.push((idx, closing_char));
                         }
                     };
 
@@ -142,9 +640,21 @@ impl Editor {
                 let mut selection =
                     selection.apply_delta(&delta, true, InsertDrift::Default);
 
+                // Keep wrapped regions inside of the inserted pair
+                for (idx, close_len) in wrapped {
+                    let region = &mut selection.regions_mut()[idx];
+                    if region.start > region.end {
+                        region.start -= close_len;
+                    } else {
+                        region.end -= close_len;
+                    }
+                }
+
                 buffer.set_cursor_after(CursorMode::Insert(selection.clone()));
 
                 deltas.push((delta, inval_lines));
+                let paired =
+                    edits_after.iter().map(|(idx, _)| *idx).collect::<Vec<_>>();
                 // Apply late edits
                 let edits_after = edits_after
                     .iter()
@@ -194,6 +704,12 @@ impl Editor {
                     }
                 }
 
+                let pending_pairs = paired
+                    .iter()
+                    .filter_map(|idx| selection.regions().get(*idx))
+                    .map(|region| region.end)
+                    .collect();
+                cursor.pending_pairs = Some((buffer.rev(), pending_pairs));
                 cursor.mode = CursorMode::Insert(selection);
             }
         }
@@ -228,140 +744,487 @@ impl Editor {
         }
     }
 
-    fn insert_new_line(
-        buffer: &mut Buffer,
+    /// Opens a line below or above the line of every caret, indented like
+    /// that line, and puts the cursor in insert mode at the new lines.
+    fn insert_line(
         cursor: &mut Cursor,
-        selection: Selection,
+        buffer: &mut Buffer,
+        below: bool,
     ) -> Vec<(RopeDelta, InvalLines)> {
-        let mut deltas = Vec::new();
-        let mut edits = Vec::new();
-        let mut extra_edits = Vec::new();
-        let mut shift = 0i32;
-        for region in selection.regions() {
-            let offset = region.max();
-            let line = buffer.line_of_offset(offset);
-            let line_start = buffer.offset_of_line(line);
-            let line_end = buffer.line_end_offset(line, true);
-            let line_indent = buffer.indent_on_line(line);
-            let first_half = buffer.slice_to_cow(line_start..offset).to_string();
-            let second_half = buffer.slice_to_cow(offset..line_end).to_string();
+        let mut lines = match &cursor.mode {
+            CursorMode::Normal(offset) => vec![buffer.line_of_offset(*offset)],
+            CursorMode::Visual { end, .. } => vec![buffer.line_of_offset(*end)],
+            CursorMode::Insert(selection) => selection
+                .regions()
+                .iter()
+                .map(|region| buffer.line_of_offset(region.end))
+                .collect(),
+        };
+        lines.dedup();
 
-            let indent = if has_unmatched_pair(&first_half) {
-                format!("{}{}", line_indent, buffer.indent_unit())
-            } else if second_half.trim().is_empty() {
-                let next_line_indent = buffer.indent_on_line(line + 1);
-                if next_line_indent.len() > line_indent.len() {
-                    next_line_indent
-                } else {
-                    line_indent.clone()
-                }
+        let mut edits = Vec::new();
+        for line in lines {
+            let indent = buffer.indent_on_line(line);
+            if below {
+                let offset = buffer.line_end_offset(line, true);
+                let content = format!("\n{}", indent);
+                edits.push((Selection::caret(offset), content, indent.len() + 1));
             } else {
-                line_indent.clone()
-            };
+                let offset = buffer.offset_of_line(line);
+                let content = format!("{}\n", indent);
+                edits.push((Selection::caret(offset), content, indent.len()));
+            }
+        }
 
-            let selection = Selection::region(region.min(), region.max());
-            let content = format!("{}{}", "\n", indent);
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content, _)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::InsertNewline,
+        );
 
-            shift -= (region.max() - region.min()) as i32;
-            shift += content.len() as i32;
+        let mut transformer = Transformer::new(&delta);
+        let mut selection = Selection::new();
+        for (edit, _, caret) in &edits {
+            let offset = transformer.transform(edit.min_offset(), false) + caret;
+            selection.add_region(SelRegion::caret(offset));
+        }
+        cursor.mode = CursorMode::Insert(selection);
 
-            edits.push((selection, content));
+        vec![(delta, inval_lines)]
+    }
 
-            for c in first_half.chars().rev() {
-                if c != ' ' {
-                    if let Some(pair_start) = matching_pair_direction(c) {
-                        if pair_start {
-                            if let Some(c) = matching_char(c) {
-                                if second_half.trim().starts_with(&c.to_string()) {
-                                    let selection = Selection::caret(
-                                        (region.max() as i32 + shift) as usize,
-                                    );
-                                    let content = format!("{}{}", "\n", line_indent);
-                                    extra_edits.push((selection.clone(), content));
-                                }
-                            }
-                        }
-                    }
-                    break;
+    /// Inserts a snippet at every caret, replacing the selections. Tab stops
+    /// are written `$1` or `${1}`, and placeholders with default text
+    /// `${1:text}`, while `\\$` is a literal dollar sign. Returns the
+    /// selections of the tab stops in order, `$0` coming last, each one
+    /// covering every occurrence of its stop. The cursor is put on the
+    /// first of them.
+    pub fn insert_snippet(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        snippet: &str,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Vec<Selection>) {
+        let selection = match cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(offset),
+            CursorMode::Visual { .. } | CursorMode::Insert(_) => {
+                cursor.edit_selection(buffer)
+            }
+        };
+        let snippet = match Snippet::from_str(snippet) {
+            Ok(snippet) => snippet.fill_repeated_tabstops(),
+            Err(_) => return (vec![], vec![]),
+        };
+        let text = snippet.text();
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, text.as_str())], EditType::InsertChars);
+
+        let mut transformer = Transformer::new(&delta);
+        let starts = selection
+            .regions()
+            .iter()
+            .map(|region| transformer.transform(region.min(), false))
+            .collect::<Vec<_>>();
+        let mut tabs: Vec<(usize, Selection)> = Vec::new();
+        for (tab, (start, end)) in snippet.tabs(0) {
+            let index = match tabs.iter().position(|(t, _)| *t == tab) {
+                Some(index) => index,
+                None => {
+                    tabs.push((tab, Selection::new()));
+                    tabs.len() - 1
                 }
+            };
+            for offset in &starts {
+                tabs[index].1.add_region(SelRegion::new(
+                    offset + start,
+                    offset + end,
+                    None,
+                ));
             }
         }
+        // The final position is the last stop
+        tabs.sort_by_key(|(tab, _)| if *tab == 0 { usize::MAX } else { *tab });
+        let tabs = tabs
+            .into_iter()
+            .map(|(_, selection)| selection)
+            .collect::<Vec<_>>();
 
-        let edits = edits
+        let selection = match tabs.first() {
+            Some(selection) => selection.clone(),
+            None => {
+                let mut selection = Selection::new();
+                for offset in &starts {
+                    selection.add_region(SelRegion::caret(offset + text.len()));
+                }
+                selection
+            }
+        };
+        cursor.mode = CursorMode::Insert(selection);
+        cursor.linked_regions = tabs
             .iter()
-            .map(|(selection, s)| (selection, s.as_str()))
-            .collect::<Vec<(&Selection, &str)>>();
-        let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertNewline);
-        let mut selection =
-            selection.apply_delta(&delta, true, InsertDrift::Default);
-        deltas.push((delta, inval_lines));
+            .filter(|selection| selection.len() > 1)
+            .cloned()
+            .collect();
 
-        if !extra_edits.is_empty() {
-            let edits = extra_edits
-                .iter()
-                .map(|(selection, s)| (selection, s.as_str()))
-                .collect::<Vec<(&Selection, &str)>>();
-            let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertNewline);
-            selection = selection.apply_delta(&delta, false, InsertDrift::Default);
-            deltas.push((delta, inval_lines));
+        (vec![(delta, inval_lines)], tabs)
+    }
+
+    /// Breaks the line at every caret, the rest of the line moving to a new
+    /// line indented like the original one, while the carets stay where they
+    /// were. A selection is replaced by the line break.
+    fn split_line(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let regions = match &cursor.mode {
+            CursorMode::Normal(offset) => vec![SelRegion::caret(*offset)],
+            CursorMode::Visual { .. } => return vec![],
+            CursorMode::Insert(selection) => selection.regions().to_vec(),
+        };
+
+        let mut edits = Vec::new();
+        for (i, region) in regions.iter().enumerate() {
+            let line = buffer.line_of_offset(region.min());
+            let indent = buffer.indent_on_line(line);
+            // Whitespace after the break would end up before the indentation,
+            // up to where the next region begins
+            let line_end = buffer.offset_line_end(region.max(), true);
+            let rest = buffer.slice_to_cow(region.max()..line_end);
+            let end = region.max() + (rest.len() - rest.trim_start().len());
+            let end = match regions.get(i + 1) {
+                Some(next) => end.min(next.min()),
+                None => end,
+            };
+            let content = format!("\n{}", indent);
+            edits.push((Selection::region(region.min(), end), content));
         }
 
-        cursor.mode = CursorMode::Insert(selection);
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::InsertNewline,
+        );
 
-        deltas
+        let mut transformer = Transformer::new(&delta);
+        cursor.mode = match &cursor.mode {
+            CursorMode::Normal(offset) => {
+                CursorMode::Normal(transformer.transform(*offset, false))
+            }
+            _ => {
+                // Every caret is shifted by the edits before its own one, which
+                // can end right where it starts.
+                let mut selection = Selection::new();
+                let mut shift = 0isize;
+                for (edit, content) in &edits {
+                    let start = edit.min_offset();
+                    selection.add_region(SelRegion::caret(
+                        (start as isize + shift) as usize,
+                    ));
+                    shift += content.len() as isize
+                        - (edit.max_offset() - start) as isize;
+                }
+                CursorMode::Insert(selection)
+            }
+        };
+
+        vec![(delta, inval_lines)]
     }
 
-    pub fn execute_motion_mode(
+    /// Puts a caret right of the block on every line of a blockwise visual
+    /// selection and enters insert mode, so that typing appends to all of
+    /// them. Lines too short to reach the block are padded with spaces.
+    fn append_block(
         cursor: &mut Cursor,
         buffer: &mut Buffer,
-        motion_mode: MotionMode,
-        start: usize,
-        end: usize,
-        is_vertical: bool,
-        register: &mut Register,
     ) -> Vec<(RopeDelta, InvalLines)> {
-        fn format_start_end(
-            buffer: &Buffer,
-            start: usize,
-            end: usize,
-            is_vertical: bool,
-        ) -> (usize, usize) {
-            if is_vertical {
-                let start_line = buffer.line_of_offset(start.min(end));
-                let end_line = buffer.line_of_offset(end.max(start));
-                let start = buffer.offset_of_line(start_line);
-                let end = buffer.offset_of_line(end_line + 1);
-                (start, end)
+        let (start, end) = match &cursor.mode {
+            CursorMode::Visual { start, end, .. } => {
+                (*start.min(end), *start.max(end))
+            }
+            CursorMode::Normal(_) | CursorMode::Insert(_) => return vec![],
+        };
+        let (start_line, start_col) = buffer.offset_to_line_col(start);
+        let (end_line, end_col) = buffer.offset_to_line_col(end);
+        let right = start_col.max(end_col) + 1;
+        let to_line_end = cursor.horiz == Some(ColPosition::End);
+
+        let mut edits = Vec::new();
+        let mut offsets = Vec::new();
+        for line in start_line..end_line + 1 {
+            let max_col = buffer.line_end_col(line, true);
+            if to_line_end || right <= max_col {
+                let col = if to_line_end { max_col } else { right };
+                offsets.push(buffer.offset_of_line_col(line, col));
             } else {
-                let s = start.min(end);
-                let e = start.max(end);
-                (s, e)
+                let offset = buffer.line_end_offset(line, true);
+                edits.push((Selection::caret(offset), " ".repeat(right - max_col)));
+                offsets.push(offset);
             }
         }
 
         let mut deltas = Vec::new();
-        match motion_mode {
-            MotionMode::Delete => {
-                let (start, end) = format_start_end(buffer, start, end, is_vertical);
-                register.add(
-                    RegisterKind::Delete,
-                    RegisterData {
-                        content: buffer.slice_to_cow(start..end).to_string(),
-                        mode: if is_vertical {
-                            VisualMode::Linewise
-                        } else {
-                            VisualMode::Normal
-                        },
-                    },
-                );
-                let selection = Selection::region(start, end);
-                let (delta, inval_lines) =
+        let mut selection = Selection::new();
+        if edits.is_empty() {
+            for offset in offsets {
+                selection.add_region(SelRegion::caret(offset));
+            }
+        } else {
+            let (delta, inval_lines) = buffer.edit(
+                &edits
+                    .iter()
+                    .map(|(selection, content)| (selection, content.as_str()))
+                    .collect::<Vec<_>>(),
+                EditType::InsertChars,
+            );
+            let mut transformer = Transformer::new(&delta);
+            for offset in offsets {
+                let offset = transformer.transform(offset, true);
+                selection.add_region(SelRegion::caret(offset));
+            }
+            deltas.push((delta, inval_lines));
+        }
+        cursor.mode = CursorMode::Insert(selection);
+        cursor.horiz = None;
+
+        deltas
+    }
+
+    /// Starts a paste session, during which [`Editor::insert`] inserts text
+    /// as it is and everything inserted becomes one undo group.
+    pub fn begin_paste(cursor: &mut Cursor, buffer: &mut Buffer) {
+        cursor.paste_session = true;
+        buffer.reset_edit_type();
+    }
+
+    /// Ends the paste session started by [`Editor::begin_paste`].
+    pub fn end_paste(cursor: &mut Cursor, buffer: &mut Buffer) {
+        cursor.paste_session = false;
+        buffer.reset_edit_type();
+    }
+
+    /// Inserts `s` at every region of an insert mode selection, without any
+    /// auto pairing or indentation.
+    pub fn insert_raw(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        s: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.clone(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return vec![],
+        };
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, s)], EditType::InsertChars);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        cursor.mode = CursorMode::Insert(selection);
+        vec![(delta, inval_lines)]
+    }
+
+    /// Inserts `s` repeated `count` times at every region as a single edit
+    /// of its own undo group, without any auto pairing. Carets end up after
+    /// the inserted text, and a normal mode cursor on its last character.
+    pub fn insert_repeated(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        s: &str,
+        count: usize,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let content = s.repeat(count);
+        if content.is_empty() {
+            return vec![];
+        }
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => return vec![],
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, content.as_str())], EditType::Other);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        if cursor.is_normal() {
+            let offset = buffer.prev_grapheme_offset(selection.max_offset(), 1, 0);
+            cursor.mode = CursorMode::Normal(offset);
+        } else {
+            cursor.mode = CursorMode::Insert(selection);
+        }
+        vec![(delta, inval_lines)]
+    }
+
+    /// Inserts the current local date and time, formatted with the
+    /// strftime-like `format`, at every region as a single undo group.
+    pub fn insert_datetime(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        format: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        Self::insert_datetime_at(cursor, buffer, format, &Local::now())
+    }
+
+    /// Like [`Editor::insert_datetime`], but with the time to insert given
+    /// by the caller. Nothing is inserted if `format` is invalid.
+    pub fn insert_datetime_at<Tz: TimeZone>(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        format: &str,
+        now: &DateTime<Tz>,
+    ) -> Vec<(RopeDelta, InvalLines)>
+    where
+        Tz::Offset: Display,
+    {
+        let mut content = String::new();
+        if write!(content, "{}", now.format(format)).is_err() {
+            return vec![];
+        }
+        Self::insert_repeated(cursor, buffer, &content, 1)
+    }
+
+    /// Types `s` over the grapheme after every caret, or inserts it at the
+    /// end of a line. Selections are replaced as usual.
+    fn overtype(
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        selection: Selection,
+        s: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let mut replaced = Selection::new();
+        for region in selection.regions() {
+            if region.is_caret() {
+                let line_end = buffer.offset_line_end(region.start, true);
+                let end = buffer.next_grapheme_offset(region.start, 1, line_end);
+                replaced.add_region(SelRegion::new(region.start, end, None));
+            } else {
+                replaced.add_region(SelRegion::new(
+                    region.min(),
+                    region.max(),
+                    None,
+                ));
+            }
+        }
+
+        let (delta, inval_lines) =
+            buffer.edit(&[(&replaced, s)], EditType::InsertChars);
+        let selection = replaced.apply_delta(&delta, true, InsertDrift::Default);
+        cursor.mode = CursorMode::Insert(selection);
+
+        vec![(delta, inval_lines)]
+    }
+
+    fn insert_new_line(
+        buffer: &mut Buffer,
+        cursor: &mut Cursor,
+        selection: Selection,
+        options: &AutoPairOptions,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let mut deltas = Vec::new();
+        let mut edits = Vec::new();
+        let mut extra_edits = Vec::new();
+        let mut shift = 0i32;
+        for region in selection.regions() {
+            let offset = region.max();
+            let line = buffer.line_of_offset(offset);
+            let line_start = buffer.offset_of_line(line);
+            let line_end = buffer.line_end_offset(line, true);
+            let line_indent = buffer.indent_on_line(line);
+            let first_half = buffer.slice_to_cow(line_start..offset).to_string();
+            let second_half = buffer.slice_to_cow(offset..line_end).to_string();
+
+            // Follow the line when it is indented with tabs
+            let indent_unit = if line_indent.contains('\t') {
+                "\t"
+            } else {
+                buffer.indent_unit()
+            };
+            let expands = options.expands_newline(&first_half, &second_half);
+            let indent = if expands || has_unmatched_pair(&first_half) {
+                format!("{}{}", line_indent, indent_unit)
+            } else if second_half.trim().is_empty() {
+                let next_line_indent = buffer.indent_on_line(line + 1);
+                if next_line_indent.len() > line_indent.len() {
+                    next_line_indent
+                } else {
+                    line_indent.clone()
+                }
+            } else {
+                line_indent.clone()
+            };
+
+            let selection = Selection::region(region.min(), region.max());
+            let content = format!("{}{}", "\n", indent);
+
+            shift -= (region.max() - region.min()) as i32;
+            shift += content.len() as i32;
+
+            edits.push((selection, content));
+
+            if expands {
+                let selection =
+                    Selection::caret((region.max() as i32 + shift) as usize);
+                let content = format!("{}{}", "\n", line_indent);
+                extra_edits.push((selection, content));
+            }
+        }
+
+        let edits = edits
+            .iter()
+            .map(|(selection, s)| (selection, s.as_str()))
+            .collect::<Vec<(&Selection, &str)>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertNewline);
+        let mut selection =
+            selection.apply_delta(&delta, true, InsertDrift::Default);
+        deltas.push((delta, inval_lines));
+
+        if !extra_edits.is_empty() {
+            let edits = extra_edits
+                .iter()
+                .map(|(selection, s)| (selection, s.as_str()))
+                .collect::<Vec<(&Selection, &str)>>();
+            let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertNewline);
+            selection = selection.apply_delta(&delta, false, InsertDrift::Default);
+            deltas.push((delta, inval_lines));
+        }
+
+        cursor.mode = CursorMode::Insert(selection);
+
+        deltas
+    }
+
+    pub fn execute_motion_mode(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        motion_mode: MotionMode,
+        start: usize,
+        end: usize,
+        is_vertical: bool,
+        register: &mut Register,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let mut deltas = Vec::new();
+        match motion_mode {
+            MotionMode::Delete => {
+                let (start, end) =
+                    Self::motion_range(buffer, start, end, is_vertical);
+                register.add(
+                    RegisterKind::Delete,
+                    RegisterData {
+                        content: buffer.slice_to_cow(start..end).to_string(),
+                        mode: if is_vertical {
+                            VisualMode::Linewise
+                        } else {
+                            VisualMode::Normal
+                        },
+                    },
+                );
+                let selection = Selection::region(start, end);
+                let (delta, inval_lines) =
                     buffer.edit(&[(&selection, "")], EditType::Delete);
                 cursor.apply_delta(&delta);
                 deltas.push((delta, inval_lines));
             }
             MotionMode::Yank => {
-                let (start, end) = format_start_end(buffer, start, end, is_vertical);
+                let (start, end) =
+                    Self::motion_range(buffer, start, end, is_vertical);
                 register.add(
                     RegisterKind::Yank,
                     RegisterData {
@@ -388,79 +1251,554 @@ impl Editor {
         deltas
     }
 
-    pub fn do_paste(
+    /// The range operated on by a motion from `start` to `end`, which covers
+    /// whole lines for vertical motions.
+    fn motion_range(
+        buffer: &Buffer,
+        start: usize,
+        end: usize,
+        is_vertical: bool,
+    ) -> (usize, usize) {
+        if is_vertical {
+            let start_line = buffer.line_of_offset(start.min(end));
+            let end_line = buffer.line_of_offset(end.max(start));
+            let start = buffer.offset_of_line(start_line);
+            let end = buffer.offset_of_line(end_line + 1);
+            (start, end)
+        } else {
+            let s = start.min(end);
+            let e = start.max(end);
+            (s, e)
+        }
+    }
+
+    /// Deletes the text between the cursor and where `movement` repeated
+    /// `count` times takes it, in either direction. Vertical motions delete
+    /// whole lines. Returns the deleted text for the register.
+    pub fn delete_motion(
         cursor: &mut Cursor,
         buffer: &mut Buffer,
-        data: &RegisterData,
-    ) -> Vec<(RopeDelta, InvalLines)> {
-        let mut deltas = Vec::new();
-        match data.mode {
-            VisualMode::Normal => {
-                let selection = match cursor.mode {
-                    CursorMode::Normal(offset) => {
-                        let line_end = buffer.offset_line_end(offset, true);
-                        let offset = (offset + 1).min(line_end);
-                        Selection::caret(offset)
-                    }
-                    CursorMode::Insert { .. } | CursorMode::Visual { .. } => {
-                        cursor.edit_selection(buffer)
+        syntax: Option<&Syntax>,
+        movement: &Movement,
+        count: usize,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Option<RegisterData>) {
+        let (start, end) =
+            Self::motion_span(buffer, syntax, cursor.offset(), movement, count);
+        if start == end {
+            return (vec![], None);
+        }
+
+        let data = Self::motion_register_data(buffer, start, end, movement);
+        let selection = Selection::region(start, end);
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, "")], EditType::Delete);
+        cursor.apply_delta(&delta);
+        if let CursorMode::Normal(offset) = cursor.mode {
+            let offset = buffer.offset_line_end(offset, false).min(offset);
+            cursor.mode = CursorMode::Normal(offset);
+        }
+
+        (vec![(delta, inval_lines)], Some(data))
+    }
+
+    /// Deletes over a motion like [`Editor::delete_motion`] and switches to
+    /// insert mode where the text was. Vertical motions, like `Movement::Down`
+    /// with a count of 0 for a single line, keep the first line and its
+    /// indentation. Returns the deleted text for the register.
+    pub fn change_motion(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        syntax: Option<&Syntax>,
+        movement: &Movement,
+        count: usize,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Option<RegisterData>) {
+        let offset = cursor.offset();
+        if !movement.is_vertical() {
+            // Like in vim, changing words leaves the whitespace after them
+            let on_word = matches!(
+                buffer.char_at_offset(offset),
+                Some(c) if !c.is_whitespace()
+            );
+            let movement = match movement {
+                Movement::WordForward if on_word => &Movement::WordEndForward,
+                Movement::BigWordForward if on_word => &Movement::BigWordEndForward,
+                _ => movement,
+            };
+            let (start, end) =
+                Self::motion_span(buffer, syntax, offset, movement, count);
+            let data = Self::motion_register_data(buffer, start, end, movement);
+            let (delta, inval_lines) = buffer
+                .edit(&[(&Selection::region(start, end), "")], EditType::Delete);
+            cursor.mode = CursorMode::Insert(Selection::caret(start));
+            return (vec![(delta, inval_lines)], Some(data));
+        }
+
+        let (start, end) =
+            Self::motion_span(buffer, syntax, offset, movement, count);
+        let data = Self::motion_register_data(buffer, start, end, movement);
+        let start_line = buffer.line_of_offset(start);
+        let end_line = buffer.line_of_offset(end.saturating_sub(1).max(start));
+        let indent = buffer.indent_on_line(start_line);
+        let end = buffer.line_end_offset(end_line, true);
+        let (delta, inval_lines) = buffer.edit(
+            &[(&Selection::region(start, end), &indent)],
+            EditType::Other,
+        );
+        cursor.mode = CursorMode::Insert(Selection::caret(start + indent.len()));
+
+        (vec![(delta, inval_lines)], Some(data))
+    }
+
+    /// Copies the text between the cursor and where `movement` repeated
+    /// `count` times takes it, without moving the cursor. Vertical motions
+    /// copy whole lines.
+    pub fn yank_motion(
+        cursor: &Cursor,
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+        movement: &Movement,
+        count: usize,
+    ) -> RegisterData {
+        let (start, end) =
+            Self::motion_span(buffer, syntax, cursor.offset(), movement, count);
+        Self::motion_register_data(buffer, start, end, movement)
+    }
+
+    fn motion_register_data(
+        buffer: &Buffer,
+        start: usize,
+        end: usize,
+        movement: &Movement,
+    ) -> RegisterData {
+        let mut content = buffer.slice_to_cow(start..end).to_string();
+        if !movement.is_vertical() {
+            return RegisterData {
+                content,
+                mode: VisualMode::Normal,
+            };
+        }
+        // Linewise content always ends with a newline, even when taken from
+        // the last line, so that it pastes as whole lines
+        if !content.ends_with('\n') {
+            content.push('\n');
+        }
+        RegisterData {
+            content,
+            mode: VisualMode::Linewise,
+        }
+    }
+
+    /// The range a motion from `offset` operates on, with inclusive motions
+    /// covering the character they land on.
+    fn motion_span(
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+        offset: usize,
+        movement: &Movement,
+        count: usize,
+    ) -> (usize, usize) {
+        let new_offset =
+            Self::motion_offset(buffer, syntax, offset, movement, count);
+        // A motion landing on a line ending, like `$` on an empty line, has
+        // no character to cover.
+        let after = match buffer.char_at_offset(new_offset) {
+            Some('\n' | '\r') => new_offset,
+            _ => buffer.next_grapheme_offset(new_offset, 1, buffer.len()),
+        };
+        let (start, end) = match movement {
+            Movement::EndOfLine
+            | Movement::WordEndForward
+            | Movement::BigWordEndForward => (offset, after),
+            // Without a match there is nothing to operate on
+            Movement::MatchPairs if new_offset == offset => (offset, offset),
+            Movement::MatchPairs if new_offset > offset => (offset, after),
+            Movement::MatchPairs => (
+                new_offset,
+                buffer.next_grapheme_offset(offset, 1, buffer.len()),
+            ),
+            Movement::FindChar(search) if search.forward && new_offset > offset => {
+                (offset, after)
+            }
+            _ => (offset, new_offset),
+        };
+        Self::motion_range(buffer, start, end, movement.is_vertical())
+    }
+
+    /// Where `movement` repeated `count` times takes a normal mode cursor at
+    /// `offset`. Vertical motions only determine the line, and bracket motions
+    /// use `syntax` when there is one, like the cursor movements do.
+    fn motion_offset(
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+        offset: usize,
+        movement: &Movement,
+        count: usize,
+    ) -> usize {
+        match movement {
+            Movement::Left => buffer.move_left(offset, Mode::Normal, count),
+            Movement::Right => buffer.next_grapheme_offset(
+                offset,
+                count,
+                buffer.offset_line_end(offset, true),
+            ),
+            Movement::Up => {
+                let line = buffer.line_of_offset(offset).saturating_sub(count);
+                buffer.offset_of_line(line)
+            }
+            Movement::Down => {
+                let line =
+                    (buffer.line_of_offset(offset) + count).min(buffer.last_line());
+                buffer.offset_of_line(line)
+            }
+            Movement::DocumentStart => 0,
+            Movement::DocumentEnd => buffer.len(),
+            Movement::FirstNonBlank => buffer
+                .first_non_blank_character_on_line(buffer.line_of_offset(offset)),
+            Movement::StartOfLine => {
+                buffer.offset_of_line(buffer.line_of_offset(offset))
+            }
+            Movement::EndOfLine => buffer.offset_line_end(offset, false),
+            Movement::Line(position) => {
+                let line = match position {
+                    LinePosition::Line(line) => {
+                        line.saturating_sub(1).min(buffer.last_line())
                     }
+                    LinePosition::First => 0,
+                    LinePosition::Last => buffer.last_line(),
                 };
-                let after = cursor.is_insert() || !data.content.contains('\n');
-                let (delta, inval_lines) = buffer
-                    .edit(&[(&selection, &data.content)], EditType::InsertChars);
-                let selection =
-                    selection.apply_delta(&delta, after, InsertDrift::Default);
-                deltas.push((delta, inval_lines));
-                if !after {
-                    cursor.update_selection(buffer, selection);
+                buffer.offset_of_line(line)
+            }
+            Movement::Offset(offset) => (*offset).min(buffer.len()),
+            Movement::WordEndForward => {
+                buffer.move_n_wordends_forward(offset, count, false)
+            }
+            Movement::WordForward | Movement::BigWordForward => {
+                let new_offset = if *movement == Movement::WordForward {
+                    buffer.move_n_words_forward(offset, count)
                 } else {
-                    match cursor.mode {
-                        CursorMode::Normal(_) | CursorMode::Visual { .. } => {
-                            let offset = buffer.prev_grapheme_offset(
-                                selection.min_offset(),
-                                1,
-                                0,
-                            );
-                            cursor.mode = CursorMode::Normal(offset);
-                        }
-                        CursorMode::Insert { .. } => {
-                            cursor.mode = CursorMode::Insert(selection);
-                        }
-                    }
+                    buffer.move_n_big_words_forward(offset, count)
+                };
+                // Like in vim, a word motion stops at the end of the line
+                let line_end = buffer.offset_line_end(offset, true);
+                if new_offset > line_end && line_end > offset {
+                    line_end
+                } else {
+                    new_offset
                 }
             }
-            VisualMode::Linewise | VisualMode::Blockwise => {
-                let (selection, content) = match &cursor.mode {
-                    CursorMode::Normal(offset) => {
-                        let line = buffer.line_of_offset(*offset);
-                        let offset = buffer.offset_of_line(line + 1);
-                        (Selection::caret(offset), data.content.clone())
-                    }
-                    CursorMode::Insert(selection) => {
-                        let mut selection = selection.clone();
-                        for region in selection.regions_mut() {
-                            if region.is_caret() {
-                                let line = buffer.line_of_offset(region.start);
-                                let start = buffer.offset_of_line(line);
-                                region.start = start;
-                                region.end = start;
-                            }
-                        }
-                        (selection, data.content.clone())
-                    }
-                    CursorMode::Visual { mode, .. } => {
-                        let selection = cursor.edit_selection(buffer);
-                        let data = match mode {
-                            VisualMode::Linewise => data.content.clone(),
-                            _ => "\n".to_string() + &data.content,
-                        };
-                        (selection, data)
-                    }
-                };
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, &content)], EditType::InsertChars);
-                let selection = selection.apply_delta(
+            Movement::WordBackward => buffer.move_n_words_backward(offset, count),
+            Movement::BigWordEndForward => {
+                buffer.move_n_big_wordends_forward(offset, count, false)
+            }
+            Movement::BigWordBackward => {
+                buffer.move_n_big_words_backward(offset, count)
+            }
+            Movement::ParagraphForward => {
+                buffer.move_n_paragraphs_forward(offset, count)
+            }
+            Movement::ParagraphBackward => {
+                buffer.move_n_paragraphs_backward(offset, count)
+            }
+            Movement::NextUnmatched(c) => {
+                buffer.next_unmatched(syntax, *c, offset).unwrap_or(offset)
+            }
+            Movement::PreviousUnmatched(c) => buffer
+                .previous_unmatched(syntax, *c, offset)
+                .unwrap_or(offset),
+            Movement::MatchPairs => buffer
+                .matching_bracket_offset(offset, syntax)
+                .unwrap_or(offset),
+            Movement::FindChar(search) => buffer
+                .find_char_on_line(offset, search, count)
+                .unwrap_or(offset),
+        }
+    }
+
+    /// Sets the mark `name` at `offset`. The mark moves along with the text
+    /// around it as the buffer gets edited.
+    pub fn set_mark(buffer: &mut Buffer, name: char, offset: usize) {
+        buffer.set_mark(name, offset);
+    }
+
+    /// Moves the cursor to the mark `name`, or to the first non blank
+    /// character of its line when `linewise`, like `` ` `` and `'` in vim.
+    /// Both `` ` `` and `'` name the position before the latest jump, which
+    /// this updates. Returns whether the mark was set.
+    pub fn jump_to_mark(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        name: char,
+        linewise: bool,
+    ) -> bool {
+        let name = if name == '\'' {
+            Buffer::JUMP_MARK
+        } else {
+            name
+        };
+        let offset = match buffer.mark(name) {
+            Some(offset) => offset,
+            None => return false,
+        };
+        let offset = if linewise {
+            buffer.first_non_blank_character_on_line(buffer.line_of_offset(offset))
+        } else {
+            offset
+        };
+        buffer.set_mark(Buffer::JUMP_MARK, cursor.offset());
+
+        match &mut cursor.mode {
+            CursorMode::Normal(_) => {
+                let offset = buffer.offset_line_end(offset, false).min(offset);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Visual { end, .. } => *end = offset,
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(Selection::caret(offset))
+            }
+        }
+        cursor.horiz = None;
+        true
+    }
+
+    /// Moves the cursor to the `count`th occurrence of a character on the
+    /// line, extending a visual selection, and remembers the search for
+    /// [`Editor::repeat_char_search`]. Carets without such an occurrence stay
+    /// where they are.
+    pub fn find_char(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        search: CharSearch,
+        count: usize,
+    ) {
+        let find = |offset: usize| {
+            buffer
+                .find_char_on_line(offset, &search, count)
+                .unwrap_or(offset)
+        };
+        match &mut cursor.mode {
+            CursorMode::Normal(offset) => *offset = find(*offset),
+            CursorMode::Visual { end, .. } => *end = find(*end),
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    new_selection.add_region(SelRegion::caret(find(region.end)));
+                }
+                *selection = new_selection;
+            }
+        }
+        cursor.horiz = None;
+        cursor.last_char_search = Some(search);
+    }
+
+    /// Repeats the last search for a character on the line, in the other
+    /// direction when `reverse` is set, like `;` and `,` in vim.
+    pub fn repeat_char_search(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        reverse: bool,
+        count: usize,
+    ) {
+        if let Some(search) = cursor.last_char_search {
+            let repeated = if reverse { search.reversed() } else { search };
+            Self::find_char(cursor, buffer, repeated, count);
+            // The direction of the original search is kept for next time
+            cursor.last_char_search = Some(search);
+        }
+    }
+
+    /// Copies the selection, with the register mode matching the visual
+    /// mode. Linewise content always ends with a newline. Like in vim, a
+    /// visual selection collapses to its start afterwards.
+    pub fn yank(cursor: &mut Cursor, buffer: &Buffer) -> RegisterData {
+        let mut data = cursor.yank(buffer);
+        if data.mode == VisualMode::Linewise && !data.content.ends_with('\n') {
+            data.content.push('\n');
+        }
+        if let CursorMode::Visual { start, end, .. } = &cursor.mode {
+            let offset = *start.min(end);
+            let offset = buffer.offset_line_end(offset, false).min(offset);
+            cursor.mode = CursorMode::Normal(offset);
+        }
+        data
+    }
+
+    /// Yanks the visual selection into the selected register. An uppercase
+    /// named register gets the text appended to what it already holds.
+    pub fn yank_to_register(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        register: &mut Register,
+        selector: RegisterSelector,
+    ) {
+        if cursor.is_visual() {
+            let data = Self::yank(cursor, buffer);
+            register.add_to(selector, RegisterKind::Yank, data);
+        }
+    }
+
+    /// Deletes over a motion like [`Editor::delete_motion`], storing the
+    /// deleted text in the selected register. Deleting to
+    /// [`RegisterSelector::BlackHole`] keeps every register as it was.
+    pub fn delete_motion_to_register(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        syntax: Option<&Syntax>,
+        movement: &Movement,
+        count: usize,
+        register: &mut Register,
+        selector: RegisterSelector,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (deltas, data) =
+            Self::delete_motion(cursor, buffer, syntax, movement, count);
+        if let Some(data) = data {
+            register.add_to(selector, RegisterKind::Delete, data);
+        }
+        deltas
+    }
+
+    /// Deletes lines like [`Editor::delete_line`], storing them in the
+    /// selected register.
+    pub fn delete_line_to_register(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        register: &mut Register,
+        selector: RegisterSelector,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (deltas, data) = Self::delete_line(cursor, buffer);
+        if let Some(data) = data {
+            register.add_to(selector, RegisterKind::Delete, data);
+        }
+        deltas
+    }
+
+    /// Pastes the content of the selected register, if there is one.
+    pub fn paste_from_register(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        register: &Register,
+        selector: RegisterSelector,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        match register.get(selector) {
+            Some(data) => Self::do_paste(cursor, buffer, data, false),
+            None => vec![],
+        }
+    }
+
+    /// Pastes the register content at the cursor. With `reindent`, linewise
+    /// content is shifted so that its first line gets the indentation of the
+    /// line the cursor is on, keeping the relative indentation of the rest,
+    /// and characterwise content pasted on a blank line gets the indentation
+    /// of the nearest non blank line above.
+    pub fn do_paste(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        data: &RegisterData,
+        reindent: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        // Pasting nothing shouldn't leave an empty edit to undo
+        if data.content.is_empty() {
+            return vec![];
+        }
+        cursor.last_edit = Some(RepeatableEdit::Paste {
+            data: data.clone(),
+            reindent,
+        });
+        cursor.insert_session = false;
+
+        let mut deltas = Vec::new();
+        if Self::paste_lines_per_region(cursor, data) {
+            deltas.push(Self::do_paste_distributed(cursor, buffer, data));
+            return deltas;
+        }
+        match data.mode {
+            VisualMode::Normal => {
+                let content = match Self::blank_line_indent(cursor, buffer) {
+                    Some(indent) if reindent => indent + &data.content,
+                    _ => data.content.clone(),
+                };
+                let selection = match cursor.mode {
+                    CursorMode::Normal(offset) => {
+                        let line_end = buffer.offset_line_end(offset, true);
+                        let offset = (offset + 1).min(line_end);
+                        Selection::caret(offset)
+                    }
+                    CursorMode::Insert { .. } | CursorMode::Visual { .. } => {
+                        cursor.edit_selection(buffer)
+                    }
+                };
+                let after = cursor.is_insert() || !data.content.contains('\n');
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, &content)], EditType::InsertChars);
+                let selection =
+                    selection.apply_delta(&delta, after, InsertDrift::Default);
+                deltas.push((delta, inval_lines));
+                if !after {
+                    cursor.update_selection(buffer, selection);
+                } else {
+                    match cursor.mode {
+                        CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                            let offset = buffer.prev_grapheme_offset(
+                                selection.min_offset(),
+                                1,
+                                0,
+                            );
+                            cursor.mode = CursorMode::Normal(offset);
+                        }
+                        CursorMode::Insert { .. } => {
+                            cursor.mode = CursorMode::Insert(selection);
+                        }
+                    }
+                }
+            }
+            VisualMode::Blockwise if !cursor.is_visual() => {
+                deltas.push(Self::do_paste_block(cursor, buffer, &data.content));
+            }
+            VisualMode::Linewise | VisualMode::Blockwise => {
+                let data = if reindent && data.mode == VisualMode::Linewise {
+                    let offset = match &cursor.mode {
+                        CursorMode::Normal(offset) => *offset,
+                        CursorMode::Insert(selection) => selection.min_offset(),
+                        CursorMode::Visual { start, end, .. } => *start.min(end),
+                    };
+                    let indent =
+                        buffer.indent_on_line(buffer.line_of_offset(offset));
+                    RegisterData {
+                        content: Self::reindent_lines(&data.content, &indent),
+                        mode: data.mode,
+                    }
+                } else {
+                    data.clone()
+                };
+                let (selection, content) = match &cursor.mode {
+                    CursorMode::Normal(offset) => {
+                        let line = buffer.line_of_offset(*offset);
+                        let offset = buffer.offset_of_line(line + 1);
+                        (Selection::caret(offset), data.content.clone())
+                    }
+                    CursorMode::Insert(selection) => {
+                        let mut selection = selection.clone();
+                        for region in selection.regions_mut() {
+                            if region.is_caret() {
+                                let line = buffer.line_of_offset(region.start);
+                                let start = buffer.offset_of_line(line);
+                                region.start = start;
+                                region.end = start;
+                            }
+                        }
+                        (selection, data.content.clone())
+                    }
+                    CursorMode::Visual { mode, .. } => {
+                        let selection = cursor.edit_selection(buffer);
+                        let data = match mode {
+                            VisualMode::Linewise => data.content.clone(),
+                            _ => "\n".to_string() + &data.content,
+                        };
+                        (selection, data)
+                    }
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, &content)], EditType::InsertChars);
+                let selection = selection.apply_delta(
                     &delta,
                     cursor.is_insert(),
                     InsertDrift::Default,
@@ -487,802 +1825,8774 @@ impl Editor {
         deltas
     }
 
-    fn do_indent(
+    /// Swaps the quotes of the string the cursor is in between `'` and `"`.
+    /// Quotes inside of the string that no longer need escaping lose their
+    /// backslash, and the ones that now need it get one.
+    pub fn toggle_quotes(
+        cursor: &mut Cursor,
         buffer: &mut Buffer,
-        selection: Selection,
-    ) -> (RopeDelta, InvalLines) {
-        let indent = buffer.indent_unit();
-        let mut edits = Vec::new();
+        syntax: Option<&Syntax>,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let offset = cursor.offset();
+        let (start, end) = match syntax
+            .and_then(|syntax| syntax.find_string(offset))
+            .or_else(|| Self::find_quoted(buffer, offset))
+        {
+            Some(range) => range,
+            None => return vec![],
+        };
+        let text = buffer.slice_to_cow(start..end);
+        let (quote, other) = match text.chars().next() {
+            Some('"') => ('"', '\''),
+            Some('\'') => ('\'', '"'),
+            _ => return vec![],
+        };
+        if end - start < 2 || !text.ends_with(quote) {
+            return vec![];
+        }
 
-        let mut lines = HashSet::new();
-        for region in selection.regions() {
-            let start_line = buffer.line_of_offset(region.min());
-            let mut end_line = buffer.line_of_offset(region.max());
-            if end_line > start_line {
-                let end_line_start = buffer.offset_of_line(end_line);
-                if end_line_start == region.max() {
-                    end_line -= 1;
+        let mut edits = vec![
+            (Selection::region(start, start + 1), other.to_string()),
+            (Selection::region(end - 1, end), other.to_string()),
+        ];
+        let mut chars = text[1..text.len() - 1].char_indices();
+        while let Some((i, c)) = chars.next() {
+            let i = start + 1 + i;
+            if c == '\\' {
+                if let Some((_, next)) = chars.next() {
+                    if next == quote {
+                        edits.push((Selection::region(i, i + 1), String::new()));
+                    }
                 }
+            } else if c == other {
+                edits.push((Selection::caret(i), "\\".to_string()));
             }
-            for line in start_line..=end_line {
-                if lines.contains(&line) {
-                    continue;
+        }
+        edits.sort_by_key(|(selection, _)| selection.min_offset());
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+        cursor.apply_delta(&delta);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// The range of the quoted string around `offset` on its line, found by
+    /// scanning the line for quotes and skipping escaped ones.
+    fn find_quoted(buffer: &Buffer, offset: usize) -> Option<(usize, usize)> {
+        let line = buffer.line_of_offset(offset);
+        let line_start = buffer.offset_of_line(line);
+        let content = buffer.line_content(line);
+        let mut open = None;
+        let mut chars = content.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match open {
+                None if c == '"' || c == '\'' => open = Some((i, c)),
+                Some(_) if c == '\\' => {
+                    chars.next();
                 }
-                lines.insert(line);
-                let line_content = buffer.line_content(line);
-                if line_content == "\n" || line_content == "\r\n" {
-                    continue;
+                Some((start, quote)) if c == quote => {
+                    let (start, end) = (line_start + start, line_start + i + 1);
+                    if start <= offset && offset < end {
+                        return Some((start, end));
+                    }
+                    open = None;
                 }
-                let nonblank = buffer.first_non_blank_character_on_line(line);
-                let edit = crate::indent::create_edit(buffer, nonblank, indent);
-                edits.push(edit);
+                _ => {}
             }
         }
+        None
+    }
 
-        buffer.edit(&edits, EditType::InsertChars)
+    /// The indentation missing before a single caret on a blank line to line
+    /// it up with the nearest non blank line above.
+    fn blank_line_indent(cursor: &Cursor, buffer: &Buffer) -> Option<String> {
+        let offset = match &cursor.mode {
+            CursorMode::Normal(offset) => *offset,
+            CursorMode::Insert(selection)
+                if selection.len() == 1 && selection.regions()[0].is_caret() =>
+            {
+                selection.regions()[0].end
+            }
+            _ => return None,
+        };
+        let line = buffer.line_of_offset(offset);
+        if !buffer.line_content(line).trim().is_empty() {
+            return None;
+        }
+        let above = (0..line)
+            .rev()
+            .find(|l| !buffer.line_content(*l).trim().is_empty())?;
+        let indent = buffer.indent_on_line(above);
+        let existing = buffer.slice_to_cow(buffer.offset_of_line(line)..offset);
+        let missing = indent.strip_prefix(existing.as_ref())?;
+        if missing.is_empty() {
+            None
+        } else {
+            Some(missing.to_string())
+        }
     }
 
-    fn do_outdent(
+    /// Pastes linewise content indented to fit where it lands. With a syntax,
+    /// every line is indented by the brackets opened before it, starting
+    /// from the indentation the line above the paste calls for, like typed
+    /// code would be. Brackets on comment lines don't count. Without a
+    /// syntax, or for other content, this is [`Editor::do_paste`] with
+    /// `reindent`.
+    pub fn paste_and_reindent(
+        cursor: &mut Cursor,
         buffer: &mut Buffer,
-        selection: Selection,
-    ) -> (RopeDelta, InvalLines) {
-        let indent = buffer.indent_unit();
-        let mut edits = Vec::new();
+        data: &RegisterData,
+        syntax: Option<&Syntax>,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let syntax = match syntax {
+            Some(syntax) if data.mode == VisualMode::Linewise => syntax,
+            _ => return Self::do_paste(cursor, buffer, data, true),
+        };
 
-        let mut lines = HashSet::new();
-        for region in selection.regions() {
-            let start_line = buffer.line_of_offset(region.min());
-            let mut end_line = buffer.line_of_offset(region.max());
-            if end_line > start_line {
-                let end_line_start = buffer.offset_of_line(end_line);
-                if end_line_start == region.max() {
-                    end_line -= 1;
-                }
+        // Normal mode pastes below the cursor line, the others replace or
+        // push down the first line of the selection
+        let above = match &cursor.mode {
+            CursorMode::Normal(offset) => Some(buffer.line_of_offset(*offset)),
+            CursorMode::Insert(selection) => {
+                buffer.line_of_offset(selection.min_offset()).checked_sub(1)
             }
-            for line in start_line..=end_line {
-                if lines.contains(&line) {
-                    continue;
+            CursorMode::Visual { start, end, .. } => {
+                buffer.line_of_offset(*start.min(end)).checked_sub(1)
+            }
+        };
+        let indent_unit = buffer.indent_unit();
+        let mut base = String::new();
+        if let Some(above) = above {
+            if let Some(line) = (0..above + 1)
+                .rev()
+                .find(|l| !buffer.line_content(*l).trim().is_empty())
+            {
+                base = buffer.indent_on_line(line);
+                if has_unmatched_pair(&buffer.line_content(line)) {
+                    base.push_str(indent_unit);
                 }
-                lines.insert(line);
-                let line_content = buffer.line_content(line);
-                if line_content == "\n" || line_content == "\r\n" {
-                    continue;
+            }
+        }
+
+        let comment_token = syntax.language.comment_token();
+        let mut content = String::new();
+        let mut depth = 0i32;
+        for line in data.content.split_inclusive('\n') {
+            let trimmed = line.trim_start_matches([' ', '\t']);
+            if trimmed.trim().is_empty() {
+                content.push_str(line);
+                continue;
+            }
+            let is_comment =
+                !comment_token.is_empty() && trimmed.starts_with(comment_token);
+            let (mut opens, mut closes, mut leading_closes) = (0, 0, 0);
+            if !is_comment {
+                for c in trimmed.chars() {
+                    match matching_pair_direction(c) {
+                        Some(true) => opens += 1,
+                        Some(false) => {
+                            if opens + closes == leading_closes {
+                                leading_closes += 1;
+                            }
+                            closes += 1;
+                        }
+                        None => {}
+                    }
                 }
-                let nonblank = buffer.first_non_blank_character_on_line(line);
-                if let Some(edit) =
-                    crate::indent::create_outdent(buffer, nonblank, indent)
-                {
-                    edits.push(edit);
+            }
+            let line_depth = depth - leading_closes;
+            depth += opens - closes;
+
+            let mut indent = base.clone();
+            if line_depth >= 0 {
+                indent.push_str(&indent_unit.repeat(line_depth as usize));
+            } else {
+                for _ in line_depth..0 {
+                    let len = indent.strip_suffix(indent_unit).map(|s| s.len());
+                    indent.truncate(len.unwrap_or(0));
                 }
             }
+            content.push_str(&indent);
+            content.push_str(trimmed);
         }
 
-        buffer.edit(&edits, EditType::Delete)
+        let data = RegisterData {
+            content,
+            mode: data.mode,
+        };
+        Self::do_paste(cursor, buffer, &data, false)
     }
 
-    pub fn do_edit<T: Clipboard>(
+    /// Replaces the indentation of the first non blank line of `content` with
+    /// `indent`, and shifts the following lines by the same amount.
+    fn reindent_lines(content: &str, indent: &str) -> String {
+        let leading = |line: &str| {
+            let len = line.len() - line.trim_start_matches([' ', '\t']).len();
+            line[..len].to_string()
+        };
+        let base = match content.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => leading(line),
+            None => return content.to_string(),
+        };
+
+        let mut result = String::new();
+        for line in content.split_inclusive('\n') {
+            if line.trim().is_empty() {
+                result.push_str(line);
+                continue;
+            }
+            let line_indent = leading(line);
+            if let Some(extra) = line_indent.strip_prefix(&base) {
+                result.push_str(indent);
+                result.push_str(extra);
+            } else if base.starts_with(&line_indent) {
+                // Less indented than the first line, so it loses a bit more
+                let removed = base.len() - line_indent.len();
+                result.push_str(&indent[..indent.len().saturating_sub(removed)]);
+            } else {
+                result.push_str(indent);
+            }
+            result.push_str(&line[line_indent.len()..]);
+        }
+        result
+    }
+
+    /// Whether the clipboard has one line for each of several regions, in
+    /// which case each region gets its own line rather than the whole
+    /// content. Lines copied from several carets end in a line break, so
+    /// linewise content counts too.
+    fn paste_lines_per_region(cursor: &Cursor, data: &RegisterData) -> bool {
+        if data.mode == VisualMode::Blockwise {
+            return false;
+        }
+        match &cursor.mode {
+            CursorMode::Insert(selection) if selection.len() > 1 => {
+                let content =
+                    data.content.strip_suffix('\n').unwrap_or(&data.content);
+                content.split('\n').count() == selection.len()
+            }
+            _ => false,
+        }
+    }
+
+    fn do_paste_distributed(
         cursor: &mut Cursor,
         buffer: &mut Buffer,
-        cmd: &EditCommand,
-        syntax: Option<&Syntax>,
-        clipboard: &mut T,
-        modal: bool,
-        register: &mut Register,
-    ) -> Vec<(RopeDelta, InvalLines)> {
-        use crate::command::EditCommand::*;
-        match cmd {
-            MoveLineUp => {
-                let mut deltas = Vec::new();
-                if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
-                    for region in selection.regions_mut() {
-                        let start_line = buffer.line_of_offset(region.min());
-                        if start_line > 0 {
-                            let previous_line_len =
-                                buffer.line_content(start_line - 1).len();
+        data: &RegisterData,
+    ) -> (RopeDelta, InvalLines) {
+        let selection = cursor.edit_selection(buffer);
+        let content = data.content.strip_suffix('\n').unwrap_or(&data.content);
+        let edits = selection
+            .regions()
+            .iter()
+            .zip(content.split('\n'))
+            .map(|(region, line)| {
+                (Selection::region(region.min(), region.max()), line)
+            })
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertChars);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        cursor.mode = CursorMode::Insert(selection);
+        (delta, inval_lines)
+    }
 
-                            let end_line = buffer.line_of_offset(region.max());
-                            let start = buffer.offset_of_line(start_line);
-                            let end = buffer.offset_of_line(end_line + 1);
-                            let content =
-                                buffer.slice_to_cow(start..end).to_string();
-                            let (delta, inval_lines) = buffer.edit(
-                                &[
-                                    (&Selection::region(start, end), ""),
-                                    (
-                                        &Selection::caret(
-                                            buffer.offset_of_line(start_line - 1),
-                                        ),
-                                        &content,
-                                    ),
-                                ],
-                                EditType::InsertChars,
-                            );
-                            deltas.push((delta, inval_lines));
-                            region.start -= previous_line_len;
-                            region.end -= previous_line_len;
-                        }
-                    }
-                    cursor.mode = CursorMode::Insert(selection);
-                }
-                deltas
+    /// Pastes every line of a block at the same column on consecutive lines,
+    /// starting with the line of the cursor. Lines that are too short get
+    /// padded with spaces, and missing lines are added at the end.
+    fn do_paste_block(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        content: &str,
+    ) -> (RopeDelta, InvalLines) {
+        let offset = match &cursor.mode {
+            CursorMode::Normal(offset) => {
+                let line_end = buffer.offset_line_end(*offset, true);
+                buffer.next_grapheme_offset(*offset, 1, line_end)
             }
-            MoveLineDown => {
-                let mut deltas = Vec::new();
-                if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
-                    for region in selection.regions_mut().iter_mut().rev() {
-                        let last_line = buffer.last_line();
-                        let start_line = buffer.line_of_offset(region.min());
-                        let end_line = buffer.line_of_offset(region.max());
-                        if end_line < last_line {
-                            let next_line_len =
-                                buffer.line_content(end_line + 1).len();
+            CursorMode::Insert(selection) => selection.min_offset(),
+            CursorMode::Visual { start, end, .. } => *start.min(end),
+        };
+        let (start_line, col) = buffer.offset_to_line_col(offset);
+        let block = content.strip_suffix('\n').unwrap_or(content);
+        let block = block.split('\n').collect::<Vec<_>>();
+        let width = block.iter().map(|l| l.chars().count()).max().unwrap_or(0);
 
-                            let start = buffer.offset_of_line(start_line);
-                            let end = buffer.offset_of_line(end_line + 1);
-                            let content =
-                                buffer.slice_to_cow(start..end).to_string();
-                            let (delta, inval_lines) = buffer.edit(
-                                &[
-                                    (
-                                        &Selection::caret(
-                                            buffer.offset_of_line(end_line + 2),
-                                        ),
-                                        &content,
-                                    ),
-                                    (&Selection::region(start, end), ""),
-                                ],
-                                EditType::InsertChars,
-                            );
-                            deltas.push((delta, inval_lines));
-                            region.start += next_line_len;
-                            region.end += next_line_len;
-                        }
-                    }
-                    cursor.mode = CursorMode::Insert(selection);
-                }
-                deltas
+        // A trailing line ending doesn't start another line to paste on
+        let mut last_line = buffer.last_line();
+        if last_line > 0 && buffer.line_content(last_line).is_empty() {
+            last_line -= 1;
+        }
+
+        let mut edits = Vec::new();
+        let mut appended = String::new();
+        for (i, block_line) in block.iter().enumerate() {
+            let line = start_line + i;
+            if line > last_line {
+                appended.push('\n');
+                appended.push_str(&" ".repeat(col));
+                appended.push_str(block_line);
+                continue;
             }
-            InsertNewLine => match cursor.mode.clone() {
-                CursorMode::Normal(offset) => {
-                    Self::insert_new_line(buffer, cursor, Selection::caret(offset))
-                }
-                CursorMode::Insert(selection) => {
-                    Self::insert_new_line(buffer, cursor, selection)
+
+            let line_end_col = buffer.line_end_col(line, true);
+            let text = if line_end_col < col {
+                " ".repeat(col - line_end_col) + block_line
+            } else if line_end_col > col {
+                // Keep the text after the block aligned
+                (*block_line).to_string()
+                    + &" ".repeat(width - block_line.chars().count())
+            } else {
+                (*block_line).to_string()
+            };
+            let offset = buffer.offset_of_line_col(line, col);
+            edits.push((Selection::caret(offset), text));
+        }
+        let ends_with_newline = !buffer.is_empty()
+            && buffer.offset_of_line(buffer.last_line()) == buffer.len();
+        if !appended.is_empty() {
+            if ends_with_newline {
+                appended.remove(0);
+                appended.push('\n');
+            }
+            edits.push((Selection::caret(buffer.len()), appended));
+        }
+
+        // Where the top left corner of the block ends up
+        let (first_offset, shift) = if start_line > last_line {
+            let newline = if ends_with_newline { 0 } else { 1 };
+            (buffer.len(), newline + col)
+        } else {
+            let line_end_col = buffer.line_end_col(start_line, true);
+            (edits[0].0.min_offset(), col.saturating_sub(line_end_col))
+        };
+        let edits = edits
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertChars);
+
+        let offset = Transformer::new(&delta).transform(first_offset, false) + shift;
+        cursor.mode = match cursor.mode {
+            CursorMode::Insert(_) => CursorMode::Insert(Selection::caret(offset)),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                CursorMode::Normal(offset)
+            }
+        };
+
+        (delta, inval_lines)
+    }
+
+    /// Adds the next occurrence of the text of the most recently added region
+    /// to an insert mode selection, wrapping around at the end of the buffer.
+    /// Carets are expanded to the words under them instead.
+    ///
+    /// `matcher` returns the first occurrence of a string at or after an
+    /// offset.
+    pub fn select_next_occurrence<F>(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        mut matcher: F,
+    ) where
+        F: FnMut(&str, usize) -> Option<(usize, usize)>,
+    {
+        let mut selection = match &cursor.mode {
+            CursorMode::Insert(selection) if !selection.is_empty() => {
+                selection.clone()
+            }
+            _ => return,
+        };
+
+        let mut had_caret = false;
+        for region in selection.regions_mut() {
+            if region.is_caret() {
+                had_caret = true;
+                let (start, end) = buffer.select_word(region.start);
+                region.start = start;
+                region.end = end;
+            }
+        }
+
+        if !had_caret {
+            let region = *selection.last_inserted().unwrap();
+            let search_str = buffer.slice_to_cow(region.min()..region.max());
+            let mut offset = region.max();
+            let mut wrapped = false;
+            while !search_str.is_empty() {
+                let (start, end) = match matcher(&search_str, offset) {
+                    Some(found) => found,
+                    None if !wrapped => {
+                        wrapped = true;
+                        offset = 0;
+                        continue;
+                    }
+                    None => break,
+                };
+                // Every occurrence has been looked at once
+                if wrapped && start >= region.max() {
+                    break;
                 }
-                CursorMode::Visual {
-                    start: _,
-                    end: _,
-                    mode: _,
-                } => {
-                    vec![]
+                if !selection
+                    .regions()
+                    .iter()
+                    .any(|r| r.min() == start && r.max() == end)
+                {
+                    selection.add_region(SelRegion::new(start, end, None));
+                    break;
                 }
-            },
-            InsertTab => {
-                let mut deltas = Vec::new();
-                if let CursorMode::Insert(selection) = &cursor.mode {
-                    let indent = buffer.indent_unit();
-                    let mut edits = Vec::new();
+                offset = end;
+            }
+        }
 
-                    for region in selection.regions() {
-                        if region.is_caret() {
-                            edits.push(crate::indent::create_edit(
-                                buffer,
-                                region.start,
-                                indent,
-                            ))
-                        } else {
-                            let start_line = buffer.line_of_offset(region.min());
-                            let end_line = buffer.line_of_offset(region.max());
-                            for line in start_line..=end_line {
-                                let offset =
-                                    buffer.first_non_blank_character_on_line(line);
-                                edits.push(crate::indent::create_edit(
-                                    buffer, offset, indent,
-                                ))
-                            }
-                        }
-                    }
+        cursor.set_insert(selection);
+    }
 
-                    let (delta, inval_lines) =
-                        buffer.edit(&edits, EditType::InsertChars);
-                    let selection =
-                        selection.apply_delta(&delta, true, InsertDrift::Default);
-                    deltas.push((delta, inval_lines));
-                    cursor.mode = CursorMode::Insert(selection);
-                }
-                deltas
+    /// Grows every region of an insert mode selection to the enclosing syntax
+    /// node, or to the enclosing word, line or paragraph without a syntax tree.
+    pub fn expand_selection(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+    ) {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.clone(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return,
+        };
+
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let (start, end) = syntax
+                .and_then(|syntax| {
+                    syntax.find_enclosing_node(region.min(), region.max())
+                })
+                .unwrap_or_else(|| {
+                    Self::enclosing_text_object(buffer, region.min(), region.max())
+                });
+            new_selection.add_region(SelRegion::new(start, end, None));
+        }
+        if new_selection == selection {
+            return;
+        }
+
+        // The history only applies to selections made by expanding
+        if cursor
+            .expand_history
+            .last()
+            .map(|(_, after)| after != &selection)
+            .unwrap_or(false)
+        {
+            cursor.expand_history.clear();
+        }
+        cursor
+            .expand_history
+            .push((selection, new_selection.clone()));
+        cursor.mode = CursorMode::Insert(new_selection);
+    }
+
+    /// Reverts the last expand selection, if the selection hasn't changed
+    /// since.
+    pub fn shrink_selection(cursor: &mut Cursor) {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection,
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return,
+        };
+        match cursor.expand_history.pop() {
+            Some((before, after)) if &after == selection => {
+                cursor.mode = CursorMode::Insert(before);
             }
-            IndentLine => {
-                let selection = cursor.edit_selection(buffer);
-                let (delta, inval_lines) = Self::do_indent(buffer, selection);
-                cursor.apply_delta(&delta);
-                vec![(delta, inval_lines)]
+            _ => cursor.expand_history.clear(),
+        }
+    }
+
+    /// Moves the cursor to grapheme column `col` of `line`, both counting from
+    /// 0 and clamped to the buffer and the line. A visual selection is
+    /// extended to there instead.
+    pub fn goto(cursor: &mut Cursor, buffer: &Buffer, line: usize, col: usize) {
+        let caret = !matches!(cursor.mode, CursorMode::Normal(_));
+        let offset = buffer.offset_of_line_grapheme(line, col, caret);
+        match &cursor.mode {
+            CursorMode::Normal(_) => cursor.mode = CursorMode::Normal(offset),
+            CursorMode::Visual { start, mode, .. } => {
+                cursor.mode = CursorMode::Visual {
+                    start: *start,
+                    end: offset,
+                    mode: *mode,
+                };
             }
-            JoinLines => {
-                let offset = cursor.offset();
-                let (line, _col) = buffer.offset_to_line_col(offset);
-                if line < buffer.last_line() {
-                    let start = buffer.line_end_offset(line, true);
-                    let end = buffer.first_non_blank_character_on_line(line + 1);
-                    vec![buffer.edit(
-                        &[(&Selection::region(start, end), " ")],
-                        EditType::Other,
-                    )]
-                } else {
-                    vec![]
-                }
+            CursorMode::Insert(_) => cursor.set_insert(Selection::caret(offset)),
+        }
+        cursor.horiz = None;
+    }
+
+    /// Moves every caret to the first non-blank character of its line, or to
+    /// the start of the line when it is already there.
+    pub fn smart_home(cursor: &mut Cursor, buffer: &Buffer) {
+        let home = |offset: usize| {
+            let line = buffer.line_of_offset(offset);
+            let non_blank = buffer.first_non_blank_character_on_line(line);
+            if offset == non_blank {
+                buffer.offset_of_line(line)
+            } else {
+                non_blank
             }
-            OutdentLine => {
-                let selection = cursor.edit_selection(buffer);
-                let (delta, inval_lines) = Self::do_outdent(buffer, selection);
-                cursor.apply_delta(&delta);
-                vec![(delta, inval_lines)]
+        };
+        match &cursor.mode {
+            CursorMode::Normal(offset) => {
+                cursor.mode = CursorMode::Normal(home(*offset))
             }
-            ToggleLineComment => {
-                let mut lines = HashSet::new();
-                let selection = cursor.edit_selection(buffer);
-                let comment_token =
-                    syntax.map(|s| s.language.comment_token()).unwrap_or("//");
-                let mut had_comment = true;
-                let mut smallest_indent = usize::MAX;
+            CursorMode::Visual { start, end, mode } => {
+                cursor.mode = CursorMode::Visual {
+                    start: *start,
+                    end: home(*end),
+                    mode: *mode,
+                };
+            }
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
                 for region in selection.regions() {
-                    let mut line = buffer.line_of_offset(region.min());
-                    let end_line = buffer.line_of_offset(region.max());
-                    let end_line_offset = buffer.offset_of_line(end_line);
-                    let end = if end_line > line && region.max() == end_line_offset {
-                        end_line_offset
-                    } else {
-                        buffer.offset_of_line(end_line + 1)
-                    };
-                    let start = buffer.offset_of_line(line);
-                    for content in buffer.text().lines(start..end) {
-                        let trimmed_content = content.trim_start();
-                        if trimmed_content.is_empty() {
-                            line += 1;
-                            continue;
-                        }
-                        let indent = content.len() - trimmed_content.len();
-                        if indent < smallest_indent {
-                            smallest_indent = indent;
-                        }
-                        if !trimmed_content.starts_with(&comment_token) {
-                            had_comment = false;
-                            lines.insert((line, indent, 0));
-                        } else {
-                            let had_space_after_comment =
-                                trimmed_content.chars().nth(comment_token.len())
-                                    == Some(' ');
-                            lines.insert((
-                                line,
-                                indent,
-                                comment_token.len()
-                                    + if had_space_after_comment { 1 } else { 0 },
-                            ));
-                        }
-                        line += 1;
-                    }
+                    new_selection.add_region(SelRegion::caret(home(region.end)));
                 }
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+        }
+        cursor.horiz = None;
+    }
 
-                let (delta, inval_lines) = if had_comment {
-                    let mut selection = Selection::new();
-                    for (line, indent, len) in lines.iter() {
-                        let start = buffer.offset_of_line(*line) + indent;
-                        selection.add_region(SelRegion::new(
-                            start,
-                            start + len,
-                            None,
-                        ))
-                    }
-                    buffer.edit(&[(&selection, "")], EditType::Delete)
-                } else {
-                    let mut selection = Selection::new();
-                    for (line, _, _) in lines.iter() {
-                        let start = buffer.offset_of_line(*line) + smallest_indent;
-                        selection.add_region(SelRegion::new(start, start, None))
-                    }
-                    buffer.edit(
-                        &[(&selection, &format!("{comment_token} "))],
-                        EditType::InsertChars,
-                    )
+    /// Selects the whole buffer, with the caret at the end.
+    pub fn select_all(cursor: &mut Cursor, buffer: &Buffer) {
+        cursor.set_insert(Selection::region(0, buffer.len()));
+    }
+
+    /// Selects the full line of every caret, including its newline. Selecting
+    /// again extends each selection by the following line.
+    pub fn select_line(cursor: &mut Cursor, buffer: &Buffer) {
+        match &cursor.mode {
+            CursorMode::Normal(offset) => {
+                cursor.mode = CursorMode::Visual {
+                    start: *offset,
+                    end: *offset,
+                    mode: VisualMode::Linewise,
                 };
-                cursor.apply_delta(&delta);
-                vec![(delta, inval_lines)]
             }
-            Undo => {
-                if let Some((delta, inval_lines, cursor_mode)) = buffer.do_undo() {
-                    if let Some(cursor_mode) = cursor_mode {
-                        if modal {
-                            cursor.mode = CursorMode::Normal(cursor_mode.offset());
-                        } else {
-                            cursor.mode = cursor_mode;
-                        }
-                    } else if let Some(new_cursor) =
-                        get_first_selection_after(cursor, buffer, &delta)
-                    {
-                        *cursor = new_cursor
-                    } else {
-                        cursor.apply_delta(&delta);
-                    }
-                    vec![(delta, inval_lines)]
+            CursorMode::Visual {
+                start,
+                end,
+                mode: VisualMode::Linewise,
+            } => {
+                let line = buffer.line_of_offset(*end);
+                let end = if line < buffer.last_line() {
+                    buffer.offset_of_line(line + 1)
                 } else {
-                    vec![]
+                    *end
+                };
+                cursor.mode = CursorMode::Visual {
+                    start: *start,
+                    end,
+                    mode: VisualMode::Linewise,
+                };
+            }
+            CursorMode::Visual { start, end, .. } => {
+                cursor.mode = CursorMode::Visual {
+                    start: *start,
+                    end: *end,
+                    mode: VisualMode::Linewise,
+                };
+            }
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let start =
+                        buffer.offset_of_line(buffer.line_of_offset(region.min()));
+                    // A selection ending at a line start already covers the
+                    // line before, so this extends it by one line
+                    let end = buffer
+                        .offset_of_line(buffer.line_of_offset(region.max()) + 1);
+                    new_selection.add_region(SelRegion::new(start, end, None));
                 }
+                cursor.set_insert(new_selection);
             }
-            Redo => {
-                if let Some((delta, inval_lines, cursor_mode)) = buffer.do_redo() {
-                    if let Some(cursor_mode) = cursor_mode {
-                        if modal {
-                            cursor.mode = CursorMode::Normal(cursor_mode.offset());
-                        } else {
-                            cursor.mode = cursor_mode;
-                        }
-                    } else if let Some(new_cursor) =
-                        get_first_selection_after(cursor, buffer, &delta)
-                    {
-                        *cursor = new_cursor
-                    } else {
-                        cursor.apply_delta(&delta);
-                    }
-                    vec![(delta, inval_lines)]
-                } else {
-                    vec![]
+        }
+    }
+
+    /// Selects the paragraph around every caret, paragraphs being separated
+    /// by blank lines. On a blank line the blank lines around it are selected
+    /// instead. Unless `inner` is set, the blank lines following a paragraph,
+    /// or the paragraph following blank lines, are selected too.
+    pub fn select_paragraph(cursor: &mut Cursor, buffer: &Buffer, inner: bool) {
+        match &cursor.mode {
+            CursorMode::Normal(offset) | CursorMode::Visual { end: offset, .. } => {
+                let line = buffer.line_of_offset(*offset);
+                let (first_line, last_line) =
+                    Self::paragraph_lines(buffer, line, inner);
+                cursor.mode = CursorMode::Visual {
+                    start: buffer.offset_of_line(first_line),
+                    end: buffer.offset_of_line(last_line),
+                    mode: VisualMode::Linewise,
+                };
+            }
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let line = buffer.line_of_offset(region.end);
+                    let (first_line, last_line) =
+                        Self::paragraph_lines(buffer, line, inner);
+                    new_selection.add_region(SelRegion::new(
+                        buffer.offset_of_line(first_line),
+                        buffer.offset_of_line(last_line + 1),
+                        None,
+                    ));
                 }
+                cursor.set_insert(new_selection);
             }
-            ClipboardCopy => {
-                let data = cursor.yank(buffer);
-                clipboard.put_string(data.content);
+        }
+    }
 
-                match &cursor.mode {
-                    CursorMode::Visual {
-                        start,
-                        end,
-                        mode: _,
-                    } => {
-                        let offset = *start.min(end);
-                        let offset =
-                            buffer.offset_line_end(offset, false).min(offset);
-                        cursor.mode = CursorMode::Normal(offset);
-                    }
-                    CursorMode::Normal(_) | CursorMode::Insert(_) => {}
-                }
-                vec![]
+    fn paragraph_lines(buffer: &Buffer, line: usize, inner: bool) -> (usize, usize) {
+        let is_blank = |line: usize| buffer.line_content(line).trim().is_empty();
+        let blank = is_blank(line);
+        let mut first_line = line;
+        while first_line > 0 && is_blank(first_line - 1) == blank {
+            first_line -= 1;
+        }
+        let mut last_line = line;
+        while last_line < buffer.last_line() && is_blank(last_line + 1) == blank {
+            last_line += 1;
+        }
+        if inner {
+            return (first_line, last_line);
+        }
+
+        if last_line < buffer.last_line() {
+            let next_blank = !blank;
+            last_line += 1;
+            while last_line < buffer.last_line()
+                && is_blank(last_line + 1) == next_blank
+            {
+                last_line += 1;
             }
-            ClipboardCut => {
-                let data = cursor.yank(buffer);
-                clipboard.put_string(data.content);
+        } else if !blank {
+            // Without blank lines after the last paragraph, the ones before
+            // it are taken
+            while first_line > 0 && is_blank(first_line - 1) {
+                first_line -= 1;
+            }
+        }
+        (first_line, last_line)
+    }
 
-                let selection =
-                    if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
-                        for region in selection.regions_mut() {
-                            if region.is_caret() {
-                                let line = buffer.line_of_offset(region.start);
-                                let start = buffer.offset_of_line(line);
-                                let end = buffer.offset_of_line(line + 1);
-                                region.start = start;
-                                region.end = end;
-                            }
+    /// Selects the innermost function, method, closure or block around every
+    /// region, see [`Syntax::find_enclosing_scope`]. Without a syntax tree,
+    /// or outside of any scope, the indentation block of the region gets
+    /// selected like with [`Editor::select_indent_block`].
+    pub fn select_enclosing_scope(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+    ) {
+        let scope = |start: usize, end: usize| {
+            syntax.and_then(|syntax| syntax.find_enclosing_scope(start, end))
+        };
+        match &cursor.mode {
+            CursorMode::Normal(offset) | CursorMode::Visual { end: offset, .. } => {
+                let (start, end) = match scope(*offset, *offset) {
+                    Some(range) => range,
+                    None => return Self::select_indent_block(cursor, buffer, false),
+                };
+                cursor.mode = CursorMode::Visual {
+                    start,
+                    end: buffer.prev_grapheme_offset(end, 1, start),
+                    mode: VisualMode::Normal,
+                };
+            }
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let (start, end) = match scope(region.min(), region.max()) {
+                        Some(range) => range,
+                        None => {
+                            let line = buffer.line_of_offset(region.end);
+                            let (first_line, last_line) =
+                                Self::indent_block_lines(buffer, line, false);
+                            (
+                                buffer.offset_of_line(first_line),
+                                buffer.offset_of_line(last_line + 1),
+                            )
                         }
-                        selection
-                    } else {
-                        cursor.edit_selection(buffer)
                     };
+                    new_selection.add_region(SelRegion::new(start, end, None));
+                }
+                cursor.set_insert(new_selection);
+            }
+        }
+    }
 
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.update_selection(buffer, selection);
-                vec![(delta, inval_lines)]
+    /// Selects the block of lines indented at least as deep as the line of
+    /// every caret, blank lines included. Unless `inner` is set, the less
+    /// indented line heading the block is selected too.
+    pub fn select_indent_block(cursor: &mut Cursor, buffer: &Buffer, inner: bool) {
+        match &cursor.mode {
+            CursorMode::Normal(offset) | CursorMode::Visual { end: offset, .. } => {
+                let line = buffer.line_of_offset(*offset);
+                let (first_line, last_line) =
+                    Self::indent_block_lines(buffer, line, inner);
+                cursor.mode = CursorMode::Visual {
+                    start: buffer.offset_of_line(first_line),
+                    end: buffer.offset_of_line(last_line),
+                    mode: VisualMode::Linewise,
+                };
             }
-            ClipboardPaste => {
-                if let Some(s) = clipboard.get_string() {
-                    let mode = if s.ends_with('\n') {
-                        VisualMode::Linewise
-                    } else {
-                        VisualMode::Normal
-                    };
-                    let data = RegisterData { content: s, mode };
-                    Self::do_paste(cursor, buffer, &data)
-                } else {
-                    vec![]
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let line = buffer.line_of_offset(region.end);
+                    let (first_line, last_line) =
+                        Self::indent_block_lines(buffer, line, inner);
+                    new_selection.add_region(SelRegion::new(
+                        buffer.offset_of_line(first_line),
+                        buffer.offset_of_line(last_line + 1),
+                        None,
+                    ));
                 }
+                cursor.set_insert(new_selection);
             }
-            Yank => {
-                match &cursor.mode {
-                    CursorMode::Visual { start, end, .. } => {
-                        let data = cursor.yank(buffer);
-                        register.add_yank(data);
+        }
+    }
 
-                        let offset = *start.min(end);
-                        let offset =
-                            buffer.offset_line_end(offset, false).min(offset);
-                        cursor.mode = CursorMode::Normal(offset);
-                    }
-                    CursorMode::Normal(_) => {}
-                    CursorMode::Insert(_) => {}
+    fn indent_block_lines(
+        buffer: &Buffer,
+        line: usize,
+        inner: bool,
+    ) -> (usize, usize) {
+        let is_blank = |line: usize| buffer.line_content(line).trim().is_empty();
+        let indent = |line: usize| buffer.indent_on_line(line).chars().count();
+
+        // A blank line belongs to the block of the lines after it, or of the
+        // ones before it at the end of the buffer
+        let last = buffer.last_line();
+        let line = match (line..=last)
+            .find(|l| !is_blank(*l))
+            .or_else(|| (0..line).rev().find(|l| !is_blank(*l)))
+        {
+            Some(line) => line,
+            None => return (line, line),
+        };
+        let width = indent(line);
+        let in_block = |line: usize| is_blank(line) || indent(line) >= width;
+
+        let mut first_line = line;
+        while first_line > 0 && in_block(first_line - 1) {
+            first_line -= 1;
+        }
+        let mut last_line = line;
+        while last_line < last && in_block(last_line + 1) {
+            last_line += 1;
+        }
+        while is_blank(first_line) {
+            first_line += 1;
+        }
+        while is_blank(last_line) {
+            last_line -= 1;
+        }
+
+        if !inner && first_line > 0 {
+            let header = (0..first_line).rev().find(|l| !is_blank(*l));
+            if let Some(header) = header {
+                first_line = header;
+            }
+        }
+        (first_line, last_line)
+    }
+
+    /// Selects the word under every caret, a run of punctuation or of
+    /// whitespace counting as a word too. With `around`, the whitespace after
+    /// the word is selected as well, or the whitespace before it when there
+    /// is none after. Around whitespace, the word after it is added instead.
+    pub fn select_word_object(cursor: &mut Cursor, buffer: &Buffer, around: bool) {
+        match &cursor.mode {
+            CursorMode::Normal(offset) | CursorMode::Visual { end: offset, .. } => {
+                if let Some((start, end)) =
+                    Self::word_object(buffer, *offset, around)
+                {
+                    cursor.mode = CursorMode::Visual {
+                        start,
+                        end: buffer.prev_grapheme_offset(end, 1, start),
+                        mode: VisualMode::Normal,
+                    };
                 }
-                vec![]
             }
-            Paste => {
-                let data = register.unnamed.clone();
-                Self::do_paste(cursor, buffer, &data)
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let new_region =
+                        match Self::word_object(buffer, region.end, around) {
+                            Some((start, end)) => SelRegion::new(start, end, None),
+                            None => *region,
+                        };
+                    new_selection.add_region(new_region);
+                }
+                cursor.set_insert(new_selection);
             }
-            NewLineAbove => {
-                let offset = cursor.offset();
-                let line = buffer.line_of_offset(offset);
-                let offset = if line > 0 {
-                    buffer.line_end_offset(line - 1, true)
-                } else {
-                    buffer.first_non_blank_character_on_line(line)
-                };
-                let delta =
-                    Self::insert_new_line(buffer, cursor, Selection::caret(offset));
-                if line == 0 {
-                    cursor.mode = CursorMode::Insert(Selection::caret(offset));
+        }
+    }
+
+    /// The word object at `offset`, or `None` at the end of a line.
+    fn word_object(
+        buffer: &Buffer,
+        offset: usize,
+        around: bool,
+    ) -> Option<(usize, usize)> {
+        let line = buffer.line_of_offset(offset);
+        let line_start = buffer.offset_of_line(line);
+        let content =
+            buffer.slice_to_cow(line_start..buffer.line_end_offset(line, true));
+        let chars = content.char_indices().collect::<Vec<_>>();
+        let index = chars.iter().position(|(i, _)| line_start + i == offset)?;
+        let prop = |i: usize| get_word_property(chars[i].1);
+        let offset_at = |i: usize| {
+            chars
+                .get(i)
+                .map(|(i, _)| line_start + i)
+                .unwrap_or(line_start + content.len())
+        };
+
+        let word_prop = prop(index);
+        let mut start = index;
+        while start > 0 && prop(start - 1) == word_prop {
+            start -= 1;
+        }
+        let mut end = index + 1;
+        while end < chars.len() && prop(end) == word_prop {
+            end += 1;
+        }
+        if !around {
+            return Some((offset_at(start), offset_at(end)));
+        }
+
+        if word_prop == WordProperty::Space {
+            if end < chars.len() {
+                let next_prop = prop(end);
+                while end < chars.len() && prop(end) == next_prop {
+                    end += 1;
                 }
-                delta
             }
-            NewLineBelow => {
-                let offset = cursor.offset();
-                let offset = buffer.offset_line_end(offset, true);
-                Self::insert_new_line(buffer, cursor, Selection::caret(offset))
+        } else if end < chars.len() && prop(end) == WordProperty::Space {
+            while end < chars.len() && prop(end) == WordProperty::Space {
+                end += 1;
             }
-            DeleteBackward => {
-                let selection = match cursor.mode {
-                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
-                        cursor.edit_selection(buffer)
-                    }
-                    CursorMode::Insert(_) => {
-                        let indent = buffer.indent_unit();
-                        let selection = cursor.edit_selection(buffer);
-                        let mut new_selection = Selection::new();
-                        for region in selection.regions() {
-                            let new_region = if region.is_caret() {
-                                if indent.starts_with('\t') {
-                                    let new_end = buffer.move_left(
-                                        region.end,
-                                        Mode::Insert,
-                                        1,
-                                    );
-                                    SelRegion::new(region.start, new_end, None)
-                                } else {
-                                    let line = buffer.line_of_offset(region.start);
-                                    let nonblank = buffer
-                                        .first_non_blank_character_on_line(line);
-                                    let (_, col) =
-                                        buffer.offset_to_line_col(region.start);
-                                    let count =
-                                        if region.start <= nonblank && col > 0 {
-                                            let r = col % indent.len();
-                                            if r == 0 {
-                                                indent.len()
-                                            } else {
-                                                r
-                                            }
-                                        } else {
-                                            1
-                                        };
-                                    let new_end = buffer.move_left(
-                                        region.end,
-                                        Mode::Insert,
-                                        count,
-                                    );
-                                    SelRegion::new(region.start, new_end, None)
-                                }
-                            } else {
-                                *region
-                            };
-                            new_selection.add_region(new_region);
-                        }
-
-                        let mut selection = new_selection;
-                        if selection.regions().len() == 1 {
-                            let delete_str = buffer
-                                .slice_to_cow(
-                                    selection.min_offset()..selection.max_offset(),
-                                )
-                                .to_string();
-                            if str_is_pair_left(&delete_str) {
-                                if let Some(c) = str_matching_pair(&delete_str) {
-                                    let offset = selection.max_offset();
-                                    let line = buffer.line_of_offset(offset);
-                                    let line_end =
-                                        buffer.line_end_offset(line, true);
-                                    let content = buffer
-                                        .slice_to_cow(offset..line_end)
-                                        .to_string();
-                                    if content.trim().starts_with(&c.to_string()) {
-                                        let index = content
-                                            .match_indices(c)
-                                            .next()
-                                            .unwrap()
-                                            .0;
-                                        selection = Selection::region(
-                                            selection.min_offset(),
-                                            offset + index + 1,
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                        selection
-                    }
-                };
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.update_selection(buffer, selection);
-                vec![(delta, inval_lines)]
+        } else {
+            while start > 0 && prop(start - 1) == WordProperty::Space {
+                start -= 1;
             }
-            DeleteForward => {
-                let selection = match cursor.mode {
-                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
-                        cursor.edit_selection(buffer)
-                    }
-                    CursorMode::Insert(_) => {
-                        let selection = cursor.edit_selection(buffer);
-                        let mut new_selection = Selection::new();
-                        for region in selection.regions() {
-                            let new_region = if region.is_caret() {
-                                let new_end =
-                                    buffer.move_right(region.end, Mode::Insert, 1);
-                                SelRegion::new(region.start, new_end, None)
-                            } else {
-                                *region
-                            };
-                            new_selection.add_region(new_region);
-                        }
-                        new_selection
-                    }
+        }
+        Some((offset_at(start), offset_at(end)))
+    }
+
+    /// The word, line, paragraph or whole buffer enclosing `start..end`,
+    /// whichever is the smallest one larger than it.
+    fn enclosing_text_object(
+        buffer: &Buffer,
+        start: usize,
+        end: usize,
+    ) -> (usize, usize) {
+        let (word_start, word_end) = buffer.select_word(start);
+        if word_start <= start
+            && end <= word_end
+            && (word_start, word_end) != (start, end)
+        {
+            return (word_start, word_end);
+        }
+
+        let start_line = buffer.line_of_offset(start);
+        let end_line = buffer.line_of_offset(end);
+        let line_start = buffer.offset_of_line(start_line);
+        let line_end = buffer.line_end_offset(start_line, true);
+        if start_line == end_line && (line_start, line_end) != (start, end) {
+            return (line_start, line_end);
+        }
+
+        let is_blank = |line: usize| buffer.line_content(line).trim().is_empty();
+        let mut first_line = start_line;
+        while first_line > 0 && !is_blank(first_line - 1) {
+            first_line -= 1;
+        }
+        let mut last_line = end_line;
+        while last_line < buffer.last_line() && !is_blank(last_line + 1) {
+            last_line += 1;
+        }
+        let paragraph_start = buffer.offset_of_line(first_line);
+        let paragraph_end = buffer.line_end_offset(last_line, true);
+        if paragraph_start <= start
+            && end <= paragraph_end
+            && (paragraph_start, paragraph_end) != (start, end)
+        {
+            return (paragraph_start, paragraph_end);
+        }
+
+        (0, buffer.len())
+    }
+
+    /// Selects the content of the innermost pair of `c` enclosing each region,
+    /// optionally together with the delimiters themselves. Either side of the
+    /// pair can be given. Regions outside of any such pair are dropped, and
+    /// `None` is returned when none of them is inside one.
+    pub fn select_inside_pair(
+        cursor: &Cursor,
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+        c: char,
+        include_delimiters: bool,
+    ) -> Option<Selection> {
+        let (open, close) = match matching_pair_direction(c)? {
+            true => (c, matching_char(c)?),
+            false => (matching_char(c)?, c),
+        };
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let (start, end) =
+                match Self::pair_around(buffer, syntax, open, close, region) {
+                    Some(pair) => pair,
+                    None => continue,
                 };
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.update_selection(buffer, selection);
-                vec![(delta, inval_lines)]
+            let new_region = if include_delimiters {
+                SelRegion::new(start, end + close.len_utf8(), None)
+            } else {
+                SelRegion::new(start + open.len_utf8(), end, None)
+            };
+            new_selection.add_region(new_region);
+        }
+
+        if new_selection.is_empty() {
+            None
+        } else {
+            Some(new_selection)
+        }
+    }
+
+    /// The offsets of the delimiters of the innermost pair of `open` and
+    /// `close` enclosing `region`.
+    fn pair_around(
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+        open: char,
+        close: char,
+        region: &SelRegion,
+    ) -> Option<(usize, usize)> {
+        let offset = region.min();
+        // Being right on the opening character counts as being inside
+        let search_offset = if buffer.char_at_offset(offset) == Some(open) {
+            offset + open.len_utf8()
+        } else {
+            offset
+        };
+        let start = buffer.previous_unmatched(syntax, open, search_offset)?;
+        let end =
+            buffer.next_unmatched(syntax, close, region.max().max(search_offset))?;
+        Some((start, end))
+    }
+
+    /// Selects the innermost pair enclosing each region together with its
+    /// delimiters, like `a(` in vim. Bracket pairs go through
+    /// [`Editor::select_inside_pair`], while other delimiters, which can be
+    /// several characters long, are searched for in the text with nested
+    /// pairs skipped over.
+    pub fn select_around_pair(
+        cursor: &Cursor,
+        buffer: &Buffer,
+        syntax: Option<&Syntax>,
+        open: &str,
+        close: &str,
+    ) -> Option<Selection> {
+        let mut chars = open.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if matching_pair_direction(c) == Some(true)
+                && matching_char(c).map(String::from).as_deref() == Some(close)
+            {
+                return Self::select_inside_pair(cursor, buffer, syntax, c, true);
             }
-            DeleteWordForward => {
-                let selection = match cursor.mode {
-                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
-                        cursor.edit_selection(buffer)
+        }
+        if open.is_empty() || close.is_empty() {
+            return None;
+        }
+
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+        let text = buffer.slice_to_cow(0..buffer.len());
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            // Being right on the opening delimiter counts as being inside
+            let offset = if text[region.min()..].starts_with(open) {
+                region.min() + open.len()
+            } else {
+                region.min()
+            };
+            let start = match Self::find_unmatched(&text, open, close, offset, false)
+            {
+                Some(start) => start,
+                None => continue,
+            };
+            let end = match Self::find_unmatched(
+                &text,
+                open,
+                close,
+                region.max().max(offset),
+                true,
+            ) {
+                Some(end) => end,
+                None => continue,
+            };
+            new_selection.add_region(SelRegion::new(start, end + close.len(), None));
+        }
+
+        if new_selection.is_empty() {
+            None
+        } else {
+            Some(new_selection)
+        }
+    }
+
+    /// The offset of the closing delimiter after `offset`, or of the opening
+    /// one before it, that isn't part of a nested pair.
+    fn find_unmatched(
+        text: &str,
+        open: &str,
+        close: &str,
+        offset: usize,
+        forward: bool,
+    ) -> Option<usize> {
+        let (target, nested) = if forward {
+            (close, open)
+        } else {
+            (open, close)
+        };
+        let mut depth = 0;
+        let mut i = offset;
+        loop {
+            if forward {
+                if i >= text.len() {
+                    return None;
+                }
+            } else {
+                if i == 0 {
+                    return None;
+                }
+                i -= 1;
+                while !text.is_char_boundary(i) {
+                    i -= 1;
+                }
+            }
+            let rest = &text[i..];
+            // When both delimiters are the same, the nearest one is the match
+            if rest.starts_with(target) {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+                if forward {
+                    i += target.len();
+                    continue;
+                }
+            } else if target != nested && rest.starts_with(nested) {
+                depth += 1;
+                if forward {
+                    i += nested.len();
+                    continue;
+                }
+            }
+            if forward {
+                i += rest.chars().next().map_or(1, |c| c.len_utf8());
+            }
+        }
+    }
+
+    /// Removes the delimiters of the innermost pair of `c` enclosing each
+    /// region. Nothing happens outside of such a pair.
+    pub fn delete_surround(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        syntax: Option<&Syntax>,
+        c: char,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        Self::replace_surround(cursor, buffer, syntax, c, "", "")
+    }
+
+    /// Replaces the delimiters of the innermost pair of `from` enclosing each
+    /// region with the pair of `to`. Nothing happens outside of such a pair.
+    pub fn change_surround(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        syntax: Option<&Syntax>,
+        from: char,
+        to: char,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (open, close) = match matching_pair_direction(to) {
+            Some(true) => (to, matching_char(to).unwrap_or(to)),
+            Some(false) => (matching_char(to).unwrap_or(to), to),
+            None => (to, to),
+        };
+        Self::replace_surround(
+            cursor,
+            buffer,
+            syntax,
+            from,
+            &open.to_string(),
+            &close.to_string(),
+        )
+    }
+
+    fn replace_surround(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        syntax: Option<&Syntax>,
+        c: char,
+        open: &str,
+        close: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (open_char, close_char) = match matching_pair_direction(c) {
+            Some(true) => (c, matching_char(c).unwrap_or(c)),
+            Some(false) => (matching_char(c).unwrap_or(c), c),
+            None => return vec![],
+        };
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        // The pair of every region is looked up on its own, since the regions
+        // can be in different, even nested, pairs. Both delimiters of every
+        // pair are replaced in a single edit.
+        let mut delimiters = Vec::new();
+        for region in selection.regions() {
+            if let Some((start, end)) =
+                Self::pair_around(buffer, syntax, open_char, close_char, region)
+            {
+                let close_end = end + close_char.len_utf8();
+                delimiters.push((start, start + open_char.len_utf8(), open));
+                delimiters.push((end, close_end, close));
+            }
+        }
+        if delimiters.is_empty() {
+            return vec![];
+        }
+        delimiters.sort_by_key(|(start, _, _)| *start);
+        delimiters.dedup_by_key(|(start, _, _)| *start);
+        let edits = delimiters
+            .iter()
+            .map(|(start, end, content)| (Selection::region(*start, *end), *content))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        let mut transformer = Transformer::new(&delta);
+        match &cursor.mode {
+            CursorMode::Normal(offset) => {
+                let offset = transformer.transform(*offset, false);
+                let offset = offset.min(buffer.offset_line_end(offset, false));
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Visual { start, end, .. } => {
+                let offset = transformer.transform(*start.min(end), false);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Insert(selection) => {
+                let selection =
+                    selection.apply_delta(&delta, false, InsertDrift::Default);
+                cursor.mode = CursorMode::Insert(selection);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    fn do_indent(
+        buffer: &mut Buffer,
+        selection: Selection,
+    ) -> (RopeDelta, InvalLines) {
+        let mut lines = HashSet::new();
+        let edits = Self::indent_lines(buffer, selection.regions(), &mut lines);
+        buffer.edit(&edits, EditType::InsertChars)
+    }
+
+    /// The edits indenting every non blank line covered by the regions once,
+    /// skipping the lines already in `lines` and adding the others to it.
+    fn indent_lines<'a>(
+        buffer: &Buffer,
+        regions: impl IntoIterator<Item = &'a SelRegion>,
+        lines: &mut HashSet<usize>,
+    ) -> Vec<(Selection, &'static str)> {
+        let indent = buffer.indent_unit();
+        let mut edits = Vec::new();
+        for region in regions {
+            let start_line = buffer.line_of_offset(region.min());
+            let mut end_line = buffer.line_of_offset(region.max());
+            if end_line > start_line {
+                let end_line_start = buffer.offset_of_line(end_line);
+                if end_line_start == region.max() {
+                    end_line -= 1;
+                }
+            }
+            for line in start_line..=end_line {
+                if lines.contains(&line) {
+                    continue;
+                }
+                lines.insert(line);
+                let line_content = buffer.line_content(line);
+                if line_content.trim_end_matches(&['\r', '\n'][..]).is_empty() {
+                    continue;
+                }
+                let nonblank = buffer.first_non_blank_character_on_line(line);
+                let edit = crate::indent::create_edit(buffer, nonblank, indent);
+                edits.push(edit);
+            }
+        }
+        edits
+    }
+
+    /// Inserts the buffer's indentation unit at every caret, padding spaces
+    /// up to the next indentation stop. Lines covered by a selection are
+    /// indented as a whole instead.
+    pub fn tab(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.clone(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return vec![],
+        };
+        let indent = buffer.indent_unit();
+        let mut lines = HashSet::new();
+        let mut edits = Self::indent_lines(
+            buffer,
+            selection
+                .regions()
+                .iter()
+                .filter(|region| !region.is_caret()),
+            &mut lines,
+        );
+        // Carets on the lines indented as a whole are moved along with them
+        for region in selection.regions() {
+            if region.is_caret()
+                && !lines.contains(&buffer.line_of_offset(region.start))
+            {
+                edits.push(crate::indent::create_edit(buffer, region.start, indent))
+            }
+        }
+
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertChars);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        cursor.mode = CursorMode::Insert(selection);
+        vec![(delta, inval_lines)]
+    }
+
+    /// Outdents the lines of every caret and selection by one indentation
+    /// unit, the companion of [`Editor::tab`].
+    pub fn back_tab(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection.clone(),
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => return vec![],
+        };
+        let (delta, inval_lines) = Self::do_outdent(buffer, selection);
+        Self::apply_indent_delta(cursor, &delta);
+        vec![(delta, inval_lines)]
+    }
+
+    /// Indents the lines of the cursor by one indentation unit, like `Ctrl-T`
+    /// in vim's insert mode. Carets stay on the character they were on.
+    pub fn shift_line_right(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = cursor.edit_selection(buffer);
+        let (delta, inval_lines) = Self::do_indent(buffer, selection);
+        Self::apply_indent_delta(cursor, &delta);
+        vec![(delta, inval_lines)]
+    }
+
+    /// Outdents the lines of the cursor by one indentation unit, like
+    /// `Ctrl-D` in vim's insert mode. Carets stay on the character they were
+    /// on.
+    pub fn shift_line_left(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = cursor.edit_selection(buffer);
+        let (delta, inval_lines) = Self::do_outdent(buffer, selection);
+        Self::apply_indent_delta(cursor, &delta);
+        vec![(delta, inval_lines)]
+    }
+
+    /// Updates the cursor after indenting or outdenting lines, keeping
+    /// selections stretched over the lines they covered before.
+    fn apply_indent_delta(cursor: &mut Cursor, delta: &RopeDelta) {
+        let selection = match &cursor.mode {
+            CursorMode::Insert(selection) => selection,
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.apply_delta(delta);
+                return;
+            }
+        };
+
+        let mut transformer = Transformer::new(delta);
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let new_region = if region.is_caret() {
+                SelRegion::caret(transformer.transform(region.start, true))
+            } else {
+                let min = transformer.transform(region.min(), false);
+                let max = transformer.transform(region.max(), true);
+                if region.start > region.end {
+                    SelRegion::new(max, min, None)
+                } else {
+                    SelRegion::new(min, max, None)
+                }
+            };
+            new_selection.add_region(new_region);
+        }
+        cursor.mode = CursorMode::Insert(new_selection);
+        cursor.horiz = None;
+    }
+
+    fn duplicate_line(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        down: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut edits = Vec::new();
+        let mut lines = HashSet::new();
+        for region in selection.regions() {
+            if !region.is_caret() {
+                let content = buffer.slice_to_cow(region.min()..region.max());
+                edits.push((Selection::caret(region.max()), content.to_string()));
+                continue;
+            }
+
+            let line = buffer.line_of_offset(region.start);
+            if !lines.insert(line) {
+                continue;
+            }
+            let content = buffer.line_content(line).to_string();
+            let has_line_ending = content.ends_with('\n');
+            // Inserting the copy before the line when moving down lets the
+            // carets drift into the copy, while inserting it after the line
+            // keeps them on the upper one.
+            if down {
+                let start = buffer.offset_of_line(line);
+                let content = if has_line_ending {
+                    content
+                } else {
+                    content + "\n"
+                };
+                edits.push((Selection::caret(start), content));
+            } else {
+                let end = buffer.offset_of_line(line + 1);
+                let content = if has_line_ending {
+                    content
+                } else {
+                    format!("\n{content}")
+                };
+                edits.push((Selection::caret(end), content));
+            }
+        }
+
+        let edits = edits
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::InsertChars);
+
+        let mut transformer = Transformer::new(&delta);
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let (start, end) = if region.is_caret() {
+                let offset = transformer.transform(region.start, down);
+                (offset, offset)
+            } else if down {
+                (
+                    transformer.transform(region.max(), false),
+                    transformer.transform(region.max(), true),
+                )
+            } else {
+                (
+                    transformer.transform(region.min(), true),
+                    transformer.transform(region.max(), false),
+                )
+            };
+            let new_region = if region.start > region.end {
+                SelRegion::new(end, start, None)
+            } else {
+                SelRegion::new(start, end, None)
+            };
+            new_selection.add_region(new_region);
+        }
+        cursor.update_selection(buffer, new_selection);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Deletes from every caret to the end of its line, returning the removed
+    /// text. A caret already at the line end is left alone.
+    pub fn delete_to_line_end(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Option<RegisterData>) {
+        Self::delete_to_line_boundary(cursor, buffer, true)
+    }
+
+    /// Deletes from the first non blank character of the line to every caret,
+    /// or from the line start when the caret is within the indentation. The
+    /// removed text is returned. A caret at the line start is left alone.
+    pub fn delete_to_line_start(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Option<RegisterData>) {
+        Self::delete_to_line_boundary(cursor, buffer, false)
+    }
+
+    fn delete_to_line_boundary(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        to_end: bool,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Option<RegisterData>) {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } | CursorMode::Insert(_) => {
+                cursor.edit_selection(buffer)
+            }
+        };
+
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let (start, end) = if to_end {
+                let start = region.min();
+                (start, buffer.offset_line_end(start, true).max(region.max()))
+            } else {
+                let end = region.max();
+                let line = buffer.line_of_offset(end);
+                let non_blank = buffer.first_non_blank_character_on_line(line);
+                let start = if non_blank < region.min() {
+                    non_blank
+                } else {
+                    buffer.offset_of_line(line)
+                };
+                (start.min(region.min()), end)
+            };
+            if start < end {
+                new_selection.add_region(SelRegion::new(start, end, None));
+            }
+        }
+        if new_selection.is_empty() {
+            return (vec![], None);
+        }
+
+        let content = new_selection
+            .regions()
+            .iter()
+            .map(|region| buffer.slice_to_cow(region.min()..region.max()))
+            .join("\n");
+        let (delta, inval_lines) =
+            buffer.edit(&[(&new_selection, "")], EditType::Delete);
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                let offset = selection.min_offset();
+                let offset = offset.min(buffer.offset_line_end(offset, false));
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Insert(_) => {
+                let mut carets = Selection::new();
+                for region in selection.regions() {
+                    carets.add_region(SelRegion::caret(region.min()));
+                }
+                cursor.mode = CursorMode::Insert(carets);
+            }
+        }
+
+        let data = RegisterData {
+            content,
+            mode: VisualMode::Normal,
+        };
+        (vec![(delta, inval_lines)], Some(data))
+    }
+
+    /// Deletes every line touched by the cursor, returning the removed text
+    /// as linewise register data.
+    pub fn delete_line(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> (Vec<(RopeDelta, InvalLines)>, Option<RegisterData>) {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } | CursorMode::Insert(_) => {
+                cursor.edit_selection(buffer)
+            }
+        };
+
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for region in selection.regions() {
+            let start_line = buffer.line_of_offset(region.min());
+            let mut end_line = buffer.line_of_offset(region.max());
+            if end_line > start_line
+                && buffer.offset_of_line(end_line) == region.max()
+            {
+                end_line -= 1;
+            }
+            let start = buffer.offset_of_line(start_line);
+            let end = buffer.offset_of_line(end_line + 1);
+            match ranges.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => ranges.push((start, end)),
+            }
+        }
+
+        let mut content = String::new();
+        for (start, end) in ranges.iter() {
+            content.push_str(&buffer.slice_to_cow(*start..*end));
+        }
+        if content.is_empty() {
+            return (Vec::new(), None);
+        }
+        if !content.ends_with('\n') {
+            content.push('\n');
+            // The last line has no line ending of its own, so remove the one
+            // of the line before it instead.
+            if let Some(last) = ranges.last_mut() {
+                if last.0 > 0 {
+                    last.0 -= 1;
+                }
+            }
+        }
+
+        let mut delete_selection = Selection::new();
+        for (start, end) in ranges {
+            delete_selection.add_region(SelRegion::new(start, end, None));
+        }
+        let (delta, inval_lines) =
+            buffer.edit(&[(&delete_selection, "")], EditType::Delete);
+        let selection =
+            delete_selection.apply_delta(&delta, true, InsertDrift::Default);
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                let offset = selection.min_offset();
+                let mut line = buffer.line_of_offset(offset);
+                if line > 0
+                    && offset == buffer.len()
+                    && offset == buffer.offset_of_line(line)
+                {
+                    line -= 1;
+                }
+                let offset = buffer.first_non_blank_character_on_line(line);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(selection);
+            }
+        }
+
+        let data = RegisterData {
+            content,
+            mode: VisualMode::Linewise,
+        };
+        (vec![(delta, inval_lines)], Some(data))
+    }
+
+    /// Capitalizes the first letter of every word and lowercases the rest.
+    fn title_case(s: &str) -> String {
+        let mut result = String::with_capacity(s.len());
+        let mut in_word = false;
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            // An apostrophe between letters, like in "don't", keeps the word
+            let is_word = get_word_property(c) == WordProperty::Other
+                || (in_word
+                    && matches!(c, '\'' | '\u{2019}')
+                    && matches!(
+                        chars.peek(),
+                        Some(next) if get_word_property(*next) == WordProperty::Other
+                    ));
+            if is_word && !in_word {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+            in_word = is_word;
+        }
+        result
+    }
+
+    /// Capitalizes the first letter of every sentence and lowercases the
+    /// rest. A sentence ends at a `.`, `!` or `?` followed by whitespace.
+    fn sentence_case(s: &str) -> String {
+        let mut result = String::with_capacity(s.len());
+        let mut sentence_start = true;
+        let mut prev = None;
+        for c in s.chars() {
+            if c.is_whitespace() && matches!(prev, Some('.' | '!' | '?')) {
+                sentence_start = true;
+            }
+            if sentence_start && c.is_alphanumeric() {
+                result.extend(c.to_uppercase());
+                sentence_start = false;
+            } else {
+                result.extend(c.to_lowercase());
+            }
+            prev = Some(c);
+        }
+        result
+    }
+
+    /// Replaces the text of every region, or of the word under a caret, with
+    /// the result of `change`.
+    fn change_case(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        change: fn(&str) -> String,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut ranges = Vec::new();
+        for region in selection.regions() {
+            let (start, end) = if region.is_caret() {
+                buffer.select_word(region.start)
+            } else {
+                (region.min(), region.max())
+            };
+            let len = change(&buffer.slice_to_cow(start..end)).len();
+            ranges.push((start, end, len));
+        }
+
+        // Several carets can be in the same word, so overlapping ranges are
+        // changed as one span.
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        for (start, end, _) in ranges.iter().sorted_by_key(|(start, _, _)| *start) {
+            match spans.last_mut() {
+                Some(last) if *start < last.1 || (*start, *end) == *last => {
+                    last.1 = last.1.max(*end);
+                }
+                _ => spans.push((*start, *end)),
+            }
+        }
+        let edits = spans
+            .iter()
+            .map(|(start, end)| {
+                let content = change(&buffer.slice_to_cow(*start..*end));
+                (Selection::region(*start, *end), content)
+            })
+            .collect::<Vec<_>>();
+        let changed_spans = spans
+            .iter()
+            .zip(&edits)
+            .map(|((start, end), (_, content))| {
+                (*start, *end, content.len() == end - start)
+            })
+            .collect::<Vec<_>>();
+
+        let edits = edits
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        let mut transformer = Transformer::new(&delta);
+        let mut new_selection = Selection::new();
+        for (region, (start, end, len)) in selection.regions().iter().zip(ranges) {
+            // Offsets within a span keep their place if it kept its length
+            let new_start =
+                match changed_spans.iter().find(|(span_start, span_end, _)| {
+                    *span_start <= start && end <= *span_end
+                }) {
+                    Some((span_start, _, true)) => {
+                        transformer.transform(*span_start, false) + start
+                            - span_start
                     }
-                    CursorMode::Insert(_) => {
+                    Some((span_start, _, false)) => {
+                        transformer.transform(*span_start, false)
+                    }
+                    None => transformer.transform(start, false),
+                };
+            let new_region = if region.is_caret() {
+                // Keep the caret where it was within the word, unless the
+                // word changed its length.
+                let offset = if len == end - start {
+                    new_start + region.start - start
+                } else {
+                    new_start + len
+                };
+                SelRegion::caret(offset)
+            } else if region.start > region.end {
+                SelRegion::new(new_start + len, new_start, None)
+            } else {
+                SelRegion::new(new_start, new_start + len, None)
+            };
+            new_selection.add_region(new_region);
+        }
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.mode = CursorMode::Normal(new_selection.min_offset());
+            }
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Replaces the character under every caret with `c`, staying in the
+    /// current mode. The caret stays on the new character, or moves to the
+    /// start of the new line when `c` is a newline. Carets at the end of a
+    /// line are left alone.
+    pub fn replace_char(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        c: char,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let offsets = match &cursor.mode {
+            CursorMode::Normal(offset) => vec![*offset],
+            CursorMode::Visual { .. } => return vec![],
+            CursorMode::Insert(selection) => selection
+                .regions()
+                .iter()
+                .filter(|region| region.is_caret())
+                .map(|region| region.start)
+                .collect(),
+        };
+
+        let mut selection = Selection::new();
+        for offset in offsets {
+            let line_end = buffer.offset_line_end(offset, true);
+            let end = buffer.next_grapheme_offset(offset, 1, line_end);
+            if end > offset {
+                selection.add_region(SelRegion::new(offset, end, None));
+            }
+        }
+        if selection.is_empty() {
+            return vec![];
+        }
+
+        let content = c.to_string();
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, &content)], EditType::Other);
+        let shift = if c == '\n' { 1 } else { 0 };
+        let mut transformer = Transformer::new(&delta);
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let offset = transformer.transform(region.start, false) + shift;
+            new_selection.add_region(SelRegion::caret(offset));
+        }
+        match cursor.mode {
+            CursorMode::Normal(_) => {
+                cursor.mode = CursorMode::Normal(new_selection.min_offset());
+            }
+            _ => cursor.mode = CursorMode::Insert(new_selection),
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Joins the lines from the first to the last region of an insert mode
+    /// selection into one, with `sep` between the joined lines, and leaves a
+    /// single caret at its end. With `trim_whitespace`, the whitespace around
+    /// each newline is removed too. Nothing happens unless the regions span
+    /// several lines.
+    pub fn join_regions_with(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        sep: &str,
+        trim_whitespace: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (start_line, end_line) = match &cursor.mode {
+            CursorMode::Insert(selection) if selection.len() > 1 => (
+                buffer.line_of_offset(selection.min_offset()),
+                buffer.line_of_offset(selection.max_offset()),
+            ),
+            _ => return vec![],
+        };
+        if start_line == end_line {
+            return vec![];
+        }
+
+        let start = buffer.offset_of_line(start_line);
+        let end = buffer.line_end_offset(end_line, true);
+        let joined = (start_line..end_line + 1)
+            .map(|line| {
+                let line_start = buffer.offset_of_line(line);
+                let line_end = buffer.line_end_offset(line, true);
+                let content = buffer.slice_to_cow(line_start..line_end);
+                let content: &str = &content;
+                let content = match (trim_whitespace, line) {
+                    (false, _) => content,
+                    (true, line) if line == start_line => content.trim_end(),
+                    (true, line) if line == end_line => content.trim_start(),
+                    (true, _) => content.trim(),
+                };
+                content.to_string()
+            })
+            .join(sep);
+
+        let (delta, inval_lines) = buffer.edit(
+            &[(&Selection::region(start, end), &joined)],
+            EditType::Other,
+        );
+        cursor.mode = CursorMode::Insert(Selection::caret(start + joined.len()));
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Puts a commented out copy of the line of every caret below it, keeping
+    /// its indentation, while the cursor stays on the original line. Without
+    /// a `comment_token` the line is duplicated as it is.
+    pub fn copy_line_as_comment(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        comment_token: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        if comment_token.is_empty() {
+            return Self::duplicate_line(cursor, buffer, false);
+        }
+
+        let mut edits = Vec::new();
+        for line in Self::selected_lines(cursor, buffer) {
+            let indent = buffer.indent_on_line(line);
+            let content = buffer.line_content(line);
+            let content = content.trim_end_matches(['\r', '\n']);
+            let copy =
+                format!("{indent}{comment_token} {}", &content[indent.len()..]);
+            let end = buffer.offset_of_line(line + 1);
+            let copy = if buffer.line_end_offset(line, true) == end {
+                format!("\n{copy}")
+            } else {
+                format!("{copy}\n")
+            };
+            edits.push((Selection::caret(end), copy));
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::InsertChars,
+        );
+
+        let mut transformer = Transformer::new(&delta);
+        match &mut cursor.mode {
+            CursorMode::Normal(offset) => {
+                *offset = transformer.transform(*offset, false);
+            }
+            CursorMode::Visual { start, end, .. } => {
+                *start = transformer.transform(*start, false);
+                *end = transformer.transform(*end, false);
+            }
+            CursorMode::Insert(selection) => {
+                *selection =
+                    selection.apply_delta(&delta, false, InsertDrift::Default);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Comments out the line of every caret and opens an empty line below it,
+    /// indented like it, with the cursor in insert mode on the new lines.
+    /// Without a `comment_token` a line is only opened.
+    pub fn comment_and_open_below(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        comment_token: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let mut edits = Vec::new();
+        let mut carets = Vec::new();
+        for line in Self::selected_lines(cursor, buffer) {
+            let indent = buffer.indent_on_line(line);
+            let line_end = buffer.line_end_offset(line, true);
+            let nonblank = buffer.offset_of_line(line) + indent.len();
+            if !comment_token.is_empty() && nonblank < line_end {
+                edits
+                    .push((Selection::caret(nonblank), format!("{comment_token} ")));
+            }
+            edits.push((Selection::caret(line_end), format!("\n{indent}")));
+            carets.push((line_end, indent.len() + 1));
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::InsertNewline,
+        );
+
+        let mut transformer = Transformer::new(&delta);
+        let mut selection = Selection::new();
+        for (offset, shift) in carets {
+            selection.add_region(SelRegion::caret(
+                transformer.transform(offset, false) + shift,
+            ));
+        }
+        cursor.mode = CursorMode::Insert(selection);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Wraps the lines covered by the selection in a block, putting `open`
+    /// above and `close` below them at their outer indentation and indenting
+    /// them by one unit. With a syntax, a block opened on the last line is
+    /// taken along up to its closing bracket. The cursor ends up in insert
+    /// mode on the `open` line, inside its first empty pair of brackets or
+    /// else before its trailing opening bracket.
+    pub fn wrap_in_block(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        open: &str,
+        close: &str,
+        syntax: Option<&Syntax>,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let lines = Self::selected_lines(cursor, buffer);
+        let (start_line, mut end_line) = match (lines.first(), lines.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return vec![],
+        };
+        if let Some(syntax) = syntax {
+            let mut line = start_line;
+            while line <= end_line {
+                let line_content = buffer.line_content(line);
+                let trimmed = line_content.trim_end();
+                if let Some(c) = trimmed.chars().last() {
+                    if matching_pair_direction(c) == Some(true) {
+                        let offset = buffer.offset_of_line(line) + trimmed.len()
+                            - c.len_utf8();
+                        if let Some(matching) = syntax.find_matching_pair(offset) {
+                            end_line = end_line.max(buffer.line_of_offset(matching));
+                        }
+                    }
+                }
+                line += 1;
+            }
+        }
+
+        let start = buffer.offset_of_line(start_line);
+        let end = buffer.line_end_offset(end_line, true);
+        let content = buffer.slice_to_cow(start..end).to_string();
+        let line_ending = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let outer = (start_line..=end_line)
+            .map(|line| buffer.indent_on_line(line))
+            .zip(content.lines())
+            .filter(|(indent, line)| indent.len() < line.len())
+            .map(|(indent, _)| indent)
+            .min_by_key(|indent| indent.len())
+            .unwrap_or_else(|| buffer.indent_on_line(start_line));
+        let indent = buffer.indent_unit();
+
+        let mut wrapped = format!("{outer}{open}{line_ending}");
+        for line in content.lines() {
+            if !line.trim().is_empty() {
+                let (line_indent, rest) = line.split_at(outer.len().min(line.len()));
+                wrapped.push_str(line_indent);
+                wrapped.push_str(indent);
+                wrapped.push_str(rest);
+            } else {
+                wrapped.push_str(line);
+            }
+            wrapped.push_str(line_ending);
+        }
+        wrapped.push_str(&format!("{outer}{close}"));
+
+        let (delta, inval_lines) = buffer.edit(
+            &[(&Selection::region(start, end), &wrapped)],
+            EditType::Other,
+        );
+
+        let caret = ["()", "[]", "{}"]
+            .iter()
+            .filter_map(|pair| open.find(pair).map(|i| i + 1))
+            .min()
+            .or_else(|| {
+                let trimmed = open.trim_end();
+                trimmed
+                    .chars()
+                    .last()
+                    .filter(|c| matching_pair_direction(*c) == Some(true))
+                    .map(|c| trimmed.len() - c.len_utf8())
+            })
+            .unwrap_or(open.len());
+        cursor.mode =
+            CursorMode::Insert(Selection::caret(start + outer.len() + caret));
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Wraps every selected span in the block comment delimiters `open` and
+    /// `close`, or unwraps them when all of the spans are wrapped already. A
+    /// caret stands for the content of its line. Comments inside a span are
+    /// wrapped along with it.
+    pub fn toggle_block_comment(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        open: &str,
+        close: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        // The spans with their surrounding whitespace left out
+        let mut spans = Vec::new();
+        for region in selection.regions() {
+            let (start, end) = if region.is_caret() {
+                let line = buffer.line_of_offset(region.start);
+                (
+                    buffer.offset_of_line(line),
+                    buffer.line_end_offset(line, true),
+                )
+            } else {
+                (region.min(), region.max())
+            };
+            let content = buffer.slice_to_cow(start..end);
+            let trimmed = content.trim_start();
+            let start = start + content.len() - trimmed.len();
+            let end = start + trimmed.trim_end().len();
+            if end > start && !spans.contains(&(start, end)) {
+                spans.push((start, end));
+            }
+        }
+        if spans.is_empty() {
+            return vec![];
+        }
+
+        let is_wrapped = |start: usize, end: usize| {
+            let content = buffer.slice_to_cow(start..end);
+            content.len() >= open.len() + close.len()
+                && content.starts_with(open)
+                && content.ends_with(close)
+        };
+        let mut edits = Vec::new();
+        if spans.iter().all(|(start, end)| is_wrapped(*start, *end)) {
+            for (start, end) in spans {
+                let mut open_end = start + open.len();
+                let mut close_start = end - close.len();
+                if open_end < close_start
+                    && buffer.char_at_offset(open_end) == Some(' ')
+                {
+                    open_end += 1;
+                }
+                if open_end < close_start
+                    && buffer.char_at_offset(close_start - 1) == Some(' ')
+                {
+                    close_start -= 1;
+                }
+                edits.push((Selection::region(start, open_end), String::new()));
+                edits.push((Selection::region(close_start, end), String::new()));
+            }
+        } else {
+            for (start, end) in spans {
+                edits.push((Selection::caret(start), format!("{open} ")));
+                edits.push((Selection::caret(end), format!(" {close}")));
+            }
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+        Self::apply_indent_delta(cursor, &delta);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Swaps the text of the two regions of an insert mode selection in a
+    /// single edit, keeping them selected. Nothing happens unless there are
+    /// exactly two regions.
+    pub fn exchange_regions(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (first, second) = match &cursor.mode {
+            CursorMode::Insert(selection) if selection.len() == 2 => {
+                (selection.regions()[0], selection.regions()[1])
+            }
+            _ => return vec![],
+        };
+        let first_text = buffer.slice_to_cow(first.min()..first.max()).to_string();
+        let second_text =
+            buffer.slice_to_cow(second.min()..second.max()).to_string();
+        if first_text == second_text {
+            return vec![];
+        }
+
+        let first_selection = Selection::region(first.min(), first.max());
+        let second_selection = Selection::region(second.min(), second.max());
+        let (delta, inval_lines) = buffer.edit(
+            &[
+                (&first_selection, second_text.as_str()),
+                (&second_selection, first_text.as_str()),
+            ],
+            EditType::Other,
+        );
+
+        let mut transformer = Transformer::new(&delta);
+        let first_start = transformer.transform(first.min(), false);
+        let second_start = transformer.transform(second.min(), false);
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(
+            first_start,
+            first_start + second_text.len(),
+            None,
+        ));
+        selection.add_region(SelRegion::new(
+            second_start,
+            second_start + first_text.len(),
+            None,
+        ));
+        cursor.mode = CursorMode::Insert(selection);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Splits every selected comma separated list on a single line into one
+    /// item per line, indented like the line the list starts on, or joins the
+    /// items of a selection spanning several lines into a single line list.
+    pub fn toggle_list_layout(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(_) => return vec![],
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut edits = Vec::new();
+        for region in selection.regions() {
+            if region.is_caret() {
+                continue;
+            }
+            let text = buffer.slice_to_cow(region.min()..region.max());
+            let body = text.trim_end_matches(['\r', '\n']);
+            let line_ending = &text[body.len()..];
+            let leading = &body[..body.len() - body.trim_start().len()];
+            let content = if body.contains('\n') {
+                body.lines()
+                    .map(|line| line.trim().trim_end_matches(','))
+                    .filter(|item| !item.is_empty())
+                    .join(", ")
+            } else {
+                let indent =
+                    buffer.indent_on_line(buffer.line_of_offset(region.min()));
+                body.split(',')
+                    .map(|item| item.trim())
+                    .filter(|item| !item.is_empty())
+                    .join(&format!(",\n{indent}"))
+            };
+            let content = format!("{leading}{content}{line_ending}");
+            if content != text {
+                edits.push((Selection::region(region.min(), region.max()), content));
+            }
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+
+        let mut transformer = Transformer::new(&delta);
+        cursor.mode = match &cursor.mode {
+            CursorMode::Insert(_) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    new_selection.add_region(SelRegion::new(
+                        transformer.transform(region.min(), false),
+                        transformer.transform(region.max(), true),
+                        None,
+                    ));
+                }
+                CursorMode::Insert(new_selection)
+            }
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => CursorMode::Normal(
+                transformer.transform(selection.min_offset(), false),
+            ),
+        };
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Moves the text of each region into the next one, with the last
+    /// region's text going to the first, or the other way round when not
+    /// `forward`. Each region ends up selecting its new text.
+    pub fn rotate_selections(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        forward: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let regions = match &cursor.mode {
+            CursorMode::Insert(selection) if selection.len() > 1 => {
+                selection.regions().to_vec()
+            }
+            _ => return vec![],
+        };
+        let mut texts = regions
+            .iter()
+            .map(|region| {
+                buffer.slice_to_cow(region.min()..region.max()).to_string()
+            })
+            .collect::<Vec<_>>();
+        if forward {
+            texts.rotate_right(1);
+        } else {
+            texts.rotate_left(1);
+        }
+
+        let selections = regions
+            .iter()
+            .map(|region| Selection::region(region.min(), region.max()))
+            .collect::<Vec<_>>();
+        let edits = selections
+            .iter()
+            .zip(texts.iter())
+            .map(|(selection, text)| (selection, text.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        let mut transformer = Transformer::new(&delta);
+        let mut selection = Selection::new();
+        for (region, text) in regions.iter().zip(texts.iter()) {
+            let start = transformer.transform(region.min(), false);
+            selection.add_region(SelRegion::new(start, start + text.len(), None));
+        }
+        cursor.mode = CursorMode::Insert(selection);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Splits every region spanning several lines into one region per line,
+    /// each covering that line's part of it. A region ending at the very start
+    /// of a line doesn't get an empty region on that line.
+    pub fn split_selection_into_lines(cursor: &mut Cursor, buffer: &Buffer) {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(_) => return,
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            let start_line = buffer.line_of_offset(region.min());
+            let end_line = buffer.line_of_offset(region.max());
+            if start_line == end_line {
+                new_selection.add_region(*region);
+                continue;
+            }
+            for line in start_line..end_line + 1 {
+                let line_start = buffer.offset_of_line(line);
+                if line == end_line && line_start == region.max() {
+                    break;
+                }
+                let start = region.min().max(line_start);
+                let end = region.max().min(buffer.line_end_offset(line, true));
+                new_selection.add_region(SelRegion::new(start, end, None));
+            }
+        }
+        cursor.mode = CursorMode::Insert(new_selection);
+        cursor.horiz = None;
+    }
+
+    /// Puts a caret at the end of every line the selection spans, in insert
+    /// mode. A blockwise selection gets its carets at the right edge of the
+    /// block instead.
+    pub fn carets_at_line_ends(cursor: &mut Cursor, buffer: &Buffer) {
+        let mut selection = Selection::new();
+        if let CursorMode::Visual {
+            mode: VisualMode::Blockwise,
+            ..
+        } = cursor.mode
+        {
+            for region in cursor.edit_selection(buffer).regions() {
+                selection.add_region(SelRegion::caret(region.max()));
+            }
+        } else {
+            for line in Self::selected_lines(cursor, buffer) {
+                selection.add_region(SelRegion::caret(
+                    buffer.line_end_offset(line, true),
+                ));
+            }
+        }
+        cursor.mode = CursorMode::Insert(selection);
+        cursor.horiz = None;
+    }
+
+    /// Reduces a multi region insert selection to its primary region, the one
+    /// inserted last. Returns whether there were other regions to drop.
+    pub fn collapse_to_primary(cursor: &mut Cursor) -> bool {
+        if let CursorMode::Insert(selection) = &cursor.mode {
+            if selection.len() > 1 {
+                if let Some(region) = selection.last_inserted() {
+                    cursor.mode = CursorMode::Insert(Selection::region(
+                        region.start,
+                        region.end,
+                    ));
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// The range and text of the word the caret at `offset` is in or right
+    /// after, or `None` when it is on whitespace or punctuation.
+    pub fn word_under_cursor(
+        buffer: &Buffer,
+        offset: usize,
+    ) -> Option<(Range<usize>, String)> {
+        let is_word = |c: Option<char>| {
+            matches!(c.map(get_word_property), Some(WordProperty::Other))
+        };
+        let mut word_cursor = WordCursor::new(buffer.text(), offset);
+        let after = word_cursor.inner.peek_next_codepoint();
+        let before = word_cursor.inner.prev_codepoint();
+        if !is_word(after) && !is_word(before) {
+            return None;
+        }
+
+        let start = WordCursor::new(buffer.text(), offset).prev_code_boundary();
+        let end = WordCursor::new(buffer.text(), offset).next_code_boundary();
+        Some((start..end, buffer.slice_to_cow(start..end).to_string()))
+    }
+
+    /// Grows every region to the boundaries of the words it starts and ends
+    /// in, so that partially selected words become fully selected. A caret
+    /// selects the word under it.
+    pub fn expand_regions_to_words(cursor: &mut Cursor, buffer: &Buffer) {
+        let is_word = |c: Option<char>| {
+            matches!(c.map(get_word_property), Some(WordProperty::Other))
+        };
+        let expand = |start: usize, end: usize| {
+            if start == end {
+                return buffer.select_word(start);
+            }
+            let mut word_cursor = WordCursor::new(buffer.text(), start);
+            let start = if is_word(word_cursor.inner.peek_next_codepoint()) {
+                word_cursor.prev_code_boundary()
+            } else {
+                start
+            };
+            let mut word_cursor = WordCursor::new(buffer.text(), end);
+            let before_end = word_cursor.inner.prev_codepoint();
+            word_cursor.inner.set(end);
+            let end = if is_word(before_end) {
+                word_cursor.next_code_boundary()
+            } else {
+                end
+            };
+            (start, end)
+        };
+
+        match &mut cursor.mode {
+            CursorMode::Normal(_) => {}
+            CursorMode::Visual {
+                start,
+                end,
+                mode: VisualMode::Normal,
+            } => {
+                // The visual selection includes the character under its end
+                let (min, max) = (
+                    *start.min(end),
+                    buffer.next_grapheme_offset(*start.max(end), 1, buffer.len()),
+                );
+                let (min, max) = expand(min, max);
+                let max = buffer.prev_grapheme_offset(max, 1, min);
+                if start <= end {
+                    *start = min;
+                    *end = max;
+                } else {
+                    *start = max;
+                    *end = min;
+                }
+            }
+            CursorMode::Visual { .. } => {}
+            CursorMode::Insert(selection) => {
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let (start, end) = expand(region.min(), region.max());
+                    let region = if region.start <= region.end {
+                        SelRegion::new(start, end, None)
+                    } else {
+                        SelRegion::new(end, start, None)
+                    };
+                    new_selection.add_region(region);
+                }
+                *selection = new_selection;
+            }
+        }
+        cursor.horiz = None;
+    }
+
+    /// Selects everything in `within`, or in the whole buffer, that the
+    /// selection doesn't cover. Overlapping and adjacent regions are merged
+    /// first and carets are ignored. Nothing changes when there is nothing
+    /// left to select.
+    pub fn invert_selection(
+        cursor: &mut Cursor,
+        buffer: &Buffer,
+        within: Option<Range<usize>>,
+    ) {
+        let within = within.unwrap_or(0..buffer.len());
+        let (start, end) =
+            (within.start.min(buffer.len()), within.end.min(buffer.len()));
+        let selection = match &cursor.mode {
+            CursorMode::Normal(_) => return,
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut new_selection = Selection::new();
+        let mut offset = start;
+        for region in selection.regions() {
+            if region.is_caret() || region.max() <= offset {
+                continue;
+            }
+            if region.min() >= end {
+                break;
+            }
+            if region.min() > offset {
+                new_selection.add_region(SelRegion::new(offset, region.min(), None));
+            }
+            offset = region.max();
+        }
+        if offset < end {
+            new_selection.add_region(SelRegion::new(offset, end, None));
+        }
+        if !new_selection.is_empty() {
+            cursor.mode = CursorMode::Insert(new_selection);
+        }
+    }
+
+    /// The lines covered by the selection in order, or the cursor line in
+    /// normal mode.
+    fn selected_lines(cursor: &Cursor, buffer: &Buffer) -> Vec<usize> {
+        let mut lines = match &cursor.mode {
+            CursorMode::Normal(offset) => vec![buffer.line_of_offset(*offset)],
+            CursorMode::Visual { start, end, .. } => (buffer
+                .line_of_offset(*start.min(end))
+                ..buffer.line_of_offset(*start.max(end)) + 1)
+                .collect(),
+            CursorMode::Insert(selection) => selection
+                .regions()
+                .iter()
+                .flat_map(|region| {
+                    buffer.line_of_offset(region.min())
+                        ..buffer.line_of_offset(region.max()) + 1
+                })
+                .collect(),
+        };
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+
+    /// Rewrites the indentation of the lines covered by the selection with
+    /// tabs or with spaces, a tab being `tab_width` columns wide. Whitespace
+    /// after the indentation is left alone, and indentation that isn't a
+    /// multiple of `tab_width` keeps the remainder as spaces when converting
+    /// to tabs.
+    pub fn retab(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        use_tabs: bool,
+        tab_width: usize,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let tab_width = tab_width.max(1);
+        let mut edits = Vec::new();
+        for line in Self::selected_lines(cursor, buffer) {
+            let line_start = buffer.offset_of_line(line);
+            let indent = buffer.indent_on_line(line);
+            let width = indent.chars().fold(0, |width, c| {
+                if c == '\t' {
+                    (width / tab_width + 1) * tab_width
+                } else {
+                    width + 1
+                }
+            });
+            let new_indent = if use_tabs {
+                "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+            } else {
+                " ".repeat(width)
+            };
+            if new_indent != indent {
+                edits.push((
+                    Selection::region(line_start, line_start + indent.len()),
+                    new_indent,
+                ));
+            }
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+        Self::apply_indent_delta(cursor, &delta);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Rewraps the lines covered by the selection, or the paragraph of the
+    /// caret when nothing is selected, so that no line is longer than
+    /// `width` graphemes, unless a single word is. Blank lines separate
+    /// paragraphs, and every paragraph keeps the indentation of its first
+    /// line.
+    pub fn reflow(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        width: usize,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let has_selection = match &cursor.mode {
+            CursorMode::Normal(_) => false,
+            CursorMode::Visual { .. } => true,
+            CursorMode::Insert(selection) => {
+                selection.regions().iter().any(|r| !r.is_caret())
+            }
+        };
+        let is_blank = |line: usize| buffer.line_content(line).trim().is_empty();
+        let lines = if has_selection {
+            Self::selected_lines(cursor, buffer)
+        } else {
+            let mut lines = Vec::new();
+            for line in Self::selected_lines(cursor, buffer) {
+                if !is_blank(line) {
+                    let (first, last) = Self::paragraph_lines(buffer, line, true);
+                    lines.extend(first..last + 1);
+                }
+            }
+            lines.sort_unstable();
+            lines.dedup();
+            lines
+        };
+
+        // Runs of consecutive non blank lines
+        let mut paragraphs: Vec<(usize, usize)> = Vec::new();
+        for line in lines.into_iter().filter(|line| !is_blank(*line)) {
+            match paragraphs.last_mut() {
+                Some((_, last)) if *last + 1 == line => *last = line,
+                _ => paragraphs.push((line, line)),
+            }
+        }
+
+        let graphemes = |start: usize, end: usize| {
+            let mut count = 0;
+            let mut offset = start;
+            while offset < end {
+                offset = buffer.next_grapheme_offset(offset, 1, end);
+                count += 1;
+            }
+            count
+        };
+        let mut edits = Vec::new();
+        for (first, last) in paragraphs {
+            let indent = buffer.indent_on_line(first);
+            let indent_width = graphemes(
+                buffer.offset_of_line(first),
+                buffer.offset_of_line(first) + indent.len(),
+            );
+
+            let start = buffer.offset_of_line(first);
+            let end = buffer.line_end_offset(last, true);
+            let mut words = Vec::new();
+            let mut word_start = None;
+            for (i, c) in buffer.slice_to_cow(start..end).char_indices() {
+                let is_space = matches!(
+                    get_word_property(c),
+                    WordProperty::Space | WordProperty::Cr | WordProperty::Lf
+                );
+                match (is_space, word_start) {
+                    (true, Some(word)) => {
+                        words.push((start + word, start + i));
+                        word_start = None;
+                    }
+                    (false, None) => word_start = Some(i),
+                    _ => {}
+                }
+            }
+            if let Some(word) = word_start {
+                words.push((start + word, end));
+            }
+
+            let mut content = indent.clone();
+            let mut line_width = indent_width;
+            for (i, (word_start, word_end)) in words.into_iter().enumerate() {
+                let word_width = graphemes(word_start, word_end);
+                if i > 0 {
+                    if line_width + 1 + word_width > width {
+                        content.push('\n');
+                        content.push_str(&indent);
+                        line_width = indent_width;
+                    } else {
+                        content.push(' ');
+                        line_width += 1;
+                    }
+                }
+                content.push_str(&buffer.slice_to_cow(word_start..word_end));
+                line_width += word_width;
+            }
+            if buffer.slice_to_cow(start..end) != content {
+                edits.push((Selection::region(start, end), content));
+            }
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+
+        let mut transformer = Transformer::new(&delta);
+        cursor.mode = match &cursor.mode {
+            CursorMode::Insert(_) => {
+                let mut selection = Selection::new();
+                for (edit, _) in &edits {
+                    let offset = transformer.transform(edit.max_offset(), true);
+                    selection.add_region(SelRegion::caret(offset));
+                }
+                CursorMode::Insert(selection)
+            }
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                let offset = transformer.transform(edits[0].0.min_offset(), false);
+                let line = buffer.line_of_offset(offset);
+                CursorMode::Normal(buffer.first_non_blank_character_on_line(line))
+            }
+        };
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Lines up the first `c` of every line covered by the selection by
+    /// inserting spaces before it. Lines without `c` are left alone.
+    pub fn align_on(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        c: char,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        if let CursorMode::Normal(_) = cursor.mode {
+            return vec![];
+        }
+        let lines = Self::selected_lines(cursor, buffer);
+
+        let found = lines
+            .into_iter()
+            .filter_map(|line| {
+                let line_start = buffer.offset_of_line(line);
+                let content = buffer
+                    .slice_to_cow(line_start..buffer.line_end_offset(line, true));
+                let index = content.find(c)?;
+                Some((line_start + index, content[..index].chars().count()))
+            })
+            .collect::<Vec<_>>();
+        let col = match found.iter().map(|(_, col)| *col).max() {
+            Some(col) => col,
+            None => return vec![],
+        };
+
+        let edits = found
+            .into_iter()
+            .filter(|(_, c)| *c < col)
+            .map(|(offset, c)| (Selection::caret(offset), " ".repeat(col - c)))
+            .collect::<Vec<_>>();
+        if edits.is_empty() {
+            return vec![];
+        }
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, content.as_str()))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+        cursor.apply_delta(&delta);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Sorts the lines covered by the selection, replacing them in a single
+    /// edit. Nothing happens when the selection is within one line.
+    pub fn sort_lines(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        order: SortOrder,
+        case_insensitive: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        Self::sort_lines_with_options(cursor, buffer, order, case_insensitive, false)
+    }
+
+    /// Like [`Editor::sort_lines`], but with `unique` only the first of the
+    /// lines comparing equal after sorting is kept, like vim's `sort u`.
+    pub fn sort_lines_with_options(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        order: SortOrder,
+        case_insensitive: bool,
+        unique: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let (start, end) = match &cursor.mode {
+            CursorMode::Normal(_) => return vec![],
+            CursorMode::Visual { start, end, .. } => {
+                (*start.min(end), *start.max(end))
+            }
+            CursorMode::Insert(selection) => {
+                let start = selection.min_offset();
+                let end = selection.max_offset();
+                // A selection ending at the start of a line doesn't cover it
+                if end > start
+                    && buffer.offset_of_line(buffer.line_of_offset(end)) == end
+                {
+                    (start, end - 1)
+                } else {
+                    (start, end)
+                }
+            }
+        };
+        let start_line = buffer.line_of_offset(start);
+        let end_line = buffer.line_of_offset(end);
+        if start_line == end_line {
+            return vec![];
+        }
+
+        let start = buffer.offset_of_line(start_line);
+        let end = buffer.offset_of_line(end_line + 1);
+        let content = buffer.slice_to_cow(start..end);
+        let line_ending = if content.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut lines = content.lines().collect::<Vec<_>>();
+
+        let key = |line: &str| {
+            if case_insensitive {
+                line.to_lowercase()
+            } else {
+                line.to_string()
+            }
+        };
+        match order {
+            SortOrder::Ascending => lines.sort_by_key(|line| key(line)),
+            SortOrder::Descending => {
+                lines.sort_by_key(|line| std::cmp::Reverse(key(line)))
+            }
+            SortOrder::Numeric => lines.sort_by(|a, b| {
+                leading_number(a)
+                    .partial_cmp(&leading_number(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .then_with(|| key(a).cmp(&key(b)))
+            }),
+        }
+        if unique {
+            lines.dedup_by(|a, b| key(a) == key(b));
+        }
+
+        let mut sorted = lines.join(line_ending);
+        if content.ends_with('\n') {
+            sorted.push_str(line_ending);
+        }
+        let (delta, inval_lines) = buffer.edit(
+            &[(&Selection::region(start, end), &sorted)],
+            EditType::Other,
+        );
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                let offset = buffer.first_non_blank_character_on_line(start_line);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Insert(_) => {
+                let end = start + sorted.len();
+                cursor.mode = CursorMode::Insert(Selection::region(start, end));
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Removes trailing spaces and tabs from the lines covered by the
+    /// selection, or from every line when `whole_buffer` is set, in a single
+    /// edit. Carets inside the removed whitespace end up at the new line end.
+    pub fn trim_trailing_whitespace(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        whole_buffer: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let lines = if whole_buffer {
+            (0..buffer.last_line() + 1).collect()
+        } else {
+            Self::selected_lines(cursor, buffer)
+        };
+
+        let mut selection = Selection::new();
+        for line in lines {
+            let start = buffer.offset_of_line(line);
+            let end = buffer.line_end_offset(line, true);
+            let content = buffer.slice_to_cow(start..end);
+            let trimmed = content.trim_end_matches([' ', '\t']);
+            if trimmed.len() < content.len() {
+                selection.add_region(SelRegion::new(
+                    start + trimmed.len(),
+                    end,
+                    None,
+                ));
+            }
+        }
+        if selection.is_empty() {
+            return vec![];
+        }
+
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, "")], EditType::Delete);
+        cursor.apply_delta(&delta);
+        if let CursorMode::Normal(offset) = cursor.mode {
+            let offset = buffer.offset_line_end(offset, false).min(offset);
+            cursor.mode = CursorMode::Normal(offset);
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Removes the blank lines at the end of the buffer, leaving a single
+    /// line ending after the last line with content when `keep_newline` is
+    /// set, or none at all otherwise. The cursor is moved out of the removed
+    /// text.
+    pub fn trim_final_newlines(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        keep_newline: bool,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let mut line = buffer.last_line();
+        while line > 0 && buffer.line_content(line).trim().is_empty() {
+            line -= 1;
+        }
+        let content = buffer.line_content(line);
+        let (end, ending) = if content.trim().is_empty() {
+            (0, "")
+        } else if !keep_newline {
+            (buffer.line_end_offset(line, true), "")
+        } else if content.ends_with("\r\n") {
+            (buffer.line_end_offset(line, true), "\r\n")
+        } else {
+            (buffer.line_end_offset(line, true), "\n")
+        };
+        if buffer.slice_to_cow(end..buffer.len()) == ending {
+            return vec![];
+        }
+
+        let selection = Selection::region(end, buffer.len());
+        let (delta, inval_lines) =
+            buffer.edit(&[(&selection, ending)], EditType::Delete);
+        cursor.apply_delta(&delta);
+        if let CursorMode::Normal(offset) = cursor.mode {
+            let offset = offset.min(end);
+            let offset = buffer.offset_line_end(offset, false).min(offset);
+            cursor.mode = CursorMode::Normal(offset);
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Rewrites every line ending of the buffer to `style` in a single edit.
+    /// The cursor moves along with the `\r` added or removed before it.
+    pub fn normalize_line_endings(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        style: LineEnding,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let mut edits = Vec::new();
+        for line in 0..=buffer.last_line() {
+            let content = buffer.line_content(line);
+            if !content.ends_with('\n') {
+                continue;
+            }
+            let newline = buffer.offset_of_line(line) + content.len() - 1;
+            match style {
+                LineEnding::Lf if content.ends_with("\r\n") => {
+                    edits.push((Selection::region(newline - 1, newline), ""));
+                }
+                LineEnding::CrLf if !content.ends_with("\r\n") => {
+                    edits.push((Selection::caret(newline), "\r"));
+                }
+                _ => {}
+            }
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let (delta, inval_lines) = buffer.edit(
+            &edits
+                .iter()
+                .map(|(selection, content)| (selection, *content))
+                .collect::<Vec<_>>(),
+            EditType::Other,
+        );
+        cursor.apply_delta(&delta);
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Wraps every region of the selection with `open` and `close`. A caret
+    /// wraps the word under it. The selection keeps covering the text
+    /// inside the delimiters.
+    pub fn surround(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        open: &str,
+        close: &str,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let ranges = selection
+            .regions()
+            .iter()
+            .map(|region| {
+                if region.is_caret() {
+                    buffer.select_word(region.start)
+                } else {
+                    (region.min(), region.max())
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Overlapping ranges, like those of carets in the same word, are
+        // wrapped only once.
+        let mut spans: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in ranges.iter().sorted_by_key(|(start, _)| *start) {
+            match spans.last_mut() {
+                Some(last) if *start < last.1 || (*start, *end) == *last => {
+                    last.1 = last.1.max(*end);
+                }
+                _ => spans.push((*start, *end)),
+            }
+        }
+
+        let mut inserts: Vec<(usize, String)> = Vec::new();
+        for (start, end) in spans.iter().copied() {
+            for (offset, s) in [(start, open), (end, close)] {
+                // Inserts at the same offset have to be a single edit
+                match inserts.last_mut() {
+                    Some((last, content)) if *last == offset => content.push_str(s),
+                    _ => inserts.push((offset, s.to_string())),
+                }
+            }
+        }
+
+        let edits = inserts
+            .iter()
+            .map(|(offset, content)| (Selection::caret(*offset), content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        // The closing delimiters got shifted by everything inserted before
+        // them, including their own opening one.
+        let mut transformer = Transformer::new(&delta);
+        let first_open = spans
+            .first()
+            .map(|(start, _)| transformer.transform(*start, false))
+            .unwrap_or(0);
+        let mut new_selection = Selection::new();
+        for (region, (start, end)) in selection.regions().iter().zip(ranges) {
+            // The text inside a span is untouched, so offsets within it only
+            // move along with the span's opening delimiter.
+            let span_start = spans
+                .iter()
+                .find(|(span_start, span_end)| {
+                    *span_start <= start && end <= *span_end
+                })
+                .map(|(span_start, _)| *span_start)
+                .unwrap_or(start);
+            let shift = transformer.transform(span_start, false) + open.len();
+            let new_start = shift + start - span_start;
+            let new_end = shift + end - span_start;
+            let new_region = if region.is_caret() {
+                SelRegion::caret(new_start + region.start - start)
+            } else if region.start > region.end {
+                SelRegion::new(new_end, new_start, None)
+            } else {
+                SelRegion::new(new_start, new_end, None)
+            };
+            new_selection.add_region(new_region);
+        }
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.mode = CursorMode::Normal(first_open);
+            }
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Adds `delta` to the number each caret is on, or the next one after it
+    /// on the same line. Decimal and `0x` prefixed hexadecimal numbers are
+    /// supported, and the width of zero padded numbers is kept.
+    pub fn increment_number(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        delta: i64,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => return vec![],
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut ranges: Vec<(usize, usize, String)> = Vec::new();
+        for region in selection.regions() {
+            let line = buffer.line_of_offset(region.end);
+            let line_start = buffer.offset_of_line(line);
+            // An insert mode caret is on the character before it
+            let offset = if cursor.is_insert() {
+                region.end.saturating_sub(1).max(line_start)
+            } else {
+                region.end
+            };
+            let line_content = buffer.line_content(line);
+            let (start, end) = match find_number(&line_content, offset - line_start)
+            {
+                Some(range) => range,
+                None => continue,
+            };
+            let (start, end) = (line_start + start, line_start + end);
+            if ranges.iter().any(|(s, _, _)| *s == start) {
+                continue;
+            }
+            if let Some(number) =
+                add_to_number(&buffer.slice_to_cow(start..end), delta)
+            {
+                ranges.push((start, end, number));
+            }
+        }
+        if ranges.is_empty() {
+            return vec![];
+        }
+
+        let edits = ranges
+            .iter()
+            .map(|(start, end, number)| {
+                (Selection::region(*start, *end), number.as_str())
+            })
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        // The carets end up at the end of their numbers
+        let mut transformer = Transformer::new(&delta);
+        let mut new_selection = Selection::new();
+        for (start, _, number) in &ranges {
+            let end = transformer.transform(*start, false) + number.len();
+            new_selection.add_region(SelRegion::caret(end));
+        }
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.mode = CursorMode::Normal(new_selection.min_offset() - 1);
+            }
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// The lines from the first line of the cursor up to the line the last
+    /// one gets joined with. At least two lines are joined.
+    fn join_line_range(cursor: &Cursor, buffer: &Buffer) -> Option<(usize, usize)> {
+        let (start, end) = match &cursor.mode {
+            CursorMode::Normal(offset) => (*offset, *offset),
+            CursorMode::Visual { start, end, .. } => {
+                (*start.min(end), *start.max(end))
+            }
+            CursorMode::Insert(selection) => {
+                (selection.min_offset(), selection.max_offset())
+            }
+        };
+        let mut last_line = buffer.last_line();
+        // The empty line after a trailing newline is past the end
+        if last_line > 0 && buffer.offset_of_line(last_line) == buffer.len() {
+            last_line -= 1;
+        }
+        let start_line = buffer.line_of_offset(start);
+        if start_line >= last_line {
+            return None;
+        }
+        let end_line = buffer
+            .line_of_offset(end)
+            .max(start_line + 1)
+            .min(last_line);
+        Some((start_line, end_line))
+    }
+
+    /// Replaces the text of every selected region with its transformation by
+    /// `kind`, in a single edit. If any region can't be transformed, nothing
+    /// is changed and the error is returned.
+    pub fn transform_selection(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        kind: TransformKind,
+    ) -> Result<Vec<(RopeDelta, InvalLines)>, TransformError> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(_) => return Ok(vec![]),
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut edits = Vec::new();
+        for region in selection.regions() {
+            if region.is_caret() {
+                continue;
+            }
+            let (start, end) = (region.min(), region.max());
+            let content = kind.apply(&buffer.slice_to_cow(start..end))?;
+            edits.push((Selection::region(start, end), content));
+        }
+        if edits.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let lens = edits
+            .iter()
+            .map(|(_, content)| content.len())
+            .collect::<Vec<_>>();
+        let edits = edits
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.mode = CursorMode::Normal(selection.min_offset());
+            }
+            CursorMode::Insert(_) => {
+                let mut transformer = Transformer::new(&delta);
+                let mut lens = lens.into_iter();
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let start = transformer.transform(region.min(), false);
+                    let new_region = if region.is_caret() {
+                        SelRegion::caret(start)
+                    } else {
+                        let end = start + lens.next().unwrap_or(0);
+                        if region.start > region.end {
+                            SelRegion::new(end, start, None)
+                        } else {
+                            SelRegion::new(start, end, None)
+                        }
+                    };
+                    new_selection.add_region(new_region);
+                }
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+        }
+
+        Ok(vec![(delta, inval_lines)])
+    }
+
+    /// Reverses the order of the graphemes within every selected region, in
+    /// a single edit. Carets are left alone.
+    fn reverse_regions(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(_) => return vec![],
+            CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut edits = Vec::new();
+        for region in selection.regions() {
+            if region.is_caret() {
+                continue;
+            }
+            let (start, end) = (region.min(), region.max());
+            let mut graphemes = Vec::new();
+            let mut offset = start;
+            while offset < end {
+                let next = buffer.next_grapheme_offset(offset, 1, end);
+                graphemes.push(buffer.slice_to_cow(offset..next));
+                offset = next;
+            }
+            let content: String =
+                graphemes.iter().rev().map(|g| g.as_ref()).collect();
+            edits.push((Selection::region(start, end), content));
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let edits = edits
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                cursor.mode = CursorMode::Normal(selection.min_offset());
+            }
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(selection);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    /// Swaps the graphemes on both sides of every caret and moves the caret
+    /// past them. At the end of a line the two graphemes before the caret are
+    /// swapped instead.
+    fn transpose_characters(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        let selection = match &cursor.mode {
+            CursorMode::Normal(offset) => Selection::caret(*offset),
+            CursorMode::Visual { .. } => return vec![],
+            CursorMode::Insert(selection) => selection.clone(),
+        };
+
+        let mut edits = Vec::new();
+        let mut new_selection = Selection::new();
+        let mut last_end = 0;
+        for region in selection.regions() {
+            let offset = region.end;
+            let line = buffer.line_of_offset(offset);
+            let line_start = buffer.offset_of_line(line);
+            let line_end = buffer.line_end_offset(line, true);
+
+            let range = if !region.is_caret() || offset == line_start {
+                None
+            } else if offset >= line_end {
+                let mid = buffer.prev_grapheme_offset(line_end, 1, line_start);
+                let start = buffer.prev_grapheme_offset(mid, 1, line_start);
+                if start < mid {
+                    Some((start, mid, line_end))
+                } else {
+                    None
+                }
+            } else {
+                let start = buffer.prev_grapheme_offset(offset, 1, line_start);
+                let end = buffer.next_grapheme_offset(offset, 1, line_end);
+                Some((start, offset, end))
+            };
+
+            match range {
+                Some((start, mid, end)) if edits.is_empty() || start >= last_end => {
+                    let content = format!(
+                        "{}{}",
+                        buffer.slice_to_cow(mid..end),
+                        buffer.slice_to_cow(start..mid)
+                    );
+                    edits.push((Selection::region(start, end), content));
+                    new_selection.add_region(SelRegion::caret(end));
+                    last_end = end;
+                }
+                _ => new_selection.add_region(*region),
+            }
+        }
+        if edits.is_empty() {
+            return vec![];
+        }
+
+        let edits = edits
+            .iter()
+            .map(|(selection, content)| (selection, content.as_str()))
+            .collect::<Vec<_>>();
+        let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+
+        match cursor.mode {
+            CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                let offset =
+                    buffer.prev_grapheme_offset(new_selection.min_offset(), 1, 0);
+                cursor.mode = CursorMode::Normal(offset);
+            }
+            CursorMode::Insert(_) => {
+                cursor.mode = CursorMode::Insert(new_selection);
+            }
+        }
+
+        vec![(delta, inval_lines)]
+    }
+
+    fn do_outdent(
+        buffer: &mut Buffer,
+        selection: Selection,
+    ) -> (RopeDelta, InvalLines) {
+        let indent = buffer.indent_unit();
+        let mut edits = Vec::new();
+
+        let mut lines = HashSet::new();
+        for region in selection.regions() {
+            let start_line = buffer.line_of_offset(region.min());
+            let mut end_line = buffer.line_of_offset(region.max());
+            if end_line > start_line {
+                let end_line_start = buffer.offset_of_line(end_line);
+                if end_line_start == region.max() {
+                    end_line -= 1;
+                }
+            }
+            for line in start_line..=end_line {
+                if lines.contains(&line) {
+                    continue;
+                }
+                lines.insert(line);
+                let line_content = buffer.line_content(line);
+                if line_content == "\n" || line_content == "\r\n" {
+                    continue;
+                }
+                let nonblank = buffer.first_non_blank_character_on_line(line);
+                if let Some(edit) =
+                    crate::indent::create_outdent(buffer, nonblank, indent)
+                {
+                    edits.push(edit);
+                }
+            }
+        }
+
+        buffer.edit(&edits, EditType::Delete)
+    }
+
+    /// Makes the last recorded edit again at the current cursor position.
+    /// Recorded inserts put the typed text before the cursor.
+    pub fn repeat_last_edit<T: Clipboard>(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        syntax: Option<&Syntax>,
+        clipboard: &mut T,
+        modal: bool,
+        register: &mut Register,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        match cursor.last_edit.clone() {
+            Some(RepeatableEdit::Command(cmd)) => Self::do_edit(
+                cursor, buffer, &cmd, syntax, clipboard, modal, register,
+            ),
+            Some(RepeatableEdit::Paste { data, reindent }) => {
+                Self::do_paste(cursor, buffer, &data, reindent)
+            }
+            Some(RepeatableEdit::Insert(text)) => {
+                let selection = match &cursor.mode {
+                    CursorMode::Normal(offset) => Selection::caret(*offset),
+                    CursorMode::Visual { .. } => cursor.edit_selection(buffer),
+                    CursorMode::Insert(selection) => selection.clone(),
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, text.as_str())], EditType::Other);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        let offset = buffer.prev_grapheme_offset(
+                            selection.min_offset(),
+                            1,
+                            0,
+                        );
+                        cursor.mode = CursorMode::Normal(offset);
+                    }
+                    CursorMode::Insert(_) => {
+                        cursor.mode = CursorMode::Insert(selection);
+                    }
+                }
+                cursor.insert_session = false;
+                vec![(delta, inval_lines)]
+            }
+            None => vec![],
+        }
+    }
+
+    pub fn do_edit<T: Clipboard>(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        cmd: &EditCommand,
+        syntax: Option<&Syntax>,
+        clipboard: &mut T,
+        modal: bool,
+        register: &mut Register,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        Self::do_edit_with_options(
+            cursor,
+            buffer,
+            cmd,
+            syntax,
+            clipboard,
+            modal,
+            register,
+            &EditOptions::default(),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn do_edit_with_options<T: Clipboard>(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        cmd: &EditCommand,
+        syntax: Option<&Syntax>,
+        clipboard: &mut T,
+        modal: bool,
+        register: &mut Register,
+        options: &EditOptions,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        use crate::command::EditCommand::*;
+
+        cursor.insert_session = false;
+        let rev = buffer.rev();
+        let cursor_before = cursor.mode.clone();
+        let linked = !cursor.linked_regions.is_empty()
+            && matches!(
+                cmd,
+                DeleteBackward
+                    | DeleteForward
+                    | DeleteWordBackward
+                    | DeleteWordForward
+            );
+        let deltas = if linked {
+            Self::edit_linked(cursor, buffer, |cursor, buffer| {
+                Self::execute_edit(
+                    cursor, buffer, cmd, syntax, clipboard, modal, register, options,
+                )
+            })
+        } else {
+            let deltas = Self::execute_edit(
+                cursor, buffer, cmd, syntax, clipboard, modal, register, options,
+            );
+            for (delta, _) in &deltas {
+                cursor.apply_delta_to_linked(delta);
+            }
+            deltas
+        };
+        cursor.pending_pairs = None;
+        if !deltas.is_empty() && !matches!(cmd, Undo | Redo) {
+            buffer.set_edit_cursors(rev, cursor_before, cursor.mode.clone());
+        }
+        // Pastes record themselves along with what got pasted
+        if !deltas.is_empty() && !matches!(cmd, Undo | Redo | Paste | ClipboardPaste)
+        {
+            cursor.last_edit = Some(RepeatableEdit::Command(cmd.clone()));
+        }
+        deltas
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_edit<T: Clipboard>(
+        cursor: &mut Cursor,
+        buffer: &mut Buffer,
+        cmd: &EditCommand,
+        syntax: Option<&Syntax>,
+        clipboard: &mut T,
+        modal: bool,
+        register: &mut Register,
+        options: &EditOptions,
+    ) -> Vec<(RopeDelta, InvalLines)> {
+        use crate::command::EditCommand::*;
+        match cmd {
+            MoveLineUp => {
+                let mut deltas = Vec::new();
+                if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
+                    for region in selection.regions_mut() {
+                        let start_line = buffer.line_of_offset(region.min());
+                        if start_line > 0 {
+                            let previous_line_len =
+                                buffer.line_content(start_line - 1).len();
+
+                            let end_line = buffer.line_of_offset(region.max());
+                            let start = buffer.offset_of_line(start_line);
+                            let end = buffer.offset_of_line(end_line + 1);
+                            let content =
+                                buffer.slice_to_cow(start..end).to_string();
+                            let (delta, inval_lines) = buffer.edit(
+                                &[
+                                    (&Selection::region(start, end), ""),
+                                    (
+                                        &Selection::caret(
+                                            buffer.offset_of_line(start_line - 1),
+                                        ),
+                                        &content,
+                                    ),
+                                ],
+                                EditType::InsertChars,
+                            );
+                            deltas.push((delta, inval_lines));
+                            region.start -= previous_line_len;
+                            region.end -= previous_line_len;
+                        }
+                    }
+                    cursor.mode = CursorMode::Insert(selection);
+                }
+                deltas
+            }
+            MoveLineDown => {
+                let mut deltas = Vec::new();
+                if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
+                    // Regions sharing a line are moved together as one block.
+                    let mut blocks: Vec<(usize, usize)> = Vec::new();
+                    let mut region_blocks = Vec::new();
+                    for region in selection.regions() {
+                        let start_line = buffer.line_of_offset(region.min());
+                        let end_line = buffer.line_of_offset(region.max());
+                        match blocks.last_mut() {
+                            Some(block) if start_line <= block.1 => {
+                                block.1 = block.1.max(end_line);
+                            }
+                            _ => blocks.push((start_line, end_line)),
+                        }
+                        region_blocks.push(blocks.len() - 1);
+                    }
+
+                    let last_line = buffer.last_line();
+                    let touches_last_line = blocks.iter().any(|(_, end_line)| {
+                        *end_line >= last_line
+                            || buffer.line_content(end_line + 1).is_empty()
+                    });
+                    if touches_last_line {
+                        return deltas;
+                    }
+
+                    let mut shifts = vec![0; blocks.len()];
+                    for (i, (start_line, end_line)) in
+                        blocks.iter().enumerate().rev()
+                    {
+                        let start = buffer.offset_of_line(*start_line);
+                        let end = buffer.offset_of_line(end_line + 1);
+                        let next_line =
+                            buffer.line_content(end_line + 1).to_string();
+                        let content = buffer.slice_to_cow(start..end).to_string();
+
+                        // The next line may be the last one of the buffer, which
+                        // has no line ending to carry over to the moved lines.
+                        let (new_content, shift) = if next_line.ends_with('\n') {
+                            (format!("{next_line}{content}"), next_line.len())
+                        } else {
+                            let content = content
+                                .strip_suffix('\n')
+                                .unwrap_or(&content)
+                                .to_string();
+                            (format!("{next_line}\n{content}"), next_line.len() + 1)
+                        };
+
+                        let (delta, inval_lines) = buffer.edit(
+                            &[(
+                                &Selection::region(start, end + next_line.len()),
+                                new_content.as_str(),
+                            )],
+                            EditType::InsertChars,
+                        );
+                        deltas.push((delta, inval_lines));
+                        shifts[i] = shift;
+                    }
+
+                    for (region, block) in
+                        selection.regions_mut().iter_mut().zip(region_blocks)
+                    {
+                        region.start += shifts[block];
+                        region.end += shifts[block];
+                    }
+                    cursor.mode = CursorMode::Insert(selection);
+                }
+                deltas
+            }
+            DuplicateLineUp => Self::duplicate_line(cursor, buffer, false),
+            DuplicateLineDown => Self::duplicate_line(cursor, buffer, true),
+            SortLines => {
+                Self::sort_lines(cursor, buffer, SortOrder::Ascending, false)
+            }
+            SortLinesDescending => {
+                Self::sort_lines(cursor, buffer, SortOrder::Descending, false)
+            }
+            SortLinesNumeric => {
+                Self::sort_lines(cursor, buffer, SortOrder::Numeric, false)
+            }
+            SortLinesCaseInsensitive => {
+                Self::sort_lines(cursor, buffer, SortOrder::Ascending, true)
+            }
+            SortLinesUnique => Self::sort_lines_with_options(
+                cursor,
+                buffer,
+                SortOrder::Ascending,
+                false,
+                true,
+            ),
+            SortLinesUniqueCaseInsensitive => Self::sort_lines_with_options(
+                cursor,
+                buffer,
+                SortOrder::Ascending,
+                true,
+                true,
+            ),
+            IncrementNumber => Self::increment_number(cursor, buffer, 1),
+            DecrementNumber => Self::increment_number(cursor, buffer, -1),
+            UppercaseSelection => {
+                Self::change_case(cursor, buffer, |s| s.to_uppercase())
+            }
+            LowercaseSelection => {
+                Self::change_case(cursor, buffer, |s| s.to_lowercase())
+            }
+            ToggleCaseSelection => Self::change_case(cursor, buffer, |s| {
+                s.chars()
+                    .flat_map(|c| {
+                        if c.is_uppercase() {
+                            c.to_lowercase().collect::<Vec<_>>()
+                        } else {
+                            c.to_uppercase().collect::<Vec<_>>()
+                        }
+                    })
+                    .collect()
+            }),
+            TitleCaseSelection => {
+                Self::change_case(cursor, buffer, Self::title_case)
+            }
+            SentenceCaseSelection => {
+                Self::change_case(cursor, buffer, Self::sentence_case)
+            }
+            TransposeCharacters => Self::transpose_characters(cursor, buffer),
+            ReverseRegions => Self::reverse_regions(cursor, buffer),
+            DeleteToLineEnd => {
+                let (deltas, data) = Self::delete_to_line_end(cursor, buffer);
+                if let Some(data) = data {
+                    register.add_delete(data);
+                }
+                deltas
+            }
+            DeleteToLineStart => {
+                let (deltas, data) = Self::delete_to_line_start(cursor, buffer);
+                if let Some(data) = data {
+                    register.add_delete(data);
+                }
+                deltas
+            }
+            ExchangeRegions => Self::exchange_regions(cursor, buffer),
+            RotateSelectionsForward => Self::rotate_selections(cursor, buffer, true),
+            RotateSelectionsBackward => {
+                Self::rotate_selections(cursor, buffer, false)
+            }
+            ToggleListLayout => Self::toggle_list_layout(cursor, buffer),
+            RetabSelectionToSpaces => {
+                Self::retab(cursor, buffer, false, options.tab_width)
+            }
+            RetabSelectionToTabs => {
+                Self::retab(cursor, buffer, true, options.tab_width)
+            }
+            TrimFinalNewlines => Self::trim_final_newlines(cursor, buffer, true),
+            TrimTrailingWhitespace => {
+                let has_selection = match &cursor.mode {
+                    CursorMode::Normal(_) => false,
+                    CursorMode::Visual { .. } => true,
+                    CursorMode::Insert(selection) => {
+                        selection.regions().iter().any(|r| !r.is_caret())
+                    }
+                };
+                Self::trim_trailing_whitespace(cursor, buffer, !has_selection)
+            }
+            DeleteLine => Self::delete_line_to_register(
+                cursor,
+                buffer,
+                register,
+                RegisterSelector::Unnamed,
+            ),
+            InsertNewLine => match cursor.mode.clone() {
+                CursorMode::Normal(offset) => Self::insert_new_line(
+                    buffer,
+                    cursor,
+                    Selection::caret(offset),
+                    &options.auto_pairs,
+                ),
+                CursorMode::Insert(selection) => Self::insert_new_line(
+                    buffer,
+                    cursor,
+                    selection,
+                    &options.auto_pairs,
+                ),
+                CursorMode::Visual {
+                    start: _,
+                    end: _,
+                    mode: _,
+                } => {
+                    vec![]
+                }
+            },
+            InsertTab => Self::tab(cursor, buffer),
+            InsertBackTab => Self::back_tab(cursor, buffer),
+            IndentLine => Self::shift_line_right(cursor, buffer),
+            JoinLines => {
+                let (start_line, end_line) =
+                    match Self::join_line_range(cursor, buffer) {
+                        Some(range) => range,
+                        None => return vec![],
+                    };
+                let start = buffer.offset_of_line(start_line);
+
+                let mut edits = Vec::new();
+                for line in start_line..end_line {
+                    let line_start = buffer.offset_of_line(line);
+                    let line_end = buffer.line_end_offset(line, true);
+                    let next_start = buffer.offset_of_line(line + 1);
+                    let next_end = buffer.line_end_offset(line + 1, true);
+                    let nonblank =
+                        buffer.first_non_blank_character_on_line(line + 1);
+
+                    let content = buffer.slice_to_cow(line_start..line_end);
+                    // Don't add a space if the current line already ends with
+                    // whitespace or if there is nothing to join on either side.
+                    let separator = if content.is_empty()
+                        || content.ends_with(char::is_whitespace)
+                        || nonblank >= next_end
+                        || next_start == next_end
+                    {
+                        ""
+                    } else {
+                        " "
+                    };
+                    edits.push((Selection::region(line_end, nonblank), separator));
+                }
+
+                let join_offset = edits
+                    .last()
+                    .map(|(selection, _)| selection.min_offset())
+                    .unwrap_or(start);
+                let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+                let selection = Selection::caret(join_offset).apply_delta(
+                    &delta,
+                    false,
+                    InsertDrift::Default,
+                );
+                match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.mode = CursorMode::Normal(selection.min_offset());
+                    }
+                    CursorMode::Insert(_) => {
+                        cursor.mode = CursorMode::Insert(selection);
+                    }
+                }
+                vec![(delta, inval_lines)]
+            }
+            JoinLinesRaw => {
+                let (start_line, end_line) =
+                    match Self::join_line_range(cursor, buffer) {
+                        Some(range) => range,
+                        None => return vec![],
+                    };
+
+                let mut edits = Vec::new();
+                for line in start_line..end_line {
+                    let line_end = buffer.line_end_offset(line, true);
+                    let next_start = buffer.offset_of_line(line + 1);
+                    edits.push((Selection::region(line_end, next_start), ""));
+                }
+
+                let join_offset = edits[0].0.min_offset();
+                let (delta, inval_lines) = buffer.edit(&edits, EditType::Other);
+                let offset = Transformer::new(&delta).transform(join_offset, false);
+                match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.mode = CursorMode::Normal(offset);
+                    }
+                    CursorMode::Insert(_) => {
+                        cursor.mode = CursorMode::Insert(Selection::caret(offset));
+                    }
+                }
+                vec![(delta, inval_lines)]
+            }
+            OutdentLine => Self::shift_line_left(cursor, buffer),
+            ToggleLineComment => {
+                let comment_token = match syntax.map(|s| s.language.comment_token())
+                {
+                    Some(token) if !token.is_empty() => token,
+                    _ => return vec![],
+                };
+                let mut lines = HashSet::new();
+                let selection = cursor.edit_selection(buffer);
+                let mut had_comment = true;
+                let mut smallest_indent = usize::MAX;
+                for region in selection.regions() {
+                    let mut line = buffer.line_of_offset(region.min());
+                    let end_line = buffer.line_of_offset(region.max());
+                    let end_line_offset = buffer.offset_of_line(end_line);
+                    let end = if end_line > line && region.max() == end_line_offset {
+                        end_line_offset
+                    } else {
+                        buffer.offset_of_line(end_line + 1)
+                    };
+                    let start = buffer.offset_of_line(line);
+                    for content in buffer.text().lines(start..end) {
+                        let trimmed_content = content.trim_start();
+                        if trimmed_content.is_empty() {
+                            line += 1;
+                            continue;
+                        }
+                        let indent = content.len() - trimmed_content.len();
+                        if indent < smallest_indent {
+                            smallest_indent = indent;
+                        }
+                        if !trimmed_content.starts_with(&comment_token) {
+                            had_comment = false;
+                            lines.insert((line, indent, 0));
+                        } else {
+                            let had_space_after_comment =
+                                trimmed_content.chars().nth(comment_token.len())
+                                    == Some(' ');
+                            lines.insert((
+                                line,
+                                indent,
+                                comment_token.len()
+                                    + if had_space_after_comment { 1 } else { 0 },
+                            ));
+                        }
+                        line += 1;
+                    }
+                }
+
+                let (delta, inval_lines) = if had_comment {
+                    let mut selection = Selection::new();
+                    for (line, indent, len) in lines.iter() {
+                        let start = buffer.offset_of_line(*line) + indent;
+                        selection.add_region(SelRegion::new(
+                            start,
+                            start + len,
+                            None,
+                        ))
+                    }
+                    buffer.edit(&[(&selection, "")], EditType::Delete)
+                } else {
+                    let mut selection = Selection::new();
+                    for (line, _, _) in lines.iter() {
+                        let start = buffer.offset_of_line(*line) + smallest_indent;
+                        selection.add_region(SelRegion::new(start, start, None))
+                    }
+                    buffer.edit(
+                        &[(&selection, &format!("{comment_token} "))],
+                        EditType::InsertChars,
+                    )
+                };
+                cursor.apply_delta(&delta);
+                vec![(delta, inval_lines)]
+            }
+            ToggleBlockComment => {
+                let (open, close) =
+                    match syntax.map(|s| s.language.block_comment_tokens()) {
+                        Some((open, close)) if !open.is_empty() => (open, close),
+                        _ => return vec![],
+                    };
+                Self::toggle_block_comment(cursor, buffer, open, close)
+            }
+            CopyLineAsComment => {
+                let comment_token =
+                    syntax.map(|s| s.language.comment_token()).unwrap_or("");
+                Self::copy_line_as_comment(cursor, buffer, comment_token)
+            }
+            CommentAndOpenBelow => {
+                let comment_token =
+                    syntax.map(|s| s.language.comment_token()).unwrap_or("");
+                Self::comment_and_open_below(cursor, buffer, comment_token)
+            }
+            Undo => {
+                if let Some((delta, inval_lines, cursor_mode)) = buffer.do_undo() {
+                    if let Some(cursor_mode) = cursor_mode {
+                        if modal {
+                            cursor.mode = CursorMode::Normal(cursor_mode.offset());
+                        } else {
+                            cursor.mode = cursor_mode;
+                        }
+                    } else if let Some(new_cursor) =
+                        get_first_selection_after(cursor, buffer, &delta)
+                    {
+                        *cursor = new_cursor
+                    } else {
+                        cursor.apply_delta(&delta);
+                    }
+                    vec![(delta, inval_lines)]
+                } else {
+                    vec![]
+                }
+            }
+            Redo => {
+                if let Some((delta, inval_lines, cursor_mode)) = buffer.do_redo() {
+                    if let Some(cursor_mode) = cursor_mode {
+                        if modal {
+                            cursor.mode = CursorMode::Normal(cursor_mode.offset());
+                        } else {
+                            cursor.mode = cursor_mode;
+                        }
+                    } else if let Some(new_cursor) =
+                        get_first_selection_after(cursor, buffer, &delta)
+                    {
+                        *cursor = new_cursor
+                    } else {
+                        cursor.apply_delta(&delta);
+                    }
+                    vec![(delta, inval_lines)]
+                } else {
+                    vec![]
+                }
+            }
+            ClipboardCopy => {
+                let data = cursor.yank(buffer);
+                clipboard.put_string(data.content);
+
+                match &cursor.mode {
+                    CursorMode::Visual {
+                        start,
+                        end,
+                        mode: _,
+                    } => {
+                        let offset = *start.min(end);
+                        let offset =
+                            buffer.offset_line_end(offset, false).min(offset);
+                        cursor.mode = CursorMode::Normal(offset);
+                    }
+                    CursorMode::Normal(_) | CursorMode::Insert(_) => {}
+                }
+                vec![]
+            }
+            ClipboardCut => {
+                let data = cursor.yank(buffer);
+                clipboard.put_string(data.content);
+
+                let selection =
+                    if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
+                        for region in selection.regions_mut() {
+                            if region.is_caret() {
+                                let line = buffer.line_of_offset(region.start);
+                                let start = buffer.offset_of_line(line);
+                                let end = buffer.offset_of_line(line + 1);
+                                region.start = start;
+                                region.end = end;
+                            }
+                        }
+                        selection
+                    } else {
+                        cursor.edit_selection(buffer)
+                    };
+
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.update_selection(buffer, selection);
+                vec![(delta, inval_lines)]
+            }
+            ClipboardPaste => {
+                if let Some(s) = clipboard.get_string() {
+                    let mode = if s.ends_with('\n') {
+                        VisualMode::Linewise
+                    } else {
+                        VisualMode::Normal
+                    };
+                    let data = RegisterData { content: s, mode };
+                    Self::do_paste(cursor, buffer, &data, false)
+                } else {
+                    vec![]
+                }
+            }
+            Yank => {
+                Self::yank_to_register(
+                    cursor,
+                    buffer,
+                    register,
+                    RegisterSelector::Unnamed,
+                );
+                vec![]
+            }
+            Paste => Self::paste_from_register(
+                cursor,
+                buffer,
+                register,
+                RegisterSelector::Unnamed,
+            ),
+            NewLineAbove => {
+                let offset = cursor.offset();
+                let line = buffer.line_of_offset(offset);
+                let offset = if line > 0 {
+                    buffer.line_end_offset(line - 1, true)
+                } else {
+                    buffer.first_non_blank_character_on_line(line)
+                };
+                let delta = Self::insert_new_line(
+                    buffer,
+                    cursor,
+                    Selection::caret(offset),
+                    &options.auto_pairs,
+                );
+                if line == 0 {
+                    cursor.mode = CursorMode::Insert(Selection::caret(offset));
+                }
+                delta
+            }
+            InsertLineAbove => Self::insert_line(cursor, buffer, false),
+            InsertLineBelow => Self::insert_line(cursor, buffer, true),
+            SplitLine => Self::split_line(cursor, buffer),
+            NewLineBelow => {
+                let offset = cursor.offset();
+                let offset = buffer.offset_line_end(offset, true);
+                Self::insert_new_line(
+                    buffer,
+                    cursor,
+                    Selection::caret(offset),
+                    &options.auto_pairs,
+                )
+            }
+            ToggleOvertype => {
+                cursor.overtype = !cursor.overtype;
+                vec![]
+            }
+            // Backspace only moves back over what was typed over
+            DeleteBackward
+                if cursor.overtype
+                    && matches!(&cursor.mode, CursorMode::Insert(selection)
+                        if selection.regions().iter().all(|r| r.is_caret())) =>
+            {
+                let selection = cursor.edit_selection(buffer);
+                let mut new_selection = Selection::new();
+                for region in selection.regions() {
+                    let offset = buffer.move_left(region.start, Mode::Insert, 1);
+                    new_selection.add_region(SelRegion::caret(offset));
+                }
+                cursor.mode = CursorMode::Insert(new_selection);
+                vec![]
+            }
+            DeleteBackward => {
+                let selection = match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.edit_selection(buffer)
+                    }
+                    CursorMode::Insert(_) => {
+                        let indent = buffer.indent_unit();
+                        let selection = cursor.edit_selection(buffer);
+                        let mut new_selection = Selection::new();
+                        for region in selection.regions() {
+                            let new_region = if region.is_caret() {
+                                let new_end = if indent.starts_with('\t') {
+                                    buffer.move_left(region.end, Mode::Insert, 1)
+                                } else {
+                                    let line = buffer.line_of_offset(region.start);
+                                    let nonblank = buffer
+                                        .first_non_blank_character_on_line(line);
+                                    let (_, col) =
+                                        buffer.offset_to_line_col(region.start);
+                                    let count =
+                                        if region.start <= nonblank && col > 0 {
+                                            let r = col % indent.len();
+                                            if r == 0 {
+                                                indent.len()
+                                            } else {
+                                                r
+                                            }
+                                        } else {
+                                            1
+                                        };
+                                    buffer.move_left(region.end, Mode::Insert, count)
+                                };
+
+                                // Remove the closing character too when the
+                                // caret sits right inside of an empty pair
+                                let prev = buffer.char_at_offset(new_end);
+                                let next = buffer.char_at_offset(region.end);
+                                let close = match prev {
+                                    Some(c @ ('"' | '\'' | '`')) => Some(c),
+                                    Some(c)
+                                        if matching_pair_direction(c)
+                                            == Some(true) =>
+                                    {
+                                        matching_char(c)
+                                    }
+                                    _ => None,
+                                };
+                                let auto_inserted = !options
+                                    .auto_pairs
+                                    .delete_only_auto_inserted_pairs
+                                    || cursor.is_pending_pair(buffer, region.end);
+                                let start = match (prev, close, next) {
+                                    (Some(prev), Some(close), Some(next))
+                                        if close == next
+                                            && auto_inserted
+                                            && new_end + prev.len_utf8()
+                                                == region.end =>
+                                    {
+                                        region.end + close.len_utf8()
+                                    }
+                                    _ => region.start,
+                                };
+                                SelRegion::new(start, new_end, None)
+                            } else {
+                                *region
+                            };
+                            new_selection.add_region(new_region);
+                        }
+
+                        new_selection
+                    }
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.update_selection(buffer, selection);
+                vec![(delta, inval_lines)]
+            }
+            DeleteForward => {
+                let selection = match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.edit_selection(buffer)
+                    }
+                    CursorMode::Insert(_) => {
+                        let selection = cursor.edit_selection(buffer);
+                        let mut new_selection = Selection::new();
+                        for region in selection.regions() {
+                            let new_region = if region.is_caret() {
+                                let new_end =
+                                    buffer.move_right(region.end, Mode::Insert, 1);
+                                SelRegion::new(region.start, new_end, None)
+                            } else {
+                                *region
+                            };
+                            new_selection.add_region(new_region);
+                        }
+                        new_selection
+                    }
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.update_selection(buffer, selection);
+                vec![(delta, inval_lines)]
+            }
+            DeleteWordForward => {
+                let selection = match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.edit_selection(buffer)
+                    }
+                    CursorMode::Insert(_) => {
+                        let mut new_selection = Selection::new();
+                        let selection = cursor.edit_selection(buffer);
+
+                        for region in selection.regions() {
+                            let new_region = if region.is_caret() {
+                                let end =
+                                    buffer.next_word_deletion_boundary(region.end);
+                                SelRegion::new(region.start, end, None)
+                            } else {
+                                *region
+                            };
+                            new_selection.add_region(new_region);
+                        }
+
+                        new_selection
+                    }
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.update_selection(buffer, selection);
+                vec![(delta, inval_lines)]
+            }
+            DeleteWordBackward => {
+                let selection = match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.edit_selection(buffer)
+                    }
+                    CursorMode::Insert(_) => {
                         let mut new_selection = Selection::new();
                         let selection = cursor.edit_selection(buffer);
 
-                        for region in selection.regions() {
-                            let end = buffer.move_word_forward(region.end);
-                            let new_region = SelRegion::new(region.start, end, None);
-                            new_selection.add_region(new_region);
-                        }
+                        for region in selection.regions() {
+                            let new_region = if region.is_caret() {
+                                let end =
+                                    buffer.prev_word_deletion_boundary(region.end);
+                                SelRegion::new(region.start, end, None)
+                            } else {
+                                *region
+                            };
+                            new_selection.add_region(new_region);
+                        }
+
+                        new_selection
+                    }
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.update_selection(buffer, selection);
+                vec![(delta, inval_lines)]
+            }
+            DeleteToBeginningOfLine => {
+                let selection = match cursor.mode {
+                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
+                        cursor.edit_selection(buffer)
+                    }
+                    CursorMode::Insert(_) => {
+                        let selection = cursor.edit_selection(buffer);
+
+                        let mut new_selection = Selection::new();
+                        for region in selection.regions() {
+                            let line = buffer.line_of_offset(region.end);
+                            let end = buffer.offset_of_line(line);
+                            let new_region = SelRegion::new(region.start, end, None);
+                            new_selection.add_region(new_region);
+                        }
+
+                        new_selection
+                    }
+                };
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.update_selection(buffer, selection);
+                vec![(delta, inval_lines)]
+            }
+            ToggleQuotes => Self::toggle_quotes(cursor, buffer, syntax),
+            CollapseToPrimary => {
+                Self::collapse_to_primary(cursor);
+                vec![]
+            }
+            DeleteForwardAndInsert => {
+                let selection = cursor.edit_selection(buffer);
+                let (delta, inval_lines) =
+                    buffer.edit(&[(&selection, "")], EditType::Delete);
+                let selection =
+                    selection.apply_delta(&delta, true, InsertDrift::Default);
+                cursor.mode = CursorMode::Insert(selection);
+                vec![(delta, inval_lines)]
+            }
+            NormalMode => {
+                if !modal {
+                    if let CursorMode::Insert(selection) = &cursor.mode {
+                        match selection.regions().len() {
+                            i if i > 1 => {
+                                if let Some(region) = selection.last_inserted() {
+                                    let new_selection =
+                                        Selection::region(region.start, region.end);
+                                    cursor.mode = CursorMode::Insert(new_selection);
+                                    return vec![];
+                                }
+                            }
+                            i if i == 1 => {
+                                let region = selection.regions()[0];
+                                if !region.is_caret() {
+                                    let new_selection = Selection::caret(region.end);
+                                    cursor.mode = CursorMode::Insert(new_selection);
+                                    return vec![];
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+
+                    return vec![];
+                }
+
+                let offset = match &cursor.mode {
+                    CursorMode::Insert(selection) => {
+                        let offset = selection.min_offset();
+                        buffer.prev_grapheme_offset(
+                            offset,
+                            1,
+                            buffer.offset_of_line(buffer.line_of_offset(offset)),
+                        )
+                    }
+                    CursorMode::Visual { end, .. } => {
+                        buffer.offset_line_end(*end, false).min(*end)
+                    }
+                    CursorMode::Normal(offset) => *offset,
+                };
+
+                buffer.reset_edit_type();
+                cursor.mode = CursorMode::Normal(offset);
+                cursor.horiz = None;
+                vec![]
+            }
+            InsertMode => {
+                cursor.mode = CursorMode::Insert(Selection::caret(cursor.offset()));
+                vec![]
+            }
+            InsertFirstNonBlank => {
+                match &cursor.mode {
+                    CursorMode::Normal(offset) => {
+                        let line = buffer.line_of_offset(*offset);
+                        let offset = buffer.first_non_blank_character_on_line(line);
+                        cursor.mode = CursorMode::Insert(Selection::caret(offset));
+                    }
+                    CursorMode::Visual { .. } => {
+                        let mut selection = Selection::new();
+                        for region in cursor.edit_selection(buffer).regions() {
+                            selection.add_region(SelRegion::caret(region.min()));
+                        }
+                        cursor.mode = CursorMode::Insert(selection);
+                    }
+                    CursorMode::Insert(_) => {}
+                };
+                vec![]
+            }
+            Append => {
+                if let CursorMode::Visual {
+                    mode: VisualMode::Blockwise,
+                    ..
+                } = cursor.mode
+                {
+                    return Self::append_block(cursor, buffer);
+                }
+                let offset = buffer.move_right(cursor.offset(), Mode::Insert, 1);
+                cursor.mode = CursorMode::Insert(Selection::caret(offset));
+                vec![]
+            }
+            AppendEndOfLine => {
+                let offset = cursor.offset();
+                let line = buffer.line_of_offset(offset);
+                let offset = buffer.line_end_offset(line, true);
+                cursor.mode = CursorMode::Insert(Selection::caret(offset));
+                vec![]
+            }
+            ToggleVisualMode => {
+                Self::toggle_visual(cursor, VisualMode::Normal, modal);
+                vec![]
+            }
+            ToggleLinewiseVisualMode => {
+                Self::toggle_visual(cursor, VisualMode::Linewise, modal);
+                vec![]
+            }
+            ToggleBlockwiseVisualMode => {
+                Self::toggle_visual(cursor, VisualMode::Blockwise, modal);
+                vec![]
+            }
+        }
+    }
+}
+
+/// The byte range of the number in `line` that `col` is on, or else the
+/// first one after `col`. A `-` right before a decimal number is included.
+fn find_number(line: &str, col: usize) -> Option<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let count = |from: usize, f: fn(&u8) -> bool| {
+        bytes[from..].iter().take_while(|b| f(b)).count()
+    };
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        let is_hex = bytes[i] == b'0'
+            && matches!(bytes.get(i + 1), Some(b'x' | b'X'))
+            && matches!(bytes.get(i + 2), Some(b) if b.is_ascii_hexdigit());
+        let (start, end) = if is_hex {
+            (i, i + 2 + count(i + 2, u8::is_ascii_hexdigit))
+        } else {
+            let end = i + count(i, u8::is_ascii_digit);
+            if i > 0 && bytes[i - 1] == b'-' {
+                (i - 1, end)
+            } else {
+                (i, end)
+            }
+        };
+        if end > col {
+            return Some((start, end));
+        }
+        i = end;
+    }
+    None
+}
+
+/// Adds `delta` to the number in `text` and formats it the same way.
+fn add_to_number(text: &str, delta: i64) -> Option<String> {
+    if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X"))
+    {
+        let value = u64::from_str_radix(digits, 16).ok()?;
+        let value = value.wrapping_add(delta as u64);
+        let width = digits.len();
+        let prefix = &text[..2];
+        return Some(if digits.chars().any(|c| c.is_ascii_uppercase()) {
+            format!("{}{:0width$X}", prefix, value, width = width)
+        } else {
+            format!("{}{:0width$x}", prefix, value, width = width)
+        });
+    }
+
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value: i128 = digits.parse().ok()?;
+    let value = if negative { -value } else { value } + delta as i128;
+    let width = if digits.len() > 1 && digits.starts_with('0') {
+        digits.len()
+    } else {
+        0
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    Some(format!("{}{:0width$}", sign, value.abs(), width = width))
+}
+
+/// The number at the start of `line`, ignoring leading whitespace.
+fn leading_number(line: &str) -> Option<f64> {
+    let line = line.trim_start();
+    let mut end = 0;
+    for (i, c) in line.char_indices() {
+        if c.is_ascii_digit() || c == '.' || (i == 0 && (c == '-' || c == '+')) {
+            end = i + 1;
+        } else {
+            break;
+        }
+    }
+    line[..end].parse().ok()
+}
+
+fn url_encode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            result.push(b as char);
+        } else {
+            result.push_str(&format!("%{:02X}", b));
+        }
+    }
+    result
+}
+
+fn url_decode(s: &str) -> Result<String, TransformError> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(TransformError::InvalidPercentEncoding)?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| TransformError::InvalidUtf8)
+}
+
+fn html_encode(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn html_decode(s: &str) -> Result<String, TransformError> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let end = rest.find(';').ok_or(TransformError::InvalidHtmlEntity)?;
+        let entity = &rest[..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = if let Some(hex) = entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+            }
+        };
+        result.push(c.ok_or(TransformError::InvalidHtmlEntity)?);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::buffer::{Buffer, LineEnding};
+    use crate::command::EditCommand;
+    use crate::cursor::{ColPosition, Cursor, CursorMode};
+    use crate::editor::{
+        AutoPairOptions, EditOptions, EditType, Editor, RepeatableEdit, SortOrder,
+        TransformError, TransformKind,
+    };
+    use crate::mode::VisualMode;
+    use crate::movement::{CharSearch, Movement};
+    use crate::register::{Clipboard, Register, RegisterData, RegisterSelector};
+    use crate::selection::{SelRegion, Selection};
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct TestClipboard(Option<String>);
+
+    impl Clipboard for TestClipboard {
+        fn get_string(&self) -> Option<String> {
+            self.0.clone()
+        }
+
+        fn put_string(&mut self, s: impl AsRef<str>) {
+            self.0 = Some(s.as_ref().to_string());
+        }
+    }
+
+    fn do_edit(cursor: &mut Cursor, buffer: &mut Buffer, cmd: &EditCommand) {
+        Editor::do_edit(
+            cursor,
+            buffer,
+            cmd,
+            None,
+            &mut TestClipboard::default(),
+            false,
+            &mut Register::default(),
+        );
+    }
+
+    #[test]
+    fn test_insert_simple() {
+        let mut buffer = Buffer::new("abc");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "e", None);
+        assert_eq!("aebc", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_insert_multiple_cursor() {
+        let mut buffer = Buffer::new("abc\nefg\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "i", None);
+        assert_eq!("aibc\neifg\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+    #[test]
+    fn test_insert_with_cursor_added_below() {
+        let mut buffer = Buffer::new("abc\nd\n");
+        let mut selection = Selection::caret(2);
+        // The line below is shorter, so InsertCursorBelow puts the caret at
+        // its end, and adding it a second time changes nothing
+        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::caret(5));
+        assert_eq!(2, selection.len());
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "x", None);
+        assert_eq!("abxc\ndx\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::caret(3);
+        expected.add_region(SelRegion::caret(7));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_insert_complex() {
+        let mut buffer = Buffer::new("abc\nefg\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "i", None);
+        assert_eq!("aibc\neifg\n", buffer.slice_to_cow(0..buffer.len()));
+        Editor::insert(&mut cursor, &mut buffer, "j", None);
+        assert_eq!("aijbc\neijfg\n", buffer.slice_to_cow(0..buffer.len()));
+        Editor::insert(&mut cursor, &mut buffer, "{", None);
+        assert_eq!("aij{bc\neij{fg\n", buffer.slice_to_cow(0..buffer.len()));
+        Editor::insert(&mut cursor, &mut buffer, " ", None);
+        assert_eq!("aij{ bc\neij{ fg\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_insert_pair() {
+        let mut buffer = Buffer::new("a bc\ne fg\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(6));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "{", None);
+        assert_eq!("a{} bc\ne{} fg\n", buffer.slice_to_cow(0..buffer.len()));
+        Editor::insert(&mut cursor, &mut buffer, "}", None);
+        assert_eq!("a{} bc\ne{} fg\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_move_line_down() {
+        let mut buffer = Buffer::new("abc\nefg\nhij\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::MoveLineDown);
+        assert_eq!("efg\nabc\nhij\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(5, cursor.offset());
+    }
+
+    #[test]
+    fn test_move_line_down_multiple_cursor() {
+        let mut buffer = Buffer::new("abc\nefg\nhij\nklm\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(9));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::MoveLineDown);
+        assert_eq!("efg\nabc\nklm\nhij\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(5));
+        expected.add_region(SelRegion::caret(13));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_move_line_down_without_trailing_newline() {
+        let mut buffer = Buffer::new("abc\nefg");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::MoveLineDown);
+        assert_eq!("efg\nabc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(5, cursor.offset());
+    }
+
+    #[test]
+    fn test_move_line_down_last_line() {
+        let mut buffer = Buffer::new("abc\nefg\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::MoveLineDown);
+        assert_eq!("abc\nefg\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_insert_pair_with_selection() {
+        for (open, close) in [("(", ")"), ("[", "]"), ("{", "}"), ("\"", "\"")] {
+            let mut buffer = Buffer::new("abc def\n");
+            let mut cursor =
+                Cursor::new(CursorMode::Insert(Selection::region(0, 3)), None, None);
+
+            Editor::insert(&mut cursor, &mut buffer, open, None);
+            assert_eq!(
+                format!("{open}abc{close} def\n"),
+                buffer.slice_to_cow(0..buffer.len())
+            );
+            assert_eq!(CursorMode::Insert(Selection::region(1, 4)), cursor.mode);
+        }
+    }
+
+    #[test]
+    fn test_insert_pair_with_multiple_selection() {
+        let mut buffer = Buffer::new("abc def\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(4, 7, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "'", None);
+        assert_eq!("'abc' 'def'\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(1, 4, None));
+        expected.add_region(SelRegion::new(7, 10, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let mut buffer = Buffer::new("abc\n    def\nghi\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!("abc def\nghi\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+
+        let mut buffer = Buffer::new("abc \ndef\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!("abc def\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_join_lines_empty_next_line() {
+        let mut buffer = Buffer::new("abc\n\ndef\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!("abc\ndef\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+    }
+
+    #[test]
+    fn test_join_lines_visual() {
+        let mut buffer = Buffer::new("a\n  b\nc\nd\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 6,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!("a b c\nd\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+    }
+
+    #[test]
+    fn test_join_lines_last_line() {
+        let mut buffer = Buffer::new("abc\ndef");
+        let mut cursor = Cursor::new(CursorMode::Normal(5), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!("abc\ndef", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(5), cursor.mode);
+
+        // The final newline isn't joined away
+        let mut buffer = Buffer::new("abc\ndef\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(5), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!("abc\ndef\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(5), cursor.mode);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLinesRaw);
+        assert_eq!("abc\ndef\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_duplicate_line() {
+        let mut buffer = Buffer::new("abc\nefg\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DuplicateLineDown);
+        assert_eq!("abc\nabc\nefg\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(5, cursor.offset());
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DuplicateLineUp);
+        assert_eq!("abc\nabc\nabc\nefg\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(5, cursor.offset());
+    }
+
+    #[test]
+    fn test_duplicate_last_line() {
+        let mut buffer = Buffer::new("abc\nefg");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(7)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DuplicateLineDown);
+        assert_eq!("abc\nefg\nefg", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(11, cursor.offset());
+    }
+
+    #[test]
+    fn test_duplicate_selection_multiple_cursor() {
+        let mut buffer = Buffer::new("abc efg\nhij\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(4, 7, None));
+        selection.add_region(SelRegion::caret(9));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DuplicateLineDown);
+        assert_eq!(
+            "abcabc efgefg\nhij\nhij\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(3, 6, None));
+        expected.add_region(SelRegion::new(10, 13, None));
+        expected.add_region(SelRegion::caret(19));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    fn delete_line(cursor: &mut Cursor, buffer: &mut Buffer) -> RegisterData {
+        let mut register = Register::default();
+        Editor::do_edit(
+            cursor,
+            buffer,
+            &EditCommand::DeleteLine,
+            None,
+            &mut TestClipboard::default(),
+            true,
+            &mut register,
+        );
+        register.unnamed
+    }
+
+    #[test]
+    fn test_delete_line_start() {
+        let mut buffer = Buffer::new("abc\n  def\nghi\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+
+        let data = delete_line(&mut cursor, &mut buffer);
+        assert_eq!("  def\nghi\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("abc\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+        assert_eq!(CursorMode::Normal(2), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_line_middle() {
+        let mut buffer = Buffer::new("abc\ndef\n  ghi\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(5), None, None);
+
+        let data = delete_line(&mut cursor, &mut buffer);
+        assert_eq!("abc\n  ghi\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("def\n", data.content);
+        assert_eq!(CursorMode::Normal(6), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_line_last() {
+        let mut buffer = Buffer::new("abc\n def\nghi");
+        let mut cursor = Cursor::new(CursorMode::Normal(10), None, None);
+
+        let data = delete_line(&mut cursor, &mut buffer);
+        assert_eq!("abc\n def", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("ghi\n", data.content);
+        assert_eq!(CursorMode::Normal(5), cursor.mode);
+
+        let mut buffer = Buffer::new("abc\ndef\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(5), None, None);
+
+        let data = delete_line(&mut cursor, &mut buffer);
+        assert_eq!("abc\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("def\n", data.content);
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_line_visual() {
+        let mut buffer = Buffer::new("abc\ndef\nghi\njkl\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 5,
+                end: 9,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+
+        let data = delete_line(&mut cursor, &mut buffer);
+        assert_eq!("abc\njkl\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("def\nghi\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+        assert_eq!(CursorMode::Normal(4), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_backward_pair() {
+        for pair in ["()", "[]", "{}", "\"\"", "''", "``"] {
+            let mut buffer = Buffer::new(&format!("a{pair}b"));
+            let mut cursor =
+                Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+
+            do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+            assert_eq!("ab", buffer.slice_to_cow(0..buffer.len()));
+            assert_eq!(CursorMode::Insert(Selection::caret(1)), cursor.mode);
+        }
+    }
+
+    #[test]
+    fn test_delete_backward_unmatched_pair() {
+        let mut buffer = Buffer::new("a(]b ( )");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+        assert_eq!("a]b ( )", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+        assert_eq!("a]b  )", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_delete_backward_pair_multiple_cursor() {
+        let mut buffer = Buffer::new("a()\nb[x\nc{}\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(6));
+        selection.add_region(SelRegion::caret(10));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+        assert_eq!("a\nbx\nc\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(1));
+        expected.add_region(SelRegion::caret(3));
+        expected.add_region(SelRegion::caret(6));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_insert_pair_inside_string_or_comment() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let text = "let a = \"\";\n// \nlet b = ;\n";
+        let buffer = Buffer::new(text);
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+
+        for (offset, expected) in [
+            (9, "let a = \"(\";\n// \nlet b = ;\n"),
+            (15, "let a = \"\";\n// (\nlet b = ;\n"),
+            (24, "let a = \"\";\n// \nlet b = ();\n"),
+        ] {
+            let mut buffer = Buffer::new(text);
+            let mut cursor = Cursor::new(
+                CursorMode::Insert(Selection::caret(offset)),
+                None,
+                None,
+            );
+            Editor::insert(&mut cursor, &mut buffer, "(", Some(&syntax));
+            assert_eq!(expected, buffer.slice_to_cow(0..buffer.len()));
+        }
+    }
+
+    #[test]
+    fn test_insert_custom_pair() {
+        let mut options = AutoPairOptions::default();
+        options.pairs.insert('<', '>');
+
+        let mut buffer = Buffer::new("a\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "<", None, &options);
+        assert_eq!("a<>\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(2)), cursor.mode);
+
+        Editor::insert_with_options(&mut cursor, &mut buffer, ">", None, &options);
+        assert_eq!("a<>\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 1)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "<", None, &options);
+        assert_eq!("<a><>\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(1, 2)), cursor.mode);
+    }
+
+    #[test]
+    fn test_insert_disabled_pair() {
+        let mut options = AutoPairOptions::default();
+        options.disabled.insert('(');
+        options.disabled.insert('\'');
+
+        let mut buffer = Buffer::new("a\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "(", None, &options);
+        assert_eq!("a(\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 1)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "'", None, &options);
+        assert_eq!("'(\n", buffer.slice_to_cow(0..buffer.len()));
+
+        Editor::insert_with_options(&mut cursor, &mut buffer, "[", None, &options);
+        assert_eq!("'[](\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_indent_spaces() {
+        let mut buffer = Buffer::new("a\n  b\n\nc");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 8)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::IndentLine);
+        assert_eq!(
+            "    a\n    b\n\n    c",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::region(0, 18)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::OutdentLine);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::OutdentLine);
+        assert_eq!("a\nb\n\nc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(0, 6)), cursor.mode);
+    }
+
+    #[test]
+    fn test_indent_tabs() {
+        let mut buffer = Buffer::new("a {\n\tb {\n\t\tc\n\t}\n}\n");
+        buffer.detect_indent(None);
+        assert_eq!("\t", buffer.indent_unit());
+
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 4,
+                end: 12,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::IndentLine);
+        assert_eq!(
+            "a {\n\t\tb {\n\t\t\tc\n\t}\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(
+            CursorMode::Visual {
+                start: 4,
+                end: 14,
+                mode: VisualMode::Linewise,
+            },
+            cursor.mode
+        );
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::OutdentLine);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::OutdentLine);
+        assert_eq!(
+            "a {\nb {\n\tc\n\t}\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_outdent_mixed_indentation() {
+        let mut buffer = Buffer::new("\t\ta\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::OutdentLine);
+        assert_eq!("\ta\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+
+        let mut buffer = Buffer::new("\t  a\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(3), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::OutdentLine);
+        assert_eq!("\ta\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_toggle_line_comment_without_syntax() {
+        let mut buffer = Buffer::new("a\nb\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleLineComment);
+        assert_eq!("a\nb\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[cfg(feature = "lang-rust")]
+    fn toggle_line_comment(cursor: &mut Cursor, buffer: &mut Buffer) {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let syntax = Syntax::from_language(LapceLanguage::Rust);
+        Editor::do_edit(
+            cursor,
+            buffer,
+            &EditCommand::ToggleLineComment,
+            Some(&syntax),
+            &mut TestClipboard::default(),
+            false,
+            &mut Register::default(),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_toggle_line_comment() {
+        let mut buffer = Buffer::new("fn a() {\n    b();\n\n        c();\n}\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(9, 31)), None, None);
+
+        toggle_line_comment(&mut cursor, &mut buffer);
+        assert_eq!(
+            "fn a() {\n    // b();\n\n    //     c();\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        toggle_line_comment(&mut cursor, &mut buffer);
+        assert_eq!(
+            "fn a() {\n    b();\n\n        c();\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_toggle_line_comment_mixed() {
+        let mut buffer = Buffer::new("// a\nb\n//c\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 10,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+
+        toggle_line_comment(&mut cursor, &mut buffer);
+        assert_eq!(
+            "// // a\n// b\n// //c\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_change_case() {
+        let mut buffer = Buffer::new("hello World foo");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::new(6, 11, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::UppercaseSelection);
+        assert_eq!("HELLO WORLD foo", buffer.slice_to_cow(0..buffer.len()));
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::LowercaseSelection);
+        assert_eq!("hello world foo", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(2));
+        expected.add_region(SelRegion::new(6, 11, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 8,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleCaseSelection);
+        assert_eq!("HELLO WORld foo", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
+
+    #[test]
+    fn test_toggle_case_word_under_caret() {
+        let mut buffer = Buffer::new("hello World");
+        let mut cursor = Cursor::new(CursorMode::Normal(8), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleCaseSelection);
+        assert_eq!("hello wORLD", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(8), cursor.mode);
+
+        // Carets right before or after a word take that word
+        let mut buffer = Buffer::new("foo.Bar baz");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(11));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleCaseSelection);
+        assert_eq!("FOO.bAR BAZ", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(3));
+        expected.add_region(SelRegion::caret(4));
+        expected.add_region(SelRegion::caret(11));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_change_case_unicode() {
+        let mut buffer = Buffer::new("straße İx");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 7, None));
+        selection.add_region(SelRegion::new(8, 11, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::UppercaseSelection);
+        assert_eq!("STRASSE İX", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 7, None));
+        expected.add_region(SelRegion::new(8, 11, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleCaseSelection);
+        assert_eq!("strasse i\u{307}x", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_transpose_characters() {
+        let mut buffer = Buffer::new("abc\nde");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+        assert_eq!("bac\nde", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(2)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+        assert_eq!("bca\nde", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        // At the end of the line the two characters before the caret swap
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+        assert_eq!("bac\nde", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(6)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+        assert_eq!("bac\ned", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(6)), cursor.mode);
+    }
+
+    #[test]
+    fn test_transpose_characters_start() {
+        let mut buffer = Buffer::new("ab\ncd");
+        for offset in [0, 3] {
+            let mut cursor = Cursor::new(
+                CursorMode::Insert(Selection::caret(offset)),
+                None,
+                None,
+            );
+            do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+            assert_eq!("ab\ncd", buffer.slice_to_cow(0..buffer.len()));
+            assert_eq!(CursorMode::Insert(Selection::caret(offset)), cursor.mode);
+        }
+    }
+
+    #[test]
+    fn test_transpose_characters_combining() {
+        let mut buffer = Buffer::new("e\u{301}x\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+        assert_eq!("xe\u{301}\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TransposeCharacters);
+        assert_eq!("e\u{301}x\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+    }
+
+    #[test]
+    fn test_select_inside_pair() {
+        let buffer = Buffer::new("foo(a, (b + c), d) {e}");
+        //                      ->0123456789012345678901<-
+
+        let cursor = Cursor::new(CursorMode::Normal(9), None, None);
+        let selection =
+            Editor::select_inside_pair(&cursor, &buffer, None, '(', false);
+        assert_eq!(Some(Selection::region(8, 13)), selection);
+        let selection =
+            Editor::select_inside_pair(&cursor, &buffer, None, ')', true);
+        assert_eq!(Some(Selection::region(7, 14)), selection);
+
+        let cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let selection =
+            Editor::select_inside_pair(&cursor, &buffer, None, '(', false);
+        assert_eq!(Some(Selection::region(4, 17)), selection);
+
+        let cursor = Cursor::new(CursorMode::Normal(7), None, None);
+        let selection =
+            Editor::select_inside_pair(&cursor, &buffer, None, '(', false);
+        assert_eq!(Some(Selection::region(8, 13)), selection);
+
+        let cursor = Cursor::new(CursorMode::Normal(20), None, None);
+        let selection =
+            Editor::select_inside_pair(&cursor, &buffer, None, '{', false);
+        assert_eq!(Some(Selection::region(20, 21)), selection);
+        let selection =
+            Editor::select_inside_pair(&cursor, &buffer, None, '[', false);
+        assert_eq!(None, selection);
+    }
+
+    #[test]
+    fn test_select_next_occurrence() {
+        let buffer = Buffer::new("foo bar foo\nfoo");
+        let matcher = |search: &str, offset: usize| {
+            let text = buffer.slice_to_cow(0..buffer.len());
+            text[offset..]
+                .find(search)
+                .map(|start| (offset + start, offset + start + search.len()))
+        };
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(9)), None, None);
+
+        Editor::select_next_occurrence(&mut cursor, &buffer, matcher);
+        assert_eq!(CursorMode::Insert(Selection::region(8, 11)), cursor.mode);
+
+        Editor::select_next_occurrence(&mut cursor, &buffer, matcher);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(8, 11, None));
+        expected.add_region(SelRegion::new(12, 15, None));
+        assert_eq!(CursorMode::Insert(expected.clone()), cursor.mode);
+
+        // Wraps around to the start of the buffer
+        Editor::select_next_occurrence(&mut cursor, &buffer, matcher);
+        expected.add_region(SelRegion::new(0, 3, None));
+        assert_eq!(CursorMode::Insert(expected.clone()), cursor.mode);
+
+        // Every occurrence is selected already
+        Editor::select_next_occurrence(&mut cursor, &buffer, matcher);
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_expand_selection() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let buffer = Buffer::new("fn main() {\n    foo(bar);\n}\n");
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(21)), None, None);
+
+        Editor::expand_selection(&mut cursor, &buffer, Some(&syntax));
+        assert_eq!(CursorMode::Insert(Selection::region(20, 23)), cursor.mode);
+        Editor::expand_selection(&mut cursor, &buffer, Some(&syntax));
+        assert_eq!(CursorMode::Insert(Selection::region(19, 24)), cursor.mode);
+        Editor::expand_selection(&mut cursor, &buffer, Some(&syntax));
+        assert_eq!(CursorMode::Insert(Selection::region(16, 24)), cursor.mode);
+
+        Editor::shrink_selection(&mut cursor);
+        assert_eq!(CursorMode::Insert(Selection::region(19, 24)), cursor.mode);
+        Editor::shrink_selection(&mut cursor);
+        Editor::shrink_selection(&mut cursor);
+        assert_eq!(CursorMode::Insert(Selection::caret(21)), cursor.mode);
+    }
+
+    #[test]
+    fn test_expand_selection_without_syntax() {
+        let buffer = Buffer::new("one two\nthree\n\nfour\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+
+        Editor::expand_selection(&mut cursor, &buffer, None);
+        assert_eq!(CursorMode::Insert(Selection::region(4, 7)), cursor.mode);
+        Editor::expand_selection(&mut cursor, &buffer, None);
+        assert_eq!(CursorMode::Insert(Selection::region(0, 7)), cursor.mode);
+        Editor::expand_selection(&mut cursor, &buffer, None);
+        assert_eq!(CursorMode::Insert(Selection::region(0, 13)), cursor.mode);
+        Editor::expand_selection(&mut cursor, &buffer, None);
+        assert_eq!(CursorMode::Insert(Selection::region(0, 20)), cursor.mode);
+
+        // A selection changed in between can't be shrunk back
+        cursor.mode = CursorMode::Insert(Selection::caret(0));
+        Editor::shrink_selection(&mut cursor);
+        assert_eq!(CursorMode::Insert(Selection::caret(0)), cursor.mode);
+        assert!(cursor.expand_history.is_empty());
+    }
+
+    #[test]
+    fn test_paste_from_numbered_register() {
+        let mut buffer = Buffer::new("a\nb\nc\n");
+        let mut register = Register::default();
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 0,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        Editor::do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::Yank,
+            None,
+            &mut TestClipboard::default(),
+            true,
+            &mut register,
+        );
+
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        Editor::do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::DeleteLine,
+            None,
+            &mut TestClipboard::default(),
+            true,
+            &mut register,
+        );
+        assert_eq!("a\nc\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        Editor::paste_from_register(
+            &mut cursor,
+            &mut buffer,
+            &register,
+            RegisterSelector::Numbered(0),
+        );
+        assert_eq!("a\nc\na\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::paste_from_register(
+            &mut cursor,
+            &mut buffer,
+            &register,
+            RegisterSelector::Numbered(1),
+        );
+        assert_eq!("a\nb\nc\na\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_paste_block() {
+        let mut buffer = Buffer::new("abcd\nx\nefgh\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        let data = RegisterData {
+            content: "12\n3\n56\n".to_string(),
+            mode: VisualMode::Blockwise,
+        };
+
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!(
+            "ab12cd\nx 3\nef56gh\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(2), cursor.mode);
+
+        let mut buffer = Buffer::new("abcd\nefgh\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(6), None, None);
+
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!(
+            "abcd\nef12gh\n  3\n  56\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(7), cursor.mode);
+    }
+
+    #[test]
+    fn test_paste_reindent() {
+        let data = RegisterData {
+            content: "if a {\n    if b {\n        c();\n    }\n}\n".to_string(),
+            mode: VisualMode::Linewise,
+        };
+
+        let mut buffer = Buffer::new("fn main() {\n        let x = 1;\n}\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(14), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, true);
+        assert_eq!(
+            "fn main() {\n        let x = 1;\n        if a {\n            if b {\n                c();\n            }\n        }\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(39), cursor.mode);
+
+        let mut buffer = Buffer::new("fn main() {\n        let x = 1;\n}\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(14), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!(
+            "fn main() {\n        let x = 1;\nif a {\n    if b {\n        c();\n    }\n}\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_paste_reindent_nested_source() {
+        let data = RegisterData {
+            content: "        x();\n\n            y();\n    z();\n".to_string(),
+            mode: VisualMode::Linewise,
+        };
+
+        let mut buffer = Buffer::new("    a\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, true);
+        assert_eq!(
+            "    a\n    x();\n\n        y();\nz();\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_sort_lines() {
+        let mut buffer = Buffer::new("b\nC\na\nc\nz");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 6,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SortLines);
+        assert_eq!("C\na\nb\nc\nz", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+
+        let mut buffer = Buffer::new("b\nC\na\nc\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 8)), None, None);
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::SortLinesCaseInsensitive,
+        );
+        assert_eq!("a\nb\nC\nc\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(0, 8)), cursor.mode);
+
+        // The last line keeps lacking a newline
+        let mut buffer = Buffer::new("x\nb\nc\na");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(2, 7)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SortLines);
+        assert_eq!("x\na\nb\nc", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_sort_lines_reverse_and_numeric() {
+        let mut buffer = Buffer::new("b\nc\na\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 6)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SortLinesDescending);
+        assert_eq!("c\nb\na\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("10 x\n9 y\n-1 z\nno\n2.5\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 21)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SortLinesNumeric);
+        assert_eq!(
+            "no\n-1 z\n2.5\n9 y\n10 x\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut buffer = Buffer::new("b\na\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SortLines);
+        assert_eq!("b\na\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 2,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        Editor::sort_lines(&mut cursor, &mut buffer, SortOrder::Numeric, false);
+        assert_eq!("a\nb\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_delete_to_line_end() {
+        let mut buffer = Buffer::new("abc def\n  ghi\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        let (_, data) = Editor::delete_to_line_end(&mut cursor, &mut buffer);
+        assert_eq!("abc\n  ghi\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(" def", data.unwrap().content);
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        // At the line end the newline stays
+        let (deltas, data) = Editor::delete_to_line_end(&mut cursor, &mut buffer);
+        assert!(deltas.is_empty());
+        assert!(data.is_none());
+        assert_eq!("abc\n  ghi\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        let (_, data) = Editor::delete_to_line_end(&mut cursor, &mut buffer);
+        assert_eq!("a\n \n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("bc\n ghi", data.unwrap().content);
+
+        let mut buffer = Buffer::new("abc def\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let mut register = Register::default();
+        Editor::do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::DeleteToLineEnd,
+            None,
+            &mut TestClipboard::default(),
+            true,
+            &mut register,
+        );
+        assert_eq!("abc \n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+        assert_eq!("def", register.unnamed.content);
+    }
+
+    #[test]
+    fn test_delete_to_line_start() {
+        let mut buffer = Buffer::new("  abc def\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(6)), None, None);
+        let (_, data) = Editor::delete_to_line_start(&mut cursor, &mut buffer);
+        assert_eq!("  def\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("abc ", data.unwrap().content);
+        assert_eq!(CursorMode::Insert(Selection::caret(2)), cursor.mode);
+
+        // Within the indentation everything up to the caret goes
+        let (_, data) = Editor::delete_to_line_start(&mut cursor, &mut buffer);
+        assert_eq!("def\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("  ", data.unwrap().content);
+        assert_eq!(CursorMode::Insert(Selection::caret(0)), cursor.mode);
+
+        // At the line start the previous newline stays
+        let mut buffer = Buffer::new("abc\ndef\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(4)), None, None);
+        let (deltas, data) = Editor::delete_to_line_start(&mut cursor, &mut buffer);
+        assert!(deltas.is_empty());
+        assert!(data.is_none());
+        assert_eq!("abc\ndef\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_delete_word_backward() {
+        let mut buffer = Buffer::new("let x = foo.bar  \n    baz");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(17));
+        selection.add_region(SelRegion::caret(25));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordBackward);
+        assert_eq!("let x = foo.\n    ", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordBackward);
+        assert_eq!("let x = foo\n", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordBackward);
+        assert_eq!("let x = ", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(8));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_word_forward() {
+        let mut buffer = Buffer::new("foo.bar  baz\nqux(1)");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(13));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordForward);
+        assert_eq!(".bar  baz\n(1)", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordForward);
+        assert_eq!("bar  baz\n1)", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordForward);
+        assert_eq!("baz\n)", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("ab\ncd");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordForward);
+        assert_eq!("abcd", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_insert_newline_keeps_indent() {
+        let mut buffer = Buffer::new("    let a = 1;");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(14)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "\n", None);
+        assert_eq!("    let a = 1;\n    ", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(19)), cursor.mode);
+
+        let mut buffer = Buffer::new("\tlet a = 1;");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(11)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "\n", None);
+        assert_eq!("\tlet a = 1;\n\t", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_insert_newline_between_braces() {
+        let mut buffer = Buffer::new("    fn a() {}");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(12)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "\n", None);
+        assert_eq!(
+            "    fn a() {\n        \n    }",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(21)), cursor.mode);
+
+        let mut buffer = Buffer::new("\tfn a() {}");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(9)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "\n", None);
+        assert_eq!(
+            "\tfn a() {\n\t\t\n\t}",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(12)), cursor.mode);
+    }
+
+    #[test]
+    fn test_surround() {
+        let mut buffer = Buffer::new("let foo = bar;");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        Editor::surround(&mut cursor, &mut buffer, "\"", "\"");
+        assert_eq!("let \"foo\" = bar;", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(6)), cursor.mode);
+
+        let mut buffer = Buffer::new("some text");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 4)), None, None);
+        Editor::surround(&mut cursor, &mut buffer, "<b>", "</b>");
+        assert_eq!("<b>some</b> text", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(3, 7)), cursor.mode);
+    }
+
+    #[test]
+    fn test_surround_multiple_cursors() {
+        let mut buffer = Buffer::new("a bc d");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::new(5, 2, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::surround(&mut cursor, &mut buffer, "<div>", "</div>");
+        assert_eq!(
+            "<div>a</div> <div>bc </div>d",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(5));
+        expected.add_region(SelRegion::new(21, 18, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        let mut buffer = Buffer::new("ab");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        Editor::surround(&mut cursor, &mut buffer, "(", ")");
+        assert_eq!("(ab)", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
+
+    #[test]
+    fn test_change_surround() {
+        let mut buffer = Buffer::new("let a = (foo);");
+        let mut cursor = Cursor::new(CursorMode::Normal(10), None, None);
+        Editor::change_surround(&mut cursor, &mut buffer, None, '(', '[');
+        assert_eq!("let a = [foo];", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(10), cursor.mode);
+
+        // Either side of the new pair can be given
+        Editor::change_surround(&mut cursor, &mut buffer, None, ']', '}');
+        assert_eq!("let a = {foo};", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("(a (b) c)");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(4)), None, None);
+        Editor::change_surround(&mut cursor, &mut buffer, None, '(', '"');
+        assert_eq!("(a \"b\" c)", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_delete_surround() {
+        let mut buffer = Buffer::new("f((foo), bar)");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::delete_surround(&mut cursor, &mut buffer, None, ')');
+        assert_eq!("f(foo, bar)", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(3));
+        expected.add_region(SelRegion::caret(4));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Without an enclosing pair nothing changes
+        let mut buffer = Buffer::new("foo (bar)");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        let deltas = Editor::delete_surround(&mut cursor, &mut buffer, None, '(');
+        assert!(deltas.is_empty());
+        assert_eq!("foo (bar)", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+    }
+
+    #[test]
+    fn test_increment_number() {
+        let mut buffer = Buffer::new("let a = 9; let b = 41;");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::increment_number(&mut cursor, &mut buffer, 1);
+        assert_eq!(
+            "let a = 10; let b = 41;",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(9), cursor.mode);
+
+        let mut cursor = Cursor::new(CursorMode::Normal(20), None, None);
+        Editor::increment_number(&mut cursor, &mut buffer, -50);
+        assert_eq!(
+            "let a = 10; let b = -9;",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        Editor::increment_number(&mut cursor, &mut buffer, 10);
+        assert_eq!(
+            "let a = 10; let b = 1;",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        // No number after the caret
+        let mut cursor = Cursor::new(CursorMode::Normal(22), None, None);
+        let deltas = Editor::increment_number(&mut cursor, &mut buffer, 1);
+        assert!(deltas.is_empty());
+    }
+
+    #[test]
+    fn test_increment_hex_and_padded_number() {
+        let mut buffer = Buffer::new("0x0f 0XFF 007 -001");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::caret(11));
+        selection.add_region(SelRegion::caret(14));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::increment_number(&mut cursor, &mut buffer, 1);
+        assert_eq!("0x10 0X100 008 000", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(4));
+        expected.add_region(SelRegion::caret(10));
+        expected.add_region(SelRegion::caret(14));
+        expected.add_region(SelRegion::caret(18));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        Editor::increment_number(&mut cursor, &mut buffer, -2);
+        assert_eq!("0x0e 0X0fe 006 -002", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_join_lines_raw() {
+        let mut buffer = Buffer::new("https://exa\n  mple.com/\npath \nend\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 30,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLinesRaw);
+        assert_eq!(
+            "https://exa  mple.com/path end\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(11), cursor.mode);
+
+        // The space inserting join trims the indentation instead
+        let mut buffer = Buffer::new("https://exa\n  mple.com/\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLines);
+        assert_eq!(
+            "https://exa mple.com/\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut buffer = Buffer::new("abc");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::JoinLinesRaw);
+        assert_eq!("abc", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    fn repeat_last_edit(cursor: &mut Cursor, buffer: &mut Buffer) {
+        Editor::repeat_last_edit(
+            cursor,
+            buffer,
+            None,
+            &mut TestClipboard::default(),
+            true,
+            &mut Register::default(),
+        );
+    }
+
+    #[test]
+    fn test_repeat_insert() {
+        let mut buffer = Buffer::new("a b\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        for c in ["f", "o", "o"] {
+            Editor::insert(&mut cursor, &mut buffer, c, None);
+        }
+        do_edit(&mut cursor, &mut buffer, &EditCommand::NormalMode);
+        assert_eq!("fooa b\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(
+            Some(RepeatableEdit::Insert("foo".to_string())),
+            cursor.last_edit
+        );
+
+        cursor.mode = CursorMode::Normal(5);
+        repeat_last_edit(&mut cursor, &mut buffer);
+        assert_eq!("fooa foob\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(7), cursor.mode);
+
+        // A new insert session replaces the recorded text
+        cursor.mode = CursorMode::Insert(Selection::caret(0));
+        Editor::insert(&mut cursor, &mut buffer, "x", None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::NormalMode);
+        cursor.mode = CursorMode::Insert(Selection::caret(0));
+        Editor::insert(&mut cursor, &mut buffer, "y", None);
+        cursor.mode = CursorMode::Normal(buffer.len() - 1);
+        repeat_last_edit(&mut cursor, &mut buffer);
+        assert_eq!("yxfooa fooby\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_repeat_delete_word() {
+        let mut buffer = Buffer::new("one two three four");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordForward);
+        assert_eq!("two three four", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(
+            Some(RepeatableEdit::Command(EditCommand::DeleteWordForward)),
+            cursor.last_edit
+        );
+
+        cursor.mode = CursorMode::Insert(Selection::caret(4));
+        repeat_last_edit(&mut cursor, &mut buffer);
+        assert_eq!("two four", buffer.slice_to_cow(0..buffer.len()));
+
+        // Edits that change nothing don't replace the recorded one
+        cursor.mode = CursorMode::Insert(Selection::caret(buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteWordForward);
+        cursor.mode = CursorMode::Insert(Selection::caret(0));
+        repeat_last_edit(&mut cursor, &mut buffer);
+        assert_eq!("four", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_breaks_undo_group_after_pause() {
+        use super::EditType;
+        use std::time::Duration;
+
+        let threshold = Duration::from_secs(1);
+        let chars = EditType::InsertChars;
+        assert!(!chars.breaks_undo_group_after(chars, None, threshold));
+        assert!(!chars.breaks_undo_group_after(
+            chars,
+            Some(Duration::from_millis(200)),
+            threshold
+        ));
+        assert!(chars.breaks_undo_group_after(
+            chars,
+            Some(Duration::from_secs(2)),
+            threshold
+        ));
+        assert!(chars.breaks_undo_group_after(EditType::Delete, None, threshold));
+
+        let delete = EditType::Delete;
+        assert!(!delete.breaks_undo_group_after(
+            delete,
+            Some(Duration::from_secs(2)),
+            threshold
+        ));
+    }
+
+    #[test]
+    fn test_insert_in_paste_session() {
+        let mut buffer = Buffer::new("abc\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "x", None);
+
+        Editor::begin_paste(&mut cursor, &mut buffer);
+        for c in "fn f() {\n\"a\"".chars() {
+            Editor::insert(&mut cursor, &mut buffer, &c.to_string(), None);
+        }
+        Editor::end_paste(&mut cursor, &mut buffer);
+        assert_eq!(
+            "abcxfn f() {\n\"a\"\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(16)), cursor.mode);
+
+        // The paste is undone at once, apart from what was typed before it
+        buffer.do_undo();
+        assert_eq!("abcx\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Auto pairing is back after the paste session
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(4)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "(", None);
+        assert_eq!("abcx()\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_skip_closing_sequence() {
+        let options = AutoPairOptions {
+            skip_closing: vec!["*/".to_string()],
+            ..Default::default()
+        };
+
+        let mut buffer = Buffer::new("/* a */ b");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "*", None, &options);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "/", None, &options);
+        assert_eq!("/* a */ b", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(7)), cursor.mode);
+
+        // Typed at once
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "*/", None, &options);
+        assert_eq!("/* a */ b", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(7)), cursor.mode);
+
+        // Only the same character follows, but not the closing sequence
+        let mut buffer = Buffer::new("a *b");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "*", None, &options);
+        assert_eq!("a **b", buffer.slice_to_cow(0..buffer.len()));
+
+        // A multibyte character after the caret is no closing sequence
+        let mut buffer = Buffer::new("a€");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "*/", None, &options);
+        assert_eq!("a*/€", buffer.slice_to_cow(0..buffer.len()));
+
+        // Without the option the sequence is inserted
+        let mut buffer = Buffer::new("/* a */");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "*", None);
+        assert_eq!("/* a **/", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_skip_closing_char() {
+        let mut buffer = Buffer::new("())");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, ")", None);
+        assert_eq!("())", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(2)), cursor.mode);
+    }
+
+    #[test]
+    fn test_insert_line_below() {
+        let mut buffer = Buffer::new("fn a() {\n    let b;\n\tc\n}");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(12));
+        selection.add_region(SelRegion::caret(15));
+        selection.add_region(SelRegion::caret(22));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertLineBelow);
+        assert_eq!(
+            "fn a() {\n    let b;\n    \n\tc\n\t\n}",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(24));
+        expected.add_region(SelRegion::caret(29));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // From a blank line at the end
+        let mut buffer = Buffer::new("a\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertLineBelow);
+        assert_eq!("a\n\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+    }
+
+    #[test]
+    fn test_insert_line_above() {
+        let mut buffer = Buffer::new("\tab\n  \ncd");
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertLineAbove);
+        assert_eq!("\t\n\tab\n  \ncd", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(1)), cursor.mode);
+
+        // A blank line keeps its whitespace as the indentation
+        let mut cursor = Cursor::new(CursorMode::Normal(7), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertLineAbove);
+        assert_eq!("\t\n\tab\n  \n  \ncd", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(8)), cursor.mode);
+    }
+
+    #[test]
+    fn test_select_all() {
+        let buffer = Buffer::new("abc\ndef\nghi");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        Editor::select_all(&mut cursor, &buffer);
+        assert_eq!(CursorMode::Insert(Selection::region(0, 11)), cursor.mode);
+        assert_eq!(11, cursor.offset());
+    }
+
+    #[test]
+    fn test_select_line() {
+        let buffer = Buffer::new("abc\ndef\nghi");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::new(9, 10, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        Editor::select_line(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 4, None));
+        expected.add_region(SelRegion::new(8, 11, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        Editor::select_line(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 8, None));
+        expected.add_region(SelRegion::new(8, 11, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_select_line_visual() {
+        let buffer = Buffer::new("abc\ndef\nghi");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+
+        Editor::select_line(&mut cursor, &buffer);
+        assert_eq!(
+            CursorMode::Visual {
+                start: 1,
+                end: 1,
+                mode: VisualMode::Linewise
+            },
+            cursor.mode
+        );
+        assert_eq!(Selection::region(0, 4), cursor.edit_selection(&buffer));
+
+        Editor::select_line(&mut cursor, &buffer);
+        Editor::select_line(&mut cursor, &buffer);
+        Editor::select_line(&mut cursor, &buffer);
+        assert_eq!(Selection::region(0, 11), cursor.edit_selection(&buffer));
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace() {
+        let mut buffer = Buffer::new("a  \nb\t \n  c\n\t\nd");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(6));
+        selection.add_region(SelRegion::caret(9));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::TrimTrailingWhitespace,
+        );
+        assert_eq!("a\nb\n  c\n\nd", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(1));
+        expected.add_region(SelRegion::caret(3));
+        expected.add_region(SelRegion::caret(5));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Undone in one step
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!(
+            "a  \nb\t \n  c\n\t\nd",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_selection() {
+        let mut buffer = Buffer::new("a \nb \nc \nd ");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(3, 6)), None, None);
+        Editor::trim_trailing_whitespace(&mut cursor, &mut buffer, false);
+        assert_eq!("a \nb\nc\nd ", buffer.slice_to_cow(0..buffer.len()));
+
+        // A normal mode caret parked in the whitespace is clamped to the
+        // last character
+        let mut cursor = Cursor::new(CursorMode::Normal(10), None, None);
+        Editor::trim_trailing_whitespace(&mut cursor, &mut buffer, true);
+        assert_eq!("a\nb\nc\nd", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(6), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_motion_word_forward() {
+        let mut buffer = Buffer::new("let a = b;\nc");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let (_, data) = Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::WordForward,
+            2,
+        );
+        assert_eq!("let b;\nc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("a = ", data.unwrap().content);
+        assert_eq!(CursorMode::Normal(4), cursor.mode);
+
+        // The last word of a line doesn't take the newline with it
+        let (_, data) = Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::WordForward,
+            3,
+        );
+        assert_eq!("let \nc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("b;", data.unwrap().content);
+
+        // Backward
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::WordBackward,
+            1,
+        );
+        assert_eq!("\nc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
+
+    #[test]
+    fn test_delete_motion_line_end() {
+        let mut buffer = Buffer::new("abc def\nghi\njkl");
+        let mut cursor = Cursor::new(CursorMode::Normal(3), None, None);
+        let (_, data) = Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::EndOfLine,
+            1,
+        );
+        assert_eq!("abc\nghi\njkl", buffer.slice_to_cow(0..buffer.len()));
+        let data = data.unwrap();
+        assert_eq!(" def", data.content);
+        assert_eq!(VisualMode::Normal, data.mode);
+        assert_eq!(CursorMode::Normal(2), cursor.mode);
+
+        // Vertical motions delete whole lines
+        let (_, data) = Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::Down,
+            1,
+        );
+        assert_eq!("jkl", buffer.slice_to_cow(0..buffer.len()));
+        let data = data.unwrap();
+        assert_eq!("abc\nghi\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+    }
+
+    #[test]
+    fn test_yank_motion() {
+        let mut buffer = Buffer::new("abc def\nghi");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        let data =
+            Editor::yank_motion(&cursor, &buffer, None, &Movement::WordForward, 1);
+        assert_eq!("abc ", data.content);
+        assert_eq!(VisualMode::Normal, data.mode);
+
+        let data =
+            Editor::yank_motion(&cursor, &buffer, None, &Movement::EndOfLine, 1);
+        assert_eq!("abc def", data.content);
+        assert_eq!(VisualMode::Normal, data.mode);
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+
+        // Linewise content pastes as whole lines
+        let data = Editor::yank_motion(&cursor, &buffer, None, &Movement::Down, 1);
+        assert_eq!("abc def\nghi\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!(
+            "abc def\nabc def\nghi\nghi",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_change_motion_word() {
+        let mut buffer = Buffer::new("let abc = d;");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let (_, data) = Editor::change_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::WordForward,
+            1,
+        );
+        assert_eq!("let  = d;", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!("abc", data.unwrap().content);
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+
+        // On whitespace the whitespace itself is changed
+        let mut cursor = Cursor::new(CursorMode::Normal(3), None, None);
+        Editor::change_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::WordForward,
+            1,
+        );
+        assert_eq!("let= d;", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+    }
+
+    #[test]
+    fn test_change_motion_line() {
+        let mut buffer = Buffer::new("a {\n    b;\n    c;\n}");
+        let mut cursor = Cursor::new(CursorMode::Normal(9), None, None);
+        let (_, data) = Editor::change_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::Down,
+            0,
+        );
+        assert_eq!("a {\n    \n    c;\n}", buffer.slice_to_cow(0..buffer.len()));
+        let data = data.unwrap();
+        assert_eq!("    b;\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+        assert_eq!(CursorMode::Insert(Selection::caret(8)), cursor.mode);
+
+        // Over several lines, down to the last one
+        let mut cursor = Cursor::new(CursorMode::Normal(10), None, None);
+        Editor::change_motion(&mut cursor, &mut buffer, None, &Movement::Down, 2);
+        assert_eq!("a {\n    \n    ", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(13)), cursor.mode);
+    }
+
+    #[test]
+    fn test_select_paragraph() {
+        let buffer = Buffer::new("a\nb\n\n\nc\nd\n\ne");
+        let select = |offset: usize, inner: bool| {
+            let mut cursor = Cursor::new(
+                CursorMode::Insert(Selection::caret(offset)),
+                None,
+                None,
+            );
+            Editor::select_paragraph(&mut cursor, &buffer, inner);
+            cursor.edit_selection(&buffer)
+        };
+
+        // At the start of the buffer
+        assert_eq!(Selection::region(0, 4), select(1, true));
+        assert_eq!(Selection::region(0, 6), select(1, false));
+
+        // In the middle
+        assert_eq!(Selection::region(6, 10), select(8, true));
+        assert_eq!(Selection::region(6, 11), select(8, false));
+
+        // On a blank line between paragraphs
+        assert_eq!(Selection::region(4, 6), select(5, true));
+        assert_eq!(Selection::region(4, 10), select(5, false));
+
+        // At the end of the buffer, taking the blank lines before it
+        assert_eq!(Selection::region(11, 12), select(12, true));
+        assert_eq!(Selection::region(10, 12), select(12, false));
+    }
+
+    #[test]
+    fn test_select_paragraph_visual() {
+        let buffer = Buffer::new("a\nb\n\nc");
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        Editor::select_paragraph(&mut cursor, &buffer, false);
+        assert_eq!(
+            CursorMode::Visual {
+                start: 0,
+                end: 4,
+                mode: VisualMode::Linewise
+            },
+            cursor.mode
+        );
+        assert_eq!(Selection::region(0, 5), cursor.edit_selection(&buffer));
+    }
+
+    #[test]
+    fn test_select_word_object() {
+        let buffer = Buffer::new("let abc = foo(x) ;\nb");
+        let select = |offset: usize, around: bool| {
+            let mut cursor = Cursor::new(
+                CursorMode::Insert(Selection::caret(offset)),
+                None,
+                None,
+            );
+            Editor::select_word_object(&mut cursor, &buffer, around);
+            cursor.edit_selection(&buffer)
+        };
+
+        // In the middle of a word
+        assert_eq!(Selection::region(4, 7), select(5, false));
+        assert_eq!(Selection::region(4, 8), select(5, true));
+
+        // On punctuation
+        assert_eq!(Selection::region(13, 14), select(13, false));
+        assert_eq!(Selection::region(15, 16), select(15, false));
+        assert_eq!(Selection::region(15, 17), select(15, true));
+
+        // On whitespace between words
+        assert_eq!(Selection::region(7, 8), select(7, false));
+        assert_eq!(Selection::region(7, 9), select(7, true));
+
+        // Without whitespace after the word, the one before it is taken
+        assert_eq!(Selection::region(16, 18), select(17, true));
+        assert_eq!(Selection::region(19, 20), select(19, true));
+    }
+
+    #[test]
+    fn test_select_word_object_visual() {
+        let buffer = Buffer::new("ab cd");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        Editor::select_word_object(&mut cursor, &buffer, true);
+        assert_eq!(
+            CursorMode::Visual {
+                start: 2,
+                end: 4,
+                mode: VisualMode::Normal
+            },
+            cursor.mode
+        );
+    }
+
+    #[test]
+    fn test_replace_char() {
+        let mut buffer = Buffer::new("abc\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        Editor::replace_char(&mut cursor, &mut buffer, 'x');
+        assert_eq!("axc\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+
+        // Nothing to replace at the end of a line
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        assert!(Editor::replace_char(&mut cursor, &mut buffer, 'x').is_empty());
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(3));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::replace_char(&mut cursor, &mut buffer, '-');
+        assert_eq!("-x-\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(0));
+        expected.add_region(SelRegion::caret(2));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_replace_char_newline() {
+        let mut buffer = Buffer::new("ab cd");
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        Editor::replace_char(&mut cursor, &mut buffer, '\n');
+        assert_eq!("ab\ncd", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+    }
+
+    #[test]
+    fn test_block_insert() {
+        let mut buffer = Buffer::new("abc\ndef\nghi\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 1,
+                end: 9,
+                mode: VisualMode::Blockwise,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertFirstNonBlank);
+        Editor::insert(&mut cursor, &mut buffer, "-", None);
+        Editor::insert(&mut cursor, &mut buffer, "-", None);
+        assert_eq!(
+            "a--bc\nd--ef\ng--hi\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_block_append() {
+        let mut buffer = Buffer::new("abc\nd\nghi\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 1,
+                end: 7,
+                mode: VisualMode::Blockwise,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Append);
+        Editor::insert(&mut cursor, &mut buffer, "|", None);
+        assert_eq!("ab|c\nd |\ngh|i\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Appending at the end of every line
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 8,
+                mode: VisualMode::Blockwise,
+            },
+            None,
+            None,
+        );
+        cursor.horiz = Some(ColPosition::End);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Append);
+        Editor::insert(&mut cursor, &mut buffer, ";", None);
+        assert_eq!("ab|c;\nd |;\ngh|i\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_tab() {
+        // At the start of a line and in leading whitespace
+        let mut buffer = Buffer::new("a\n  b\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(4));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertTab);
+        assert_eq!("    a\n    b\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(4));
+        expected.add_region(SelRegion::caret(10));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // In the middle of a line, aligned to the next indentation stop
+        let mut buffer = Buffer::new("abc");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertTab);
+        assert_eq!("a   bc", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+
+        // With tabs
+        let mut buffer = Buffer::new("\ta\n\tb\n");
+        buffer.detect_indent(None);
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertTab);
+        assert_eq!("\t\ta\n\tb\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_tab_lines() {
+        let mut buffer = Buffer::new("a\n  b\nc\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 7)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertTab);
+        assert_eq!(
+            "    a\n    b\n    c\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertBackTab);
+        assert_eq!("a\nb\nc\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Lines shared by regions are indented once, and a selection ending
+        // at the start of a line leaves that line alone
+        let mut buffer = Buffer::new("ab cd\nef\ngh\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 2, None));
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::new(4, 9, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertTab);
+        assert_eq!(
+            "    ab cd\n    ef\ngh\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(4, 6, None));
+        expected.add_region(SelRegion::caret(7));
+        expected.add_region(SelRegion::new(8, 17, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // A caret outdents its own line
+        let mut buffer = Buffer::new("a\n      b\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(9)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::InsertBackTab);
+        assert_eq!("a\n    b\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(7)), cursor.mode);
+    }
+
+    #[test]
+    fn test_exchange_regions() {
+        let mut buffer = Buffer::new("f(abc, def)");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(2, 5, None));
+        selection.add_region(SelRegion::new(7, 10, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ExchangeRegions);
+        assert_eq!("f(def, abc)", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(2, 5, None));
+        expected.add_region(SelRegion::new(7, 10, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_exchange_regions_different_length() {
+        let mut buffer = Buffer::new("f(a, bcdef, g)");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(2, 3, None));
+        selection.add_region(SelRegion::new(5, 10, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ExchangeRegions);
+        assert_eq!("f(bcdef, a, g)", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(2, 7, None));
+        expected.add_region(SelRegion::new(9, 10, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Swapping back restores the text in one undo step
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ExchangeRegions);
+        assert_eq!("f(a, bcdef, g)", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!("f(bcdef, a, g)", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_join_regions_with() {
+        let mut buffer = Buffer::new("x\n  a,\n  b \n  c\ny");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(9));
+        selection.add_region(SelRegion::caret(15));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::join_regions_with(&mut cursor, &mut buffer, ", ", true);
+        assert_eq!("x\n  a,, b, c\ny", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(12)), cursor.mode);
+
+        let mut buffer = Buffer::new("a \n b\n c");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(8));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::join_regions_with(&mut cursor, &mut buffer, ", ", false);
+        assert_eq!("a ,  b,  c", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(10)), cursor.mode);
+    }
+
+    #[test]
+    fn test_align_on() {
+        let mut buffer = Buffer::new("a = 1;\nbcd = 2;\n// no\nef = 3;\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 27)), None, None);
+        Editor::align_on(&mut cursor, &mut buffer, '=');
+        assert_eq!(
+            "a   = 1;\nbcd = 2;\n// no\nef  = 3;\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        // Already aligned
+        assert!(Editor::align_on(&mut cursor, &mut buffer, '=').is_empty());
+    }
+
+    #[test]
+    fn test_yank_visual() {
+        let buffer = Buffer::new("abc\ndef\nghi");
+        let visual = |start: usize, end: usize, mode: VisualMode| {
+            Cursor::new(CursorMode::Visual { start, end, mode }, None, None)
+        };
+
+        let mut cursor = visual(5, 1, VisualMode::Normal);
+        let data = Editor::yank(&mut cursor, &buffer);
+        assert_eq!("bc\nde", data.content);
+        assert_eq!(VisualMode::Normal, data.mode);
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+
+        let mut cursor = visual(5, 9, VisualMode::Linewise);
+        let data = Editor::yank(&mut cursor, &buffer);
+        assert_eq!("def\nghi\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+        assert_eq!(CursorMode::Normal(5), cursor.mode);
+
+        let mut cursor = visual(1, 10, VisualMode::Blockwise);
+        let data = Editor::yank(&mut cursor, &buffer);
+        assert_eq!("bc\nef\nhi\n", data.content);
+        assert_eq!(VisualMode::Blockwise, data.mode);
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+    }
+
+    #[test]
+    fn test_undo_restores_cursor() {
+        let mut buffer = Buffer::new("abc\ndef\nghi\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(5, 7)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteLine);
+        assert_eq!("abc\nghi\n", buffer.slice_to_cow(0..buffer.len()));
+        let after = cursor.mode.clone();
+
+        // Moving the cursor elsewhere doesn't matter to undo
+        cursor.mode = CursorMode::Insert(Selection::caret(0));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!("abc\ndef\nghi\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(5, 7)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Redo);
+        assert_eq!("abc\nghi\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(after, cursor.mode);
+    }
+
+    #[test]
+    fn test_undo_restores_cursor_multiple_deltas() {
+        // Moving lines makes several deltas within one undo group
+        let mut buffer = Buffer::new("a\nb\nc\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::MoveLineDown);
+        assert_eq!("b\na\nc\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(2)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!("a\nb\nc\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(0)), cursor.mode);
+    }
+
+    #[test]
+    fn test_retab() {
+        let mut buffer = Buffer::new("a {\n\tb(\"\t\");\n\t\t c;\n  \td;\n}");
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::region(0, buffer.len())),
+            None,
+            None,
+        );
+        Editor::retab(&mut cursor, &mut buffer, false, 4);
+        assert_eq!(
+            "a {\n    b(\"\t\");\n         c;\n    d;\n}",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(
+            CursorMode::Insert(Selection::region(0, buffer.len())),
+            cursor.mode
+        );
+
+        Editor::retab(&mut cursor, &mut buffer, true, 4);
+        assert_eq!(
+            "a {\n\tb(\"\t\");\n\t\t c;\n\td;\n}",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_retab_selection_command() {
+        let mut buffer = Buffer::new("\ta\n\tb\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        let options = EditOptions {
+            tab_width: 2,
+            ..Default::default()
+        };
+        let retab = |cursor: &mut Cursor, buffer: &mut Buffer, cmd| {
+            Editor::do_edit_with_options(
+                cursor,
+                buffer,
+                &cmd,
+                None,
+                &mut TestClipboard::default(),
+                false,
+                &mut Register::default(),
+                &options,
+            );
+        };
+        retab(
+            &mut cursor,
+            &mut buffer,
+            EditCommand::RetabSelectionToSpaces,
+        );
+        assert_eq!("  a\n\tb\n", buffer.slice_to_cow(0..buffer.len()));
+        retab(&mut cursor, &mut buffer, EditCommand::RetabSelectionToTabs);
+        assert_eq!("\ta\n\tb\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_match_pairs_motion() {
+        let buffer = Buffer::new("f(a, [b]) + c");
+        let motion = |offset: usize| {
+            Editor::motion_offset(&buffer, None, offset, &Movement::MatchPairs, 1)
+        };
+        // From an opening and a closing bracket
+        assert_eq!(8, motion(1));
+        assert_eq!(1, motion(8));
+        assert_eq!(7, motion(5));
+        // Right after a bracket
+        assert_eq!(1, motion(9));
+        // No bracket around the caret
+        assert_eq!(12, motion(12));
+
+        // Deleting over it takes both brackets
+        let mut buffer = Buffer::new("f(a, [b]) + c");
+        let mut cursor = Cursor::new(CursorMode::Normal(8), None, None);
+        Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::MatchPairs,
+            1,
+        );
+        assert_eq!("f + c", buffer.slice_to_cow(0..buffer.len()));
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let (deltas, _) = Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::MatchPairs,
+            1,
+        );
+        assert!(deltas.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_match_pairs_motion_with_syntax() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let text = "f(\")\", [b]) + c";
+        let mut buffer = Buffer::new(text);
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        let motion = |offset: usize, syntax| {
+            Editor::motion_offset(&buffer, syntax, offset, &Movement::MatchPairs, 1)
+        };
+        // The bracket inside the string only matches without a syntax tree
+        assert_eq!(3, motion(1, None));
+        assert_eq!(10, motion(1, Some(&syntax)));
+
+        let mut cursor = Cursor::new(CursorMode::Normal(1), None, None);
+        Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            Some(&syntax),
+            &Movement::MatchPairs,
+            1,
+        );
+        assert_eq!("f + c", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_goto() {
+        let buffer = Buffer::new("abc\nnaïve e\u{301}t\u{e9}\nxy");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        Editor::goto(&mut cursor, &buffer, 1, 4);
+        assert_eq!(CursorMode::Normal(9), cursor.mode);
+        // A combining character is part of the previous grapheme
+        Editor::goto(&mut cursor, &buffer, 1, 7);
+        assert_eq!(CursorMode::Normal(14), cursor.mode);
+
+        // Past the end of the line
+        Editor::goto(&mut cursor, &buffer, 1, 20);
+        assert_eq!(CursorMode::Normal(15), cursor.mode);
+        let mut insert_cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        Editor::goto(&mut insert_cursor, &buffer, 1, 20);
+        assert_eq!(CursorMode::Insert(Selection::caret(17)), insert_cursor.mode);
+
+        // Past the end of the buffer
+        Editor::goto(&mut cursor, &buffer, 10, 1);
+        assert_eq!(CursorMode::Normal(19), cursor.mode);
+    }
+
+    #[test]
+    fn test_smart_home() {
+        let buffer = Buffer::new("    abc\ndef\n");
+        let home = |offset: usize| {
+            let mut cursor = Cursor::new(
+                CursorMode::Insert(Selection::caret(offset)),
+                None,
+                None,
+            );
+            Editor::smart_home(&mut cursor, &buffer);
+            cursor.offset()
+        };
+
+        // In the indentation
+        assert_eq!(4, home(2));
+        // At the first non-blank character
+        assert_eq!(0, home(4));
+        // At the start of the line
+        assert_eq!(4, home(0));
+        // Further in the line
+        assert_eq!(4, home(6));
+        // Without indentation, both are the same
+        assert_eq!(8, home(10));
+        assert_eq!(8, home(8));
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(6));
+        selection.add_region(SelRegion::caret(10));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::smart_home(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(4));
+        expected.add_region(SelRegion::caret(8));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_toggle_block_comment() {
+        let mut buffer = Buffer::new("a\n  b;\n  c;\nd\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(2, 12)), None, None);
+        Editor::toggle_block_comment(&mut cursor, &mut buffer, "/*", "*/");
+        assert_eq!(
+            "a\n  /* b;\n  c; */\nd\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::region(2, 18)), cursor.mode);
+
+        Editor::toggle_block_comment(&mut cursor, &mut buffer, "/*", "*/");
+        assert_eq!("a\n  b;\n  c;\nd\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(2, 12)), cursor.mode);
+    }
+
+    #[test]
+    fn test_toggle_block_comment_line() {
+        let mut buffer = Buffer::new("  a /* b */\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        // An existing comment inside just gets wrapped
+        Editor::toggle_block_comment(&mut cursor, &mut buffer, "/*", "*/");
+        assert_eq!("  /* a /* b */ */\n", buffer.slice_to_cow(0..buffer.len()));
+
+        Editor::toggle_block_comment(&mut cursor, &mut buffer, "/*", "*/");
+        assert_eq!("  a /* b */\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("<p>\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        Editor::toggle_block_comment(&mut cursor, &mut buffer, "<!--", "-->");
+        assert_eq!("<!-- <p> -->\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_toggle_block_comment_command() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let mut buffer = Buffer::new("let a = 1;\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        let syntax = Syntax::from_language(LapceLanguage::Rust);
+        Editor::do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::ToggleBlockComment,
+            Some(&syntax),
+            &mut TestClipboard::default(),
+            true,
+            &mut Register::default(),
+        );
+        assert_eq!("/* let a = 1; */\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_preview_insert() {
+        use crate::buffer::delta_to_changes;
+
+        let mut buffer = Buffer::new("fn a() {}\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::new(7, 9, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        let rev = buffer.rev();
+        let atomic_rev = buffer.atomic_rev();
+        let before = cursor.clone();
+
+        let preview = Editor::preview_insert(&cursor, &buffer, "x", None);
+        assert_eq!("fn a() {}\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(rev, buffer.rev());
+        assert_eq!(rev, atomic_rev.load(std::sync::atomic::Ordering::Acquire));
+        assert_eq!(before, cursor);
+
+        let text = buffer.text().clone();
+        let changes = delta_to_changes(&preview, &buffer);
+        let deltas = Editor::insert(&mut cursor, &mut buffer, "x", None);
+        assert_eq!(1, deltas.len());
+        assert_eq!(
+            String::from(preview.apply(&text)),
+            String::from(deltas[0].0.apply(&text))
+        );
+        assert_eq!(changes.len(), 2);
+        assert_eq!("fn a(x) x\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_paste_distributes_lines() {
+        let mut buffer = Buffer::new("a\nb\nc\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::new(4, 5, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        let data = RegisterData {
+            content: "1\n22\n333\n".to_string(),
+            mode: VisualMode::Normal,
+        };
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!("a1\nb22\n333\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(2));
+        expected.add_region(SelRegion::caret(6));
+        expected.add_region(SelRegion::caret(10));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Counts that don't match paste everything at each caret
+        let mut buffer = Buffer::new("a\nb\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(3));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        let data = RegisterData {
+            content: "x\ny\nz".to_string(),
+            mode: VisualMode::Normal,
+        };
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!("ax\ny\nz\nbx\ny\nz\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Whole lines copied from two carets come back one per caret
+        let mut buffer = Buffer::new("x\ny\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(3));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::ClipboardPaste,
+            None,
+            &mut TestClipboard(Some("a\nb\n".to_string())),
+            false,
+            &mut Register::default(),
+        );
+        assert_eq!("xa\nyb\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_rotate_selections() {
+        let mut buffer = Buffer::new("[one, two, three]");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 4, None));
+        selection.add_region(SelRegion::new(6, 9, None));
+        selection.add_region(SelRegion::new(11, 16, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::RotateSelectionsForward,
+        );
+        assert_eq!("[three, one, two]", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(1, 6, None));
+        expected.add_region(SelRegion::new(8, 11, None));
+        expected.add_region(SelRegion::new(13, 16, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::RotateSelectionsBackward,
+        );
+        assert_eq!("[one, two, three]", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::RotateSelectionsBackward,
+        );
+        assert_eq!("[two, three, one]", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(1, 4, None));
+        expected.add_region(SelRegion::new(6, 11, None));
+        expected.add_region(SelRegion::new(13, 16, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_select_indent_block() {
+        let buffer = Buffer::new(
+            "import os\n\ndef f(x):\n    if x:\n        return 1\n\n    return 2\n\nprint(f(1))\n",
+        );
+
+        // A caret in the function body selects the whole body, blank line
+        // included
+        let mut cursor = Cursor::new(CursorMode::Normal(23), None, None);
+        Editor::select_indent_block(&mut cursor, &buffer, true);
+        assert_eq!(
+            CursorMode::Visual {
+                start: buffer.offset_of_line(3),
+                end: buffer.offset_of_line(6),
+                mode: VisualMode::Linewise,
+            },
+            cursor.mode
+        );
+
+        let mut cursor = Cursor::new(CursorMode::Normal(23), None, None);
+        Editor::select_indent_block(&mut cursor, &buffer, false);
+        assert_eq!(
+            CursorMode::Visual {
+                start: buffer.offset_of_line(2),
+                end: buffer.offset_of_line(6),
+                mode: VisualMode::Linewise,
+            },
+            cursor.mode
+        );
+
+        // Deeper lines only select their own block
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::caret(buffer.offset_of_line(4))),
+            None,
+            None,
+        );
+        Editor::select_indent_block(&mut cursor, &buffer, false);
+        assert_eq!(
+            CursorMode::Insert(Selection::region(
+                buffer.offset_of_line(3),
+                buffer.offset_of_line(5)
+            )),
+            cursor.mode
+        );
+    }
+
+    #[test]
+    fn test_split_line() {
+        let mut buffer = Buffer::new("    let a = 1; let b = 2;\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(14)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SplitLine);
+        assert_eq!(
+            "    let a = 1;\n    let b = 2;\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(14)), cursor.mode);
+
+        // Every caret splits its line and stays put
+        let mut buffer = Buffer::new("ab\ncd\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(4));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SplitLine);
+        assert_eq!("a\nb\nc\nd\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(1));
+        expected.add_region(SelRegion::caret(5));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Carets in the same run of whitespace
+        let mut buffer = Buffer::new("a  b");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(2));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SplitLine);
+        assert_eq!("a\n\nb", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(1));
+        expected.add_region(SelRegion::caret(2));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_reflow() {
+        let mut buffer = Buffer::new(
+            "  the quick brown fox jumps over the lazy dog\n\nnext paragraph\n",
+        );
+        let mut cursor = Cursor::new(CursorMode::Normal(10), None, None);
+        Editor::reflow(&mut cursor, &mut buffer, 20);
+        assert_eq!(
+            "  the quick brown\n  fox jumps over the\n  lazy dog\n\nnext paragraph\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(2), cursor.mode);
+
+        // Selected lines are joined before being wrapped again, each
+        // paragraph on its own
+        let mut buffer = Buffer::new("a b\nc\n\nd\ne f g\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 14)), None, None);
+        Editor::reflow(&mut cursor, &mut buffer, 3);
+        assert_eq!("a b\nc\n\nd e\nf g\n", buffer.slice_to_cow(0..buffer.len()));
+        assert!(Editor::reflow(&mut cursor, &mut buffer, 3).is_empty());
+    }
+
+    #[test]
+    fn test_yank_appending_to_register() {
+        let mut buffer = Buffer::new("a\nb\nc\n");
+        let mut register = Register::default();
+        let linewise = |line: usize| {
+            Cursor::new(
+                CursorMode::Visual {
+                    start: line * 2,
+                    end: line * 2,
+                    mode: VisualMode::Linewise,
+                },
+                None,
+                None,
+            )
+        };
+
+        let a = RegisterSelector::Named('a');
+        Editor::yank_to_register(&mut linewise(0), &buffer, &mut register, a);
+        Editor::yank_to_register(
+            &mut linewise(2),
+            &buffer,
+            &mut register,
+            RegisterSelector::Named('A'),
+        );
+        let data = register.get(a).unwrap();
+        assert_eq!("a\nc\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+
+        let mut cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        Editor::paste_from_register(&mut cursor, &mut buffer, &register, a);
+        assert_eq!("a\nb\na\nc\nc\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_insert_snippet() {
+        let mut buffer = Buffer::new("a\nb\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        let (_, tabs) =
+            Editor::insert_snippet(&mut cursor, &mut buffer, "(${1:x}, $2)$0");
+        assert_eq!("a(x, )\nb\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(
+            vec![
+                Selection::region(2, 3),
+                Selection::caret(5),
+                Selection::caret(6)
+            ],
+            tabs
+        );
+        assert_eq!(CursorMode::Insert(Selection::region(2, 3)), cursor.mode);
+
+        // Repeated stops and several carets give linked regions
+        let mut buffer = Buffer::new("\n\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(1));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        let (_, tabs) =
+            Editor::insert_snippet(&mut cursor, &mut buffer, "<${1:p}>\\$</$1>");
+        assert_eq!("<p>$</p>\n<p>$</p>\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(1, 2, None));
+        expected.add_region(SelRegion::new(6, 7, None));
+        expected.add_region(SelRegion::new(10, 11, None));
+        expected.add_region(SelRegion::new(15, 16, None));
+        assert_eq!(vec![expected], tabs);
+    }
+
+    #[test]
+    fn test_linked_editing() {
+        let mut buffer = Buffer::new("\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        Editor::insert_snippet(&mut cursor, &mut buffer, "<${1:p}>$0</$1>");
+        assert_eq!("<p></p>\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Typing over one occurrence of the placeholder changes both, with
+        // the caret staying in the one typed in
+        cursor.mode = CursorMode::Insert(Selection::region(1, 2));
+        Editor::insert(&mut cursor, &mut buffer, "d", None);
+        Editor::insert(&mut cursor, &mut buffer, "i", None);
+        assert_eq!("<di></di>\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+        Editor::insert(&mut cursor, &mut buffer, "v", None);
+        assert_eq!("<dv></dv>\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Moving off the placeholder ends the linkage
+        cursor.mode = CursorMode::Insert(Selection::caret(4));
+        Editor::insert(&mut cursor, &mut buffer, "x", None);
+        assert_eq!("<dv>x</dv>\n", buffer.slice_to_cow(0..buffer.len()));
+        assert!(cursor.linked_regions.is_empty());
+        cursor.mode = CursorMode::Insert(Selection::caret(2));
+        Editor::insert(&mut cursor, &mut buffer, "y", None);
+        assert_eq!("<dyv>x</dv>\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Deleting out of the placeholder isn't mirrored and ends the linkage
+        let mut buffer = Buffer::new("\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        Editor::insert_snippet(&mut cursor, &mut buffer, "<${1:p}>$0</$1>");
+        cursor.mode = CursorMode::Insert(Selection::caret(1));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+        assert_eq!("p></p>\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(0)), cursor.mode);
+        assert!(cursor.linked_regions.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_copy_line_as_comment() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let syntax = Syntax::from_language(LapceLanguage::Rust);
+        let mut buffer = Buffer::new("fn f() {\n    g(1);\n}");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(15)), None, None);
+        Editor::do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::CopyLineAsComment,
+            Some(&syntax),
+            &mut TestClipboard::default(),
+            false,
+            &mut Register::default(),
+        );
+        assert_eq!(
+            "fn f() {\n    g(1);\n    // g(1);\n}",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(15)), cursor.mode);
+
+        // The last line without a line ending
+        let mut cursor = Cursor::new(CursorMode::Normal(32), None, None);
+        Editor::copy_line_as_comment(&mut cursor, &mut buffer, "//");
+        assert_eq!(
+            "fn f() {\n    g(1);\n    // g(1);\n}\n// }",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(32), cursor.mode);
+
+        // Without a comment token the line is duplicated
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::copy_line_as_comment(&mut cursor, &mut buffer, "");
+        assert_eq!(
+            "fn f() {\nfn f() {\n    g(1);\n    // g(1);\n}\n// }",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_newline_expands_between_pairs() {
+        let mut buffer = Buffer::new("f(1, [])\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(6)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "\n", None);
+        assert_eq!("f(1, [\n    \n])\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(11)), cursor.mode);
+
+        let mut buffer = Buffer::new("f()\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+        Editor::insert(&mut cursor, &mut buffer, "\n", None);
+        assert_eq!("f(\n    \n)\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // Pairs opted out of expansion, and extra ones like HTML tags
+        let options = AutoPairOptions {
+            newline_pairs: vec![(">".to_string(), "</".to_string())],
+            no_newline_expansion: ['('].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut buffer = Buffer::new("  <p></p>\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(5)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "\n", None, &options);
+        assert_eq!(
+            "  <p>\n      \n  </p>\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(12)), cursor.mode);
+
+        let mut buffer = Buffer::new("f()\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(2)), None, None);
+        Editor::insert_with_options(&mut cursor, &mut buffer, "\n", None, &options);
+        assert_eq!("f(\n    )\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_overtype() {
+        let mut buffer = Buffer::new("abcd\nx\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleOvertype);
+        assert!(cursor.overtype);
+
+        Editor::insert(&mut cursor, &mut buffer, "X", None);
+        Editor::insert(&mut cursor, &mut buffer, "Y", None);
+        assert_eq!("aXYd\nx\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        // At the end of the line the text is inserted
+        Editor::insert(&mut cursor, &mut buffer, "Z", None);
+        Editor::insert(&mut cursor, &mut buffer, "W", None);
+        assert_eq!("aXYZW\nx\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(5)), cursor.mode);
+
+        // Backspace moves left without deleting
+        do_edit(&mut cursor, &mut buffer, &EditCommand::DeleteBackward);
+        assert_eq!("aXYZW\nx\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleOvertype);
+        Editor::insert(&mut cursor, &mut buffer, "V", None);
+        assert_eq!("aXYZVW\nx\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_toggle_list_layout() {
+        let mut buffer = Buffer::new("    let a = [1,  2 ,3];\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(13, 21)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleListLayout);
+        assert_eq!(
+            "    let a = [1,\n    2,\n    3];\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::region(13, 28)), cursor.mode);
+
+        // Back onto a single line
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleListLayout);
+        assert_eq!(
+            "    let a = [1, 2, 3];\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::region(13, 20)), cursor.mode);
+
+        // Whole lines keep their indentation and line ending
+        let mut buffer = Buffer::new("  a,\n  b,\n  c\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 12,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleListLayout);
+        assert_eq!("  a, b, c\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
+
+    #[test]
+    fn test_paste_empty_register() {
+        let mut buffer = Buffer::new("abc\n");
+        let rev = buffer.rev();
+        for mode in [
+            VisualMode::Normal,
+            VisualMode::Linewise,
+            VisualMode::Blockwise,
+        ] {
+            let data = RegisterData {
+                content: String::new(),
+                mode,
+            };
+            for cursor_mode in [
+                CursorMode::Normal(1),
+                CursorMode::Insert(Selection::region(1, 2)),
+            ] {
+                let mut cursor = Cursor::new(cursor_mode, None, None);
+                let before = cursor.clone();
+                let deltas =
+                    Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+                assert!(deltas.is_empty());
+                assert_eq!(before, cursor);
+            }
+        }
+        assert_eq!("abc\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(rev, buffer.rev());
+    }
+
+    #[test]
+    fn test_trim_final_newlines() {
+        let mut buffer = Buffer::new("a\nb\n\n  \n\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(7), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TrimFinalNewlines);
+        assert_eq!("a\nb\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(2), cursor.mode);
+
+        // Once undone, the blank lines come back at once
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!("a\nb\n\n  \n\n", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(8)), None, None);
+        Editor::trim_final_newlines(&mut cursor, &mut buffer, false);
+        assert_eq!("a\nb", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+
+        // Nothing to trim
+        assert!(
+            Editor::trim_final_newlines(&mut cursor, &mut buffer, false).is_empty()
+        );
+        let mut buffer = Buffer::new("a\r\n\r\n");
+        Editor::trim_final_newlines(&mut cursor, &mut buffer, true);
+        assert_eq!("a\r\n", buffer.slice_to_cow(0..buffer.len()));
+
+        // A cursor before the blank lines stays put
+        let mut buffer = Buffer::new("ab\n\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::trim_final_newlines(&mut cursor, &mut buffer, true);
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
+
+    #[test]
+    fn test_shift_line() {
+        let mut buffer = Buffer::new("    foo bar\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(9)), None, None);
+        Editor::shift_line_right(&mut cursor, &mut buffer);
+        assert_eq!("        foo bar\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(13)), cursor.mode);
+
+        Editor::shift_line_left(&mut cursor, &mut buffer);
+        Editor::shift_line_left(&mut cursor, &mut buffer);
+        assert_eq!("foo bar\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(5)), cursor.mode);
+
+        // Nothing left to outdent
+        Editor::shift_line_left(&mut cursor, &mut buffer);
+        assert_eq!("foo bar\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(5)), cursor.mode);
+
+        // Tab indented buffers shift by tabs
+        let mut buffer = Buffer::new("\tfoo\n\tbar\n");
+        buffer.detect_indent(None);
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        Editor::shift_line_right(&mut cursor, &mut buffer);
+        assert_eq!("\t\tfoo\n\tbar\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+        Editor::shift_line_left(&mut cursor, &mut buffer);
+        assert_eq!("\tfoo\n\tbar\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(3)), cursor.mode);
+    }
+
+    #[test]
+    fn test_select_around_pair() {
+        let buffer = Buffer::new("f(foo, (bar)) /* a /* b */ c */");
+        //                      ->0123456789012345678901234567890<-
+
+        let cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let selection = Editor::select_around_pair(&cursor, &buffer, None, "(", ")");
+        assert_eq!(Some(Selection::region(1, 13)), selection);
+
+        let cursor = Cursor::new(CursorMode::Normal(9), None, None);
+        let selection = Editor::select_around_pair(&cursor, &buffer, None, "(", ")");
+        assert_eq!(Some(Selection::region(7, 12)), selection);
+
+        // Multi-character delimiters, with a nested pair skipped over
+        let cursor = Cursor::new(CursorMode::Normal(17), None, None);
+        let selection =
+            Editor::select_around_pair(&cursor, &buffer, None, "/*", "*/");
+        assert_eq!(Some(Selection::region(14, 31)), selection);
+        let cursor = Cursor::new(CursorMode::Normal(22), None, None);
+        let selection =
+            Editor::select_around_pair(&cursor, &buffer, None, "/*", "*/");
+        assert_eq!(Some(Selection::region(19, 26)), selection);
+        let cursor = Cursor::new(CursorMode::Normal(19), None, None);
+        let selection =
+            Editor::select_around_pair(&cursor, &buffer, None, "/*", "*/");
+        assert_eq!(Some(Selection::region(19, 26)), selection);
+
+        let cursor = Cursor::new(CursorMode::Normal(2), None, None);
+        let selection =
+            Editor::select_around_pair(&cursor, &buffer, None, "/*", "*/");
+        assert_eq!(None, selection);
+    }
+
+    #[test]
+    fn test_find_char() {
+        let buffer = Buffer::new("a.b.c.d\n.x\n");
+        let search = |c, forward, till| CharSearch { c, forward, till };
+
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::find_char(&mut cursor, &buffer, search('.', true, false), 1);
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+        Editor::find_char(&mut cursor, &buffer, search('.', true, false), 2);
+        assert_eq!(CursorMode::Normal(5), cursor.mode);
+        // Confined to the line
+        Editor::find_char(&mut cursor, &buffer, search('.', true, false), 1);
+        assert_eq!(CursorMode::Normal(5), cursor.mode);
+
+        Editor::find_char(&mut cursor, &buffer, search('.', false, false), 1);
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+        Editor::repeat_char_search(&mut cursor, &buffer, false, 1);
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+        Editor::repeat_char_search(&mut cursor, &buffer, true, 1);
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+
+        // Till stops next to the character, and repeating it moves on
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::find_char(&mut cursor, &buffer, search('c', true, true), 1);
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+        Editor::find_char(&mut cursor, &buffer, search('.', true, true), 1);
+        assert_eq!(CursorMode::Normal(4), cursor.mode);
+        Editor::repeat_char_search(&mut cursor, &buffer, false, 1);
+        assert_eq!(CursorMode::Normal(4), cursor.mode);
+        Editor::find_char(&mut cursor, &buffer, search('a', false, true), 1);
+        assert_eq!(CursorMode::Normal(1), cursor.mode);
+
+        // Visual mode extends the selection
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 0,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+        Editor::find_char(&mut cursor, &buffer, search('c', true, false), 1);
+        assert_eq!(
+            CursorMode::Visual {
+                start: 0,
+                end: 4,
+                mode: VisualMode::Normal,
+            },
+            cursor.mode
+        );
+
+        // As motions, forward ones take the character in
+        let mut buffer = Buffer::new("foo(bar) baz\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::FindChar(search(')', true, true)),
+            1,
+        );
+        assert_eq!(") baz\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::FindChar(search(')', false, false)),
+            1,
+        );
+        assert_eq!("z\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_comment_and_open_below() {
+        let mut buffer = Buffer::new("def f():\n    return 1\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(17), None, None);
+        Editor::comment_and_open_below(&mut cursor, &mut buffer, "#");
+        assert_eq!(
+            "def f():\n    # return 1\n    \n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(28)), cursor.mode);
+
+        // Without a comment token only the line is opened
+        let mut buffer = Buffer::new("  a");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(3)), None, None);
+        Editor::comment_and_open_below(&mut cursor, &mut buffer, "");
+        assert_eq!("  a\n  ", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(6)), cursor.mode);
+    }
+
+    #[test]
+    fn test_normalize_line_endings() {
+        let mut buffer = Buffer::new("a\r\nb\nc\r\nd");
+        let mut cursor = Cursor::new(CursorMode::Normal(8), None, None);
+        Editor::normalize_line_endings(&mut cursor, &mut buffer, LineEnding::Lf);
+        assert_eq!("a\nb\nc\nd", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(6), cursor.mode);
+
+        let mut buffer = Buffer::new("a\r\nb\nc\r\nd");
+        let mut cursor = Cursor::new(CursorMode::Normal(8), None, None);
+        Editor::normalize_line_endings(&mut cursor, &mut buffer, LineEnding::CrLf);
+        assert_eq!("a\r\nb\r\nc\r\nd", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(9), cursor.mode);
+
+        // Already normalized buffers are left alone
+        let deltas = Editor::normalize_line_endings(
+            &mut cursor,
+            &mut buffer,
+            LineEnding::CrLf,
+        );
+        assert!(deltas.is_empty());
+    }
+
+    #[test]
+    fn test_invert_selection() {
+        let buffer = Buffer::new("abc def ghi jkl mno");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(4, 7, None));
+        selection.add_region(SelRegion::new(12, 15, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::invert_selection(&mut cursor, &buffer, None);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 4, None));
+        expected.add_region(SelRegion::new(7, 12, None));
+        expected.add_region(SelRegion::new(15, 19, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Adjacent regions count as one
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(4, 7, None));
+        selection.add_region(SelRegion::new(7, 9, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::invert_selection(&mut cursor, &buffer, Some(2..11));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(2, 4, None));
+        expected.add_region(SelRegion::new(9, 11, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_expand_regions_to_words() {
+        let buffer = Buffer::new("foo bar.baz qux");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 6, None));
+        selection.add_region(SelRegion::new(10, 9, None));
+        selection.add_region(SelRegion::caret(13));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::expand_regions_to_words(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 7, None));
+        expected.add_region(SelRegion::new(11, 8, None));
+        expected.add_region(SelRegion::new(12, 15, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Selections already on word boundaries stay the same
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 4,
+                end: 6,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+        Editor::expand_regions_to_words(&mut cursor, &buffer);
+        assert_eq!(
+            CursorMode::Visual {
+                start: 4,
+                end: 6,
+                mode: VisualMode::Normal,
+            },
+            cursor.mode
+        );
+    }
+
+    #[test]
+    fn test_collapse_to_primary() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(8, 10, None));
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(4));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        assert!(Editor::collapse_to_primary(&mut cursor));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+        assert!(!Editor::collapse_to_primary(&mut cursor));
+    }
+
+    #[test]
+    fn test_carets_at_line_ends() {
+        let buffer = Buffer::new("abc\nde\nfghi\nj");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 1,
+                end: 8,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+        Editor::carets_at_line_ends(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(3));
+        expected.add_region(SelRegion::caret(6));
+        expected.add_region(SelRegion::caret(11));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 9,
+                mode: VisualMode::Blockwise,
+            },
+            None,
+            None,
+        );
+        Editor::carets_at_line_ends(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(3));
+        expected.add_region(SelRegion::caret(6));
+        expected.add_region(SelRegion::caret(10));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_split_selection_into_lines() {
+        let buffer = Buffer::new("abc\ndef\nghi\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(1, 10)), None, None);
+        Editor::split_selection_into_lines(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(1, 3, None));
+        expected.add_region(SelRegion::new(4, 7, None));
+        expected.add_region(SelRegion::new(8, 10, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // No empty region after a selection ending with a line break
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 8)), None, None);
+        Editor::split_selection_into_lines(&mut cursor, &buffer);
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 3, None));
+        expected.add_region(SelRegion::new(4, 7, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[cfg(feature = "lang-rust")]
+    #[test]
+    fn test_paste_and_reindent() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let syntax = Syntax::from_language(LapceLanguage::Rust);
+        let data = RegisterData {
+            content: "let a = {\n1\n};\n// (\n".to_string(),
+            mode: VisualMode::Linewise,
+        };
+        let mut buffer = Buffer::new("fn main() {\n    if x {\n    }\n}\n");
+        buffer.detect_indent(None);
+        let mut cursor = Cursor::new(CursorMode::Normal(14), None, None);
+        Editor::paste_and_reindent(&mut cursor, &mut buffer, &data, Some(&syntax));
+        assert_eq!(
+            "fn main() {\n    if x {\n        let a = {\n            1\n        };\n        // (\n    }\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        // A block closing more than it opens moves out of the scope
+        let data = RegisterData {
+            content: "    }\n    b();\n".to_string(),
+            mode: VisualMode::Linewise,
+        };
+        let mut buffer = Buffer::new("fn main() {\n    if x {\n}\n");
+        buffer.detect_indent(None);
+        let mut cursor = Cursor::new(CursorMode::Normal(14), None, None);
+        Editor::paste_and_reindent(&mut cursor, &mut buffer, &data, Some(&syntax));
+        assert_eq!(
+            "fn main() {\n    if x {\n    }\n    b();\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        // Without a syntax the block only gets shifted
+        let data = RegisterData {
+            content: "let a = {\n1\n};\n".to_string(),
+            mode: VisualMode::Linewise,
+        };
+        let mut buffer = Buffer::new("fn main() {\n    if x {\n    }\n}\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(14), None, None);
+        Editor::paste_and_reindent(&mut cursor, &mut buffer, &data, None);
+        assert_eq!(
+            "fn main() {\n    if x {\n    let a = {\n    1\n    };\n    }\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_insert_pair_with_wrapped_selection() {
+        let mut buffer = Buffer::new("abc def\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 3)), None, None);
+
+        Editor::insert(&mut cursor, &mut buffer, "(", None);
+        assert_eq!("(abc) def\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(1, 4)), cursor.mode);
+
+        // Typing the opening character again doesn't wrap twice
+        Editor::insert(&mut cursor, &mut buffer, "(", None);
+        assert_eq!("(abc) def\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(1, 4)), cursor.mode);
+
+        // The closing character steps over the end of the pair
+        Editor::insert(&mut cursor, &mut buffer, ")", None);
+        assert_eq!("(abc) def\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(5)), cursor.mode);
+    }
+
+    #[test]
+    fn test_paste_into_blank_line_reindent() {
+        let data = RegisterData {
+            content: "y();".to_string(),
+            mode: VisualMode::Normal,
+        };
+
+        let mut buffer = Buffer::new("fn a() {\n    x;\n\n}\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(16)), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, true);
+        assert_eq!(
+            "fn a() {\n    x;\n    y();\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(24)), cursor.mode);
+
+        let mut buffer = Buffer::new("fn a() {\n    x;\n\n}\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(16)), None, None);
+        Editor::do_paste(&mut cursor, &mut buffer, &data, false);
+        assert_eq!(
+            "fn a() {\n    x;\ny();\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_toggle_quotes() {
+        let mut buffer = Buffer::new("x = \"it's \\\"ok\\\"\"\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(13), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleQuotes);
+        assert_eq!(
+            "x = 'it\\'s \"ok\"'\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(13), cursor.mode);
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleQuotes);
+        assert_eq!(
+            "x = \"it's \\\"ok\\\"\"\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Normal(13), cursor.mode);
+
+        // Outside of a string nothing happens
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ToggleQuotes);
+        assert_eq!(
+            "x = \"it's \\\"ok\\\"\"\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[cfg(feature = "lang-rust")]
+    #[test]
+    fn test_toggle_quotes_syntax() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let mut buffer = Buffer::new("let s = \"a'b\";\n");
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        let mut cursor = Cursor::new(CursorMode::Normal(10), None, None);
+        Editor::toggle_quotes(&mut cursor, &mut buffer, Some(&syntax));
+        assert_eq!("let s = 'a\\'b';\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_delete_to_black_hole_register() {
+        let mut buffer = Buffer::new("abc\ndef\nghi\n");
+        let mut register = Register::default();
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 2,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+        Editor::yank_to_register(
+            &mut cursor,
+            &buffer,
+            &mut register,
+            RegisterSelector::Unnamed,
+        );
+
+        let mut cursor = Cursor::new(CursorMode::Normal(5), None, None);
+        Editor::delete_line_to_register(
+            &mut cursor,
+            &mut buffer,
+            &mut register,
+            RegisterSelector::BlackHole,
+        );
+        Editor::delete_motion_to_register(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::WordForward,
+            1,
+            &mut register,
+            RegisterSelector::BlackHole,
+        );
+        assert_eq!("abc\n\n", buffer.slice_to_cow(0..buffer.len()));
+
+        Editor::paste_from_register(
+            &mut cursor,
+            &mut buffer,
+            &register,
+            RegisterSelector::Unnamed,
+        );
+        assert_eq!("abc\nabc\n", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_word_under_cursor() {
+        let buffer = Buffer::new("let foo_bar = 1;");
+        let word = |offset| Editor::word_under_cursor(&buffer, offset);
+        assert_eq!(Some((4..11, "foo_bar".to_string())), word(6));
+        assert_eq!(Some((4..11, "foo_bar".to_string())), word(4));
+        assert_eq!(Some((4..11, "foo_bar".to_string())), word(11));
+        assert_eq!(Some((14..15, "1".to_string())), word(14));
+        assert_eq!(None, word(12));
+        assert_eq!(None, word(16));
+    }
+
+    #[test]
+    fn test_marks() {
+        let mut buffer = Buffer::new("abc def\n  ghi\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(12), None, None);
+        Editor::set_mark(&mut buffer, 'a', 4);
+
+        // The mark follows the text it was set on
+        buffer.edit(&[(Selection::caret(0), "xx")], EditType::InsertChars);
+        assert_eq!(Some(6), buffer.mark('a'));
+        assert!(Editor::jump_to_mark(&mut cursor, &mut buffer, 'a', false));
+        assert_eq!(CursorMode::Normal(6), cursor.mode);
+
+        // Jumping back to where the cursor was before
+        assert!(Editor::jump_to_mark(&mut cursor, &mut buffer, '\'', true));
+        assert_eq!(CursorMode::Normal(12), cursor.mode);
+        assert_eq!(Some(6), buffer.mark(Buffer::JUMP_MARK));
+
+        // A mark in deleted text moves to where the deletion was
+        buffer.edit(&[(Selection::region(5, 9), "")], EditType::Delete);
+        assert_eq!(Some(5), buffer.mark('a'));
+
+        assert!(!Editor::jump_to_mark(&mut cursor, &mut buffer, 'b', false));
+    }
+
+    #[test]
+    fn test_insert_repeated() {
+        let mut buffer = Buffer::new("ab");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+        Editor::insert_repeated(&mut cursor, &mut buffer, "-", 5);
+        assert_eq!("a-----b", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(6)), cursor.mode);
+
+        // Pairs are inserted as they are
+        Editor::insert_repeated(&mut cursor, &mut buffer, "(ab", 3);
+        assert_eq!("a-----(ab(ab(abb", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(15)), cursor.mode);
+
+        // A single undo brings back the text before
+        buffer.do_undo();
+        assert_eq!("a-----b", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        Editor::insert_repeated(&mut cursor, &mut buffer, "xy", 2);
+        assert_eq!("xyxya-----b", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(3), cursor.mode);
+    }
+
+    #[cfg(feature = "lang-rust")]
+    #[test]
+    fn test_select_enclosing_scope() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let buffer =
+            Buffer::new("fn outer() {\n    fn inner() {\n        a();\n    }\n}\n");
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(38)), None, None);
+        Editor::select_enclosing_scope(&mut cursor, &buffer, Some(&syntax));
+        assert_eq!(CursorMode::Insert(Selection::region(17, 48)), cursor.mode);
+
+        let mut cursor = Cursor::new(CursorMode::Normal(14), None, None);
+        Editor::select_enclosing_scope(&mut cursor, &buffer, Some(&syntax));
+        assert_eq!(
+            CursorMode::Visual {
+                start: 0,
+                end: 49,
+                mode: VisualMode::Normal,
+            },
+            cursor.mode
+        );
+
+        // On the name or the signature of a function, the function is
+        // selected rather than a part of its signature
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(4)), None, None);
+        Editor::select_enclosing_scope(&mut cursor, &buffer, Some(&syntax));
+        assert_eq!(CursorMode::Insert(Selection::region(0, 50)), cursor.mode);
+        let buffer = Buffer::new("async fn f(g: fn(u8)) {}\n");
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        for offset in [1, 17] {
+            let mut cursor = Cursor::new(
+                CursorMode::Insert(Selection::caret(offset)),
+                None,
+                None,
+            );
+            Editor::select_enclosing_scope(&mut cursor, &buffer, Some(&syntax));
+            assert_eq!(CursorMode::Insert(Selection::region(0, 24)), cursor.mode);
+        }
+
+        // Every caret gets its own scope, a block being one too
+        let buffer = Buffer::new(
+            "fn f() {\n    if x {\n        a();\n    }\n}\nfn g() {\n    b();\n}\n",
+        );
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(30));
+        selection.add_region(SelRegion::caret(55));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::select_enclosing_scope(&mut cursor, &buffer, Some(&syntax));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(18, 38, None));
+        expected.add_region(SelRegion::new(41, 60, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        // Without a syntax tree the indentation block gets selected
+        let buffer =
+            Buffer::new("fn outer() {\n    fn inner() {\n        a();\n    }\n}\n");
+        let mut cursor = Cursor::new(CursorMode::Normal(38), None, None);
+        Editor::select_enclosing_scope(&mut cursor, &buffer, None);
+        assert_eq!(
+            CursorMode::Visual {
+                start: 13,
+                end: 30,
+                mode: VisualMode::Linewise,
+            },
+            cursor.mode
+        );
+    }
+
+    #[test]
+    fn test_delete_backward_only_auto_inserted_pair() {
+        let mut buffer = Buffer::new("");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        let options = EditOptions {
+            auto_pairs: AutoPairOptions {
+                delete_only_auto_inserted_pairs: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let delete_backward = |cursor: &mut Cursor, buffer: &mut Buffer| {
+            Editor::do_edit_with_options(
+                cursor,
+                buffer,
+                &EditCommand::DeleteBackward,
+                None,
+                &mut TestClipboard::default(),
+                false,
+                &mut Register::default(),
+                &options,
+            );
+        };
+        Editor::insert_with_options(
+            &mut cursor,
+            &mut buffer,
+            "(",
+            None,
+            &options.auto_pairs,
+        );
+        assert_eq!(buffer.text().to_string(), "()");
+        delete_backward(&mut cursor, &mut buffer);
+        assert_eq!(buffer.text().to_string(), "");
+
+        Editor::insert_with_options(
+            &mut cursor,
+            &mut buffer,
+            "(",
+            None,
+            &options.auto_pairs,
+        );
+        Editor::insert_with_options(
+            &mut cursor,
+            &mut buffer,
+            "x",
+            None,
+            &options.auto_pairs,
+        );
+        delete_backward(&mut cursor, &mut buffer);
+        assert_eq!(buffer.text().to_string(), "()");
+        delete_backward(&mut cursor, &mut buffer);
+        assert_eq!(buffer.text().to_string(), ")");
+
+        // A pair put there by another edit is left as it is
+        let mut buffer = Buffer::new("");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        Editor::insert_with_options(
+            &mut cursor,
+            &mut buffer,
+            "(",
+            None,
+            &options.auto_pairs,
+        );
+        buffer.edit(&[(&Selection::region(0, 2), "[]")], EditType::Other);
+        delete_backward(&mut cursor, &mut buffer);
+        assert_eq!(buffer.text().to_string(), "]");
+    }
+
+    #[test]
+    fn test_title_case_selection() {
+        let mut buffer = Buffer::new("hello world");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 11)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TitleCaseSelection);
+        assert_eq!("Hello World", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("hELLO foo-BAR");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 13)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TitleCaseSelection);
+        assert_eq!("Hello Foo-Bar", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("DON'T stop, it's 'QUOTED'");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 25)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TitleCaseSelection);
+        assert_eq!(
+            "Don't Stop, It's 'Quoted'",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
+
+    #[test]
+    fn test_sentence_case_selection() {
+        let mut buffer = Buffer::new("fOO BAR. bAZ qux? one MORE");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 26)), None, None);
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::SentenceCaseSelection,
+        );
+        assert_eq!(
+            "Foo bar. Baz qux? One more",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+    }
 
-                        new_selection
-                    }
-                };
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.update_selection(buffer, selection);
-                vec![(delta, inval_lines)]
-            }
-            DeleteWordBackward => {
-                let selection = match cursor.mode {
-                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
-                        cursor.edit_selection(buffer)
-                    }
-                    CursorMode::Insert(_) => {
-                        let mut new_selection = Selection::new();
-                        let selection = cursor.edit_selection(buffer);
+    #[test]
+    fn test_insert_datetime() {
+        use chrono::{NaiveDate, TimeZone, Utc};
 
-                        for region in selection.regions() {
-                            let end = buffer.move_word_backward(region.end);
-                            let new_region = SelRegion::new(region.start, end, None);
-                            new_selection.add_region(new_region);
-                        }
+        let now = Utc.from_utc_datetime(
+            &NaiveDate::from_ymd_opt(2022, 5, 3)
+                .unwrap()
+                .and_hms_opt(14, 30, 7)
+                .unwrap(),
+        );
+        let mut buffer = Buffer::new("a\nb\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(3));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::insert_datetime_at(
+            &mut cursor,
+            &mut buffer,
+            " %Y-%m-%d %H:%M:%S",
+            &now,
+        );
+        assert_eq!(
+            "a 2022-05-03 14:30:07\nb 2022-05-03 14:30:07\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(21));
+        expected.add_region(SelRegion::caret(43));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
 
-                        new_selection
-                    }
-                };
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.update_selection(buffer, selection);
-                vec![(delta, inval_lines)]
-            }
-            DeleteToBeginningOfLine => {
-                let selection = match cursor.mode {
-                    CursorMode::Normal(_) | CursorMode::Visual { .. } => {
-                        cursor.edit_selection(buffer)
-                    }
-                    CursorMode::Insert(_) => {
-                        let selection = cursor.edit_selection(buffer);
+        // An invalid format inserts nothing
+        let mut buffer = Buffer::new("a");
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+        assert!(
+            Editor::insert_datetime_at(&mut cursor, &mut buffer, "%Q", &now)
+                .is_empty()
+        );
+        assert_eq!("a", buffer.slice_to_cow(0..buffer.len()));
+    }
 
-                        let mut new_selection = Selection::new();
-                        for region in selection.regions() {
-                            let line = buffer.line_of_offset(region.end);
-                            let end = buffer.offset_of_line(line);
-                            let new_region = SelRegion::new(region.start, end, None);
-                            new_selection.add_region(new_region);
-                        }
+    #[test]
+    fn test_sort_lines_unique() {
+        let mut buffer = Buffer::new("b\nA\na\nb\nB\na\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 12)), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::SortLinesUnique);
+        assert_eq!("A\nB\na\nb\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::region(0, 8)), cursor.mode);
 
-                        new_selection
-                    }
-                };
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.update_selection(buffer, selection);
-                vec![(delta, inval_lines)]
-            }
-            DeleteForwardAndInsert => {
-                let selection = cursor.edit_selection(buffer);
-                let (delta, inval_lines) =
-                    buffer.edit(&[(&selection, "")], EditType::Delete);
-                let selection =
-                    selection.apply_delta(&delta, true, InsertDrift::Default);
-                cursor.mode = CursorMode::Insert(selection);
-                vec![(delta, inval_lines)]
-            }
-            NormalMode => {
-                if !modal {
-                    if let CursorMode::Insert(selection) = &cursor.mode {
-                        match selection.regions().len() {
-                            i if i > 1 => {
-                                if let Some(region) = selection.last_inserted() {
-                                    let new_selection =
-                                        Selection::region(region.start, region.end);
-                                    cursor.mode = CursorMode::Insert(new_selection);
-                                    return vec![];
-                                }
-                            }
-                            i if i == 1 => {
-                                let region = selection.regions()[0];
-                                if !region.is_caret() {
-                                    let new_selection = Selection::caret(region.end);
-                                    cursor.mode = CursorMode::Insert(new_selection);
-                                    return vec![];
-                                }
-                            }
-                            _ => (),
-                        }
-                    }
+        // The first of the lines differing only in case is kept
+        let mut buffer = Buffer::new("b\nA\na\nb\nB\na\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 12)), None, None);
+        do_edit(
+            &mut cursor,
+            &mut buffer,
+            &EditCommand::SortLinesUniqueCaseInsensitive,
+        );
+        assert_eq!("A\nb\n", buffer.slice_to_cow(0..buffer.len()));
 
-                    return vec![];
-                }
+        let mut buffer = Buffer::new("3 x\n1\n3 x\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 8,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        Editor::sort_lines_with_options(
+            &mut cursor,
+            &mut buffer,
+            SortOrder::Numeric,
+            false,
+            true,
+        );
+        assert_eq!("1\n3 x\n", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
+    }
 
-                let offset = match &cursor.mode {
-                    CursorMode::Insert(selection) => {
-                        let offset = selection.min_offset();
-                        buffer.prev_grapheme_offset(
-                            offset,
-                            1,
-                            buffer.offset_of_line(buffer.line_of_offset(offset)),
-                        )
-                    }
-                    CursorMode::Visual { end, .. } => {
-                        buffer.offset_line_end(*end, false).min(*end)
-                    }
-                    CursorMode::Normal(offset) => *offset,
-                };
+    #[test]
+    fn test_reverse_regions() {
+        let mut buffer = Buffer::new("abc xyz");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(4, 7, None));
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(selection.clone()), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ReverseRegions);
+        assert_eq!("cba zyx", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(selection), cursor.mode);
 
-                buffer.reset_edit_type();
-                cursor.mode = CursorMode::Normal(offset);
-                cursor.horiz = None;
-                vec![]
-            }
-            InsertMode => {
-                cursor.mode = CursorMode::Insert(Selection::caret(cursor.offset()));
-                vec![]
-            }
-            InsertFirstNonBlank => {
-                match &cursor.mode {
-                    CursorMode::Normal(offset) => {
-                        let line = buffer.line_of_offset(*offset);
-                        let offset = buffer.first_non_blank_character_on_line(line);
-                        cursor.mode = CursorMode::Insert(Selection::caret(offset));
-                    }
-                    CursorMode::Visual { .. } => {
-                        let mut selection = Selection::new();
-                        for region in cursor.edit_selection(buffer).regions() {
-                            selection.add_region(SelRegion::caret(region.min()));
-                        }
-                        cursor.mode = CursorMode::Insert(selection);
-                    }
-                    CursorMode::Insert(_) => {}
-                };
-                vec![]
-            }
-            Append => {
-                let offset = buffer.move_right(cursor.offset(), Mode::Insert, 1);
-                cursor.mode = CursorMode::Insert(Selection::caret(offset));
-                vec![]
-            }
-            AppendEndOfLine => {
-                let offset = cursor.offset();
-                let line = buffer.line_of_offset(offset);
-                let offset = buffer.line_end_offset(line, true);
-                cursor.mode = CursorMode::Insert(Selection::caret(offset));
-                vec![]
-            }
-            ToggleVisualMode => {
-                Self::toggle_visual(cursor, VisualMode::Normal, modal);
-                vec![]
-            }
-            ToggleLinewiseVisualMode => {
-                Self::toggle_visual(cursor, VisualMode::Linewise, modal);
-                vec![]
-            }
-            ToggleBlockwiseVisualMode => {
-                Self::toggle_visual(cursor, VisualMode::Blockwise, modal);
-                vec![]
-            }
-        }
+        let mut buffer = Buffer::new("abc");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 2,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ReverseRegions);
+        assert_eq!("cba", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(0), cursor.mode);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::buffer::Buffer;
-    use crate::cursor::{Cursor, CursorMode};
-    use crate::editor::Editor;
-    use crate::selection::{SelRegion, Selection};
+    #[test]
+    fn test_reverse_regions_graphemes() {
+        // The combining acute accent stays on its `e`
+        let mut buffer = Buffer::new("ae\u{301}b");
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::region(0, buffer.len())),
+            None,
+            None,
+        );
+        do_edit(&mut cursor, &mut buffer, &EditCommand::ReverseRegions);
+        assert_eq!("be\u{301}a", buffer.slice_to_cow(0..buffer.len()));
+    }
 
     #[test]
-    fn test_insert_simple() {
-        let mut buffer = Buffer::new("abc");
+    fn test_transform_selection_base64() {
+        let mut buffer = Buffer::new("hello world\nfoo");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 11, None));
+        selection.add_region(SelRegion::new(15, 12, None));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::transform_selection(
+            &mut cursor,
+            &mut buffer,
+            TransformKind::Base64Encode,
+        )
+        .unwrap();
+        assert_eq!(
+            "aGVsbG8gd29ybGQ=\nZm9v",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::new(0, 16, None));
+        expected.add_region(SelRegion::new(21, 17, None));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+
+        Editor::transform_selection(
+            &mut cursor,
+            &mut buffer,
+            TransformKind::Base64Decode,
+        )
+        .unwrap();
+        assert_eq!("hello world\nfoo", buffer.slice_to_cow(0..buffer.len()));
+    }
+
+    #[test]
+    fn test_transform_selection_invalid_decode() {
+        let mut buffer = Buffer::new("Zm9v not*base64");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 4, None));
+        selection.add_region(SelRegion::new(5, 15, None));
         let mut cursor =
-            Cursor::new(CursorMode::Insert(Selection::caret(1)), None, None);
+            Cursor::new(CursorMode::Insert(selection.clone()), None, None);
+        assert_eq!(
+            Some(TransformError::InvalidBase64),
+            Editor::transform_selection(
+                &mut cursor,
+                &mut buffer,
+                TransformKind::Base64Decode,
+            )
+            .err()
+        );
+        assert_eq!("Zm9v not*base64", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(selection), cursor.mode);
+    }
 
-        Editor::insert(&mut cursor, &mut buffer, "e", None);
-        assert_eq!("aebc", buffer.slice_to_cow(0..buffer.len()));
+    #[test]
+    fn test_transform_selection_url_and_html() {
+        let mut buffer = Buffer::new("a b&c/é");
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::region(0, buffer.len())),
+            None,
+            None,
+        );
+        Editor::transform_selection(
+            &mut cursor,
+            &mut buffer,
+            TransformKind::UrlEncode,
+        )
+        .unwrap();
+        assert_eq!("a%20b%26c%2F%C3%A9", buffer.slice_to_cow(0..buffer.len()));
+        Editor::transform_selection(
+            &mut cursor,
+            &mut buffer,
+            TransformKind::UrlDecode,
+        )
+        .unwrap();
+        assert_eq!("a b&c/é", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut buffer = Buffer::new("<a href=\"x\">&</a>");
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::region(0, buffer.len())),
+            None,
+            None,
+        );
+        Editor::transform_selection(
+            &mut cursor,
+            &mut buffer,
+            TransformKind::HtmlEncode,
+        )
+        .unwrap();
+        assert_eq!(
+            "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+
+        let mut buffer = Buffer::new("&lt;&#233;&#x41;&gt; &bogus;");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(0, 20)), None, None);
+        Editor::transform_selection(
+            &mut cursor,
+            &mut buffer,
+            TransformKind::HtmlDecode,
+        )
+        .unwrap();
+        assert_eq!("<éA> &bogus;", buffer.slice_to_cow(0..buffer.len()));
+
+        let mut cursor = Cursor::new(
+            CursorMode::Insert(Selection::region(5, buffer.len())),
+            None,
+            None,
+        );
+        assert_eq!(
+            Some(TransformError::InvalidHtmlEntity),
+            Editor::transform_selection(
+                &mut cursor,
+                &mut buffer,
+                TransformKind::HtmlDecode,
+            )
+            .err()
+        );
     }
 
     #[test]
-    fn test_insert_multiple_cursor() {
-        let mut buffer = Buffer::new("abc\nefg\n");
+    fn test_wrap_in_block() {
+        let mut buffer = Buffer::new("fn main() {\n    a();\n\n    b();\n}\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::region(14, 28)), None, None);
+        Editor::wrap_in_block(&mut cursor, &mut buffer, "if () {", "}", None);
+        assert_eq!(
+            "fn main() {\n    if () {\n        a();\n\n        b();\n    }\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(20)), cursor.mode);
+
+        let mut buffer = Buffer::new("a\n  b\n");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 0,
+                end: 4,
+                mode: VisualMode::Linewise,
+            },
+            None,
+            None,
+        );
+        Editor::wrap_in_block(&mut cursor, &mut buffer, "loop {", "}", None);
+        assert_eq!(
+            "loop {\n    a\n      b\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(5)), cursor.mode);
+    }
+
+    #[test]
+    #[cfg(feature = "lang-rust")]
+    fn test_wrap_in_block_takes_block_along() {
+        use crate::language::LapceLanguage;
+        use crate::syntax::Syntax;
+
+        let mut buffer =
+            Buffer::new("fn f() {\n    if x {\n        a();\n    }\n    b();\n}\n");
+        let syntax = Syntax::from_language(LapceLanguage::Rust).parse(
+            buffer.rev(),
+            buffer.text().clone(),
+            None,
+        );
+        let mut cursor = Cursor::new(CursorMode::Normal(13), None, None);
+        Editor::wrap_in_block(
+            &mut cursor,
+            &mut buffer,
+            "loop {",
+            "}",
+            Some(&syntax),
+        );
+        assert_eq!(
+            "fn f() {\n    loop {\n        if x {\n            a();\n        }\n    }\n    b();\n}\n",
+            buffer.slice_to_cow(0..buffer.len())
+        );
+        assert_eq!(CursorMode::Insert(Selection::caret(18)), cursor.mode);
+    }
+
+    #[test]
+    fn test_change_case_carets_in_one_word() {
+        let mut buffer = Buffer::new("hello world");
         let mut selection = Selection::new();
         selection.add_region(SelRegion::caret(1));
-        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::caret(3));
         let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::UppercaseSelection);
+        assert_eq!("HELLO world", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(1));
+        expected.add_region(SelRegion::caret(3));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
 
-        Editor::insert(&mut cursor, &mut buffer, "i", None);
-        assert_eq!("aibc\neifg\n", buffer.slice_to_cow(0..buffer.len()));
+        // A caret in a word that is also partly selected
+        let mut buffer = Buffer::new("hello world");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 7, None));
+        selection.add_region(SelRegion::caret(9));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        do_edit(&mut cursor, &mut buffer, &EditCommand::TitleCaseSelection);
+        assert_eq!("Hello World", buffer.slice_to_cow(0..buffer.len()));
     }
 
     #[test]
-    fn test_insert_complex() {
-        let mut buffer = Buffer::new("abc\nefg\n");
+    fn test_surround_carets_in_one_word() {
+        let mut buffer = Buffer::new("foo bar");
         let mut selection = Selection::new();
-        selection.add_region(SelRegion::caret(1));
-        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(2));
         let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
-
-        Editor::insert(&mut cursor, &mut buffer, "i", None);
-        assert_eq!("aibc\neifg\n", buffer.slice_to_cow(0..buffer.len()));
-        Editor::insert(&mut cursor, &mut buffer, "j", None);
-        assert_eq!("aijbc\neijfg\n", buffer.slice_to_cow(0..buffer.len()));
-        Editor::insert(&mut cursor, &mut buffer, "{", None);
-        assert_eq!("aij{bc\neij{fg\n", buffer.slice_to_cow(0..buffer.len()));
-        Editor::insert(&mut cursor, &mut buffer, " ", None);
-        assert_eq!("aij{ bc\neij{ fg\n", buffer.slice_to_cow(0..buffer.len()));
+        Editor::surround(&mut cursor, &mut buffer, "(", ")");
+        assert_eq!("(foo) bar", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(1));
+        expected.add_region(SelRegion::caret(3));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
     }
 
     #[test]
-    fn test_insert_pair() {
-        let mut buffer = Buffer::new("a bc\ne fg\n");
+    fn test_delete_surround_nested_carets() {
+        let mut buffer = Buffer::new("((a) b)");
         let mut selection = Selection::new();
-        selection.add_region(SelRegion::caret(1));
-        selection.add_region(SelRegion::caret(6));
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(5));
         let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::delete_surround(&mut cursor, &mut buffer, None, '(');
+        assert_eq!("a b", buffer.slice_to_cow(0..buffer.len()));
 
-        Editor::insert(&mut cursor, &mut buffer, "{", None);
-        assert_eq!("a{} bc\ne{} fg\n", buffer.slice_to_cow(0..buffer.len()));
-        Editor::insert(&mut cursor, &mut buffer, "}", None);
-        assert_eq!("a{} bc\ne{} fg\n", buffer.slice_to_cow(0..buffer.len()));
+        let mut buffer = Buffer::new("((a) b)");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(5));
+        let mut cursor = Cursor::new(CursorMode::Insert(selection), None, None);
+        Editor::change_surround(&mut cursor, &mut buffer, None, '(', '[');
+        assert_eq!("[[a] b]", buffer.slice_to_cow(0..buffer.len()));
+        let mut expected = Selection::new();
+        expected.add_region(SelRegion::caret(2));
+        expected.add_region(SelRegion::caret(5));
+        assert_eq!(CursorMode::Insert(expected), cursor.mode);
+    }
+
+    #[test]
+    fn test_motion_to_line_end_on_empty_line() {
+        let mut buffer = Buffer::new("abc\n\ndef");
+        let mut cursor = Cursor::new(CursorMode::Normal(4), None, None);
+        let data =
+            Editor::yank_motion(&cursor, &buffer, None, &Movement::EndOfLine, 1);
+        assert_eq!("", data.content);
+
+        let (deltas, _) = Editor::delete_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::EndOfLine,
+            1,
+        );
+        assert!(deltas.is_empty());
+        assert_eq!("abc\n\ndef", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Normal(4), cursor.mode);
+
+        Editor::change_motion(
+            &mut cursor,
+            &mut buffer,
+            None,
+            &Movement::EndOfLine,
+            1,
+        );
+        assert_eq!("abc\n\ndef", buffer.slice_to_cow(0..buffer.len()));
+        assert_eq!(CursorMode::Insert(Selection::caret(4)), cursor.mode);
+    }
+
+    #[test]
+    fn test_insert_at_breaks_undo_group_after_pause() {
+        let mut buffer = Buffer::new("");
+        buffer.set_undo_group_threshold(Duration::from_secs(1));
+        let mut cursor =
+            Cursor::new(CursorMode::Insert(Selection::caret(0)), None, None);
+        let start = Instant::now();
+        for (s, millis) in [("a", 0), ("(", 500), ("b", 3000), ("c", 3100)] {
+            Editor::insert_at(
+                &mut cursor,
+                &mut buffer,
+                s,
+                None,
+                &AutoPairOptions::default(),
+                start + Duration::from_millis(millis),
+            );
+        }
+        assert_eq!("a(bc)", buffer.slice_to_cow(0..buffer.len()));
+
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!("a()", buffer.slice_to_cow(0..buffer.len()));
+        do_edit(&mut cursor, &mut buffer, &EditCommand::Undo);
+        assert_eq!("", buffer.slice_to_cow(0..buffer.len()));
     }
 }
@@ -60,8 +60,14 @@ pub fn create_edit<'s>(
     let indent = if indent.starts_with('\t') {
         indent
     } else {
-        let (_, col) = buffer.offset_to_line_col(offset);
-        indent.split_at(indent.len() - col % indent.len()).0
+        let (line, col) = buffer.offset_to_line_col(offset);
+        let line_start = buffer.offset_of_line(line);
+        // Columns can only be aligned to when the line is indented by spaces
+        if buffer.slice_to_cow(line_start..offset).contains('\t') {
+            indent
+        } else {
+            indent.split_at(indent.len() - col % indent.len()).0
+        }
     };
     (Selection::caret(offset), indent)
 }
@@ -76,12 +82,21 @@ pub fn create_outdent<'s>(
         return None;
     }
 
-    let start = if indent.starts_with('\t') {
+    let start = if indent.starts_with('\t')
+        || buffer.char_at_offset(offset - 1) == Some('\t')
+    {
         offset - 1
     } else {
         let r = col % indent.len();
         let r = if r == 0 { indent.len() } else { r };
-        offset - r
+        // Stop at a tab, which is worth a whole indentation unit by itself
+        let spaces = buffer
+            .slice_to_cow(offset - col..offset)
+            .chars()
+            .rev()
+            .take_while(|c| *c == ' ')
+            .count();
+        offset - r.min(spaces)
     };
 
     Some((Selection::region(start, offset), ""))
@@ -42,6 +42,7 @@ use crate::style::HighlightConfiguration;
 //            language: tree_sitter_foo::language,
 //            highlight: tree_sitter_foo::HIGHLIGHT_QUERY,
 //            comment: "//",
+//            block_comment: ("/*", "*/"),
 //            indent: "    ",
 //            code_lens: (&[/* ... */], &[/* ... */]),
 //            extensions: &["foo"],
@@ -76,6 +77,9 @@ struct SyntaxProperties {
     highlight: &'static str,
     /// The comment token.  "#" for python, "//" for rust for example.
     comment: &'static str,
+    /// The block comment delimiters.  ("/*", "*/") for rust for example, or
+    /// empty strings when the language has none.
+    block_comment: (&'static str, &'static str),
     /// The indent unit.  "\t" for python, "    " for rust, for example.
     indent: &'static str,
     /// TODO: someone more knowledgeable please describe what the two lists are.
@@ -163,6 +167,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_rust::language,
         highlight: tree_sitter_rust::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "    ",
         code_lens: (
             &["source_file", "impl_item", "trait_item", "declaration_list"],
@@ -176,6 +181,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_go::language,
         highlight: tree_sitter_go::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "    ",
         code_lens: (
             &[
@@ -195,6 +201,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_javascript::language,
         highlight: tree_sitter_javascript::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (&["source_file", "program"], &["source_file"]),
         extensions: &["js"],
@@ -205,6 +212,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_javascript::language,
         highlight: tree_sitter_javascript::JSX_HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (&["source_file", "program"], &["source_file"]),
         extensions: &["jsx"],
@@ -215,6 +223,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_typescript::language_typescript,
         highlight: tree_sitter_typescript::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "    ",
         code_lens: (&["source_file", "program"], &["source_file"]),
         extensions: &["ts"],
@@ -225,6 +234,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_typescript::language_tsx,
         highlight: tree_sitter_typescript::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "    ",
         code_lens: (&["source_file", "program"], &["source_file"]),
         extensions: &["tsx"],
@@ -235,6 +245,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_python::language,
         highlight: tree_sitter_python::HIGHLIGHT_QUERY,
         comment: "#",
+        block_comment: ("", ""),
         indent: "\t",
         code_lens: (
             &[
@@ -256,6 +267,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_toml::language,
         highlight: tree_sitter_toml::HIGHLIGHT_QUERY,
         comment: "#",
+        block_comment: ("", ""),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["toml"],
@@ -266,6 +278,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_php::language,
         highlight: tree_sitter_php::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["php"],
@@ -276,6 +289,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_elixir::language,
         highlight: tree_sitter_elixir::HIGHLIGHTS_QUERY,
         comment: "#",
+        block_comment: ("", ""),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["ex", "exs", "eex", "heex", "sface"],
@@ -286,6 +300,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_c::language,
         highlight: tree_sitter_c::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["c"],
@@ -296,6 +311,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_cpp::language,
         highlight: tree_sitter_cpp::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["cpp", "cxx", "cc", "c++", "hpp", "hxx", "hh", "h++"],
@@ -306,6 +322,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_json::language,
         highlight: tree_sitter_json::HIGHLIGHT_QUERY,
         comment: "",
+        block_comment: ("", ""),
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["json"],
@@ -316,6 +333,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_md::language,
         highlight: tree_sitter_md::HIGHLIGHTS_QUERY,
         comment: "",
+        block_comment: ("<!--", "-->"),
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["md"],
@@ -326,6 +344,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_ruby::language,
         highlight: tree_sitter_ruby::HIGHLIGHT_QUERY,
         comment: "#",
+        block_comment: ("", ""),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["rb"],
@@ -336,6 +355,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_html::language,
         highlight: tree_sitter_html::HIGHLIGHT_QUERY,
         comment: "",
+        block_comment: ("<!--", "-->"),
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["html", "htm"],
@@ -346,6 +366,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_java::language,
         highlight: tree_sitter_java::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["java"],
@@ -356,6 +377,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_elm::language,
         highlight: tree_sitter_elm::HIGHLIGHTS_QUERY,
         comment: "#",
+        block_comment: ("{-", "-}"),
         indent: "    ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["elm"],
@@ -366,6 +388,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_swift::language,
         highlight: tree_sitter_swift::HIGHLIGHTS_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["swift"],
@@ -376,6 +399,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_ql::language,
         highlight: tree_sitter_ql::HIGHLIGHTS_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["ql"],
@@ -386,6 +410,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_haskell::language,
         highlight: tree_sitter_haskell::HIGHLIGHTS_QUERY,
         comment: "--",
+        block_comment: ("{-", "-}"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["hs"],
@@ -396,6 +421,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_glimmer::language,
         highlight: tree_sitter_glimmer::HIGHLIGHTS_QUERY,
         comment: "{{!",
+        block_comment: ("{{!--", "--}}"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["hbs"],
@@ -406,6 +432,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_haxe::language,
         highlight: tree_sitter_haxe::HIGHLIGHTS_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["hx"],
@@ -416,6 +443,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_hcl::language,
         highlight: tree_sitter_hcl::HIGHLIGHTS_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["hcl"],
@@ -426,6 +454,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_ocaml::language_ocaml,
         highlight: tree_sitter_ocaml::HIGHLIGHTS_QUERY,
         comment: "(*",
+        block_comment: ("(*", "*)"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["ml"],
@@ -436,6 +465,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_ocaml::language_ocaml_interface,
         highlight: tree_sitter_ocaml::HIGHLIGHTS_QUERY,
         comment: "(*",
+        block_comment: ("(*", "*)"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["mli"],
@@ -446,6 +476,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_scss::language,
         highlight: tree_sitter_scss::HIGHLIGHTS_QUERY,
         comment: "//",
+        block_comment: ("/*", "*/"),
         indent: "  ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["scss"],
@@ -456,6 +487,7 @@ const LANGUAGES: &[SyntaxProperties] = &[
         language: tree_sitter_hare::language,
         highlight: tree_sitter_hare::HIGHLIGHT_QUERY,
         comment: "//",
+        block_comment: ("", ""),
         indent: "        ",
         code_lens: (DEFAULT_CODE_LENS_LIST, DEFAULT_CODE_LENS_IGNORE_LIST),
         extensions: &["ha"],
@@ -494,6 +526,10 @@ impl LapceLanguage {
         self.properties().comment
     }
 
+    pub fn block_comment_tokens(&self) -> (&str, &str) {
+        self.properties().block_comment
+    }
+
     pub fn indent_unit(&self) -> &str {
         self.properties().indent
     }
@@ -10,6 +10,7 @@ pub mod mode;
 pub mod movement;
 pub mod register;
 pub mod selection;
+pub mod snippet;
 pub mod style;
 pub mod syntax;
 pub mod word;
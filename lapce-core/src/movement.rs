@@ -5,6 +5,26 @@ pub enum LinePosition {
     Line(usize),
 }
 
+/// A search for a character on the current line, like `f`, `t`, `F` and `T`
+/// in vim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CharSearch {
+    pub c: char,
+    pub forward: bool,
+    /// Whether to stop right before the character instead of on it.
+    pub till: bool,
+}
+
+impl CharSearch {
+    /// The same search in the other direction, for `,` in vim.
+    pub fn reversed(&self) -> Self {
+        Self {
+            forward: !self.forward,
+            ..*self
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum Movement {
     Left,
@@ -21,9 +41,15 @@ pub enum Movement {
     WordEndForward,
     WordForward,
     WordBackward,
+    BigWordEndForward,
+    BigWordForward,
+    BigWordBackward,
+    ParagraphForward,
+    ParagraphBackward,
     NextUnmatched(char),
     PreviousUnmatched(char),
     MatchPairs,
+    FindChar(CharSearch),
 }
 
 impl PartialEq for Movement {
@@ -45,7 +71,11 @@ impl Movement {
     }
 
     pub fn is_inclusive(&self) -> bool {
-        matches!(self, Movement::WordEndForward)
+        match self {
+            Movement::WordEndForward | Movement::BigWordEndForward => true,
+            Movement::FindChar(search) => search.forward,
+            _ => false,
+        }
     }
 
     pub fn is_jump(&self) -> bool {
@@ -55,6 +85,8 @@ impl Movement {
                 | Movement::Offset(_)
                 | Movement::DocumentStart
                 | Movement::DocumentEnd
+                | Movement::ParagraphForward
+                | Movement::ParagraphBackward
         )
     }
 
@@ -5,7 +5,7 @@ pub trait Clipboard {
     fn put_string(&mut self, s: impl AsRef<str>);
 }
 
-#[derive(Clone, Default)]
+#[derive(Clone, Default, Debug, PartialEq)]
 pub struct RegisterData {
     pub content: String,
     pub mode: VisualMode,
@@ -14,7 +14,12 @@ pub struct RegisterData {
 #[derive(Clone, Default)]
 pub struct Register {
     pub unnamed: RegisterData,
+    /// The `"0` register, holding the last yank.
     last_yank: RegisterData,
+    /// The `"1` to `"9` registers, holding the last deletes.
+    numbered: [RegisterData; 9],
+    /// The `"a` to `"z` registers.
+    named: [RegisterData; 26],
 }
 
 pub enum RegisterKind {
@@ -22,6 +27,32 @@ pub enum RegisterKind {
     Yank,
 }
 
+/// Picks one of the registers, the way `"x` does in vim.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterSelector {
+    Unnamed,
+    /// `"0` to `"9`.
+    Numbered(usize),
+    /// `"a` to `"z`. Writing to an uppercase name appends to the register.
+    Named(char),
+    /// `"_`, which discards whatever is written to it.
+    BlackHole,
+}
+
+impl RegisterSelector {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '"' => Some(RegisterSelector::Unnamed),
+            '_' => Some(RegisterSelector::BlackHole),
+            '0'..='9' => {
+                Some(RegisterSelector::Numbered(c.to_digit(10).unwrap() as usize))
+            }
+            c if c.is_ascii_alphabetic() => Some(RegisterSelector::Named(c)),
+            _ => None,
+        }
+    }
+}
+
 impl Register {
     pub fn add(&mut self, kind: RegisterKind, data: RegisterData) {
         match kind {
@@ -30,7 +61,27 @@ impl Register {
         }
     }
 
+    /// Stores the data in the selected register. Anything but a named
+    /// register goes through the yank and delete history as usual, except
+    /// for the black hole register which leaves every register untouched.
+    pub fn add_to(
+        &mut self,
+        selector: RegisterSelector,
+        kind: RegisterKind,
+        data: RegisterData,
+    ) {
+        match selector {
+            RegisterSelector::Named(name) => self.add_named(name, data),
+            RegisterSelector::BlackHole => {}
+            RegisterSelector::Unnamed | RegisterSelector::Numbered(_) => {
+                self.add(kind, data)
+            }
+        }
+    }
+
     pub fn add_delete(&mut self, data: RegisterData) {
+        self.numbered.rotate_right(1);
+        self.numbered[0] = data.clone();
         self.unnamed = data;
     }
 
@@ -38,4 +89,137 @@ impl Register {
         self.unnamed = data.clone();
         self.last_yank = data;
     }
+
+    fn add_named(&mut self, name: char, data: RegisterData) {
+        let index = match name.to_ascii_lowercase() {
+            c @ 'a'..='z' => c as usize - 'a' as usize,
+            _ => return,
+        };
+        let register = &mut self.named[index];
+        if name.is_ascii_uppercase() && !register.content.is_empty() {
+            if register.mode == VisualMode::Linewise
+                || data.mode == VisualMode::Linewise
+            {
+                if !register.content.ends_with('\n') {
+                    register.content.push('\n');
+                }
+                register.mode = VisualMode::Linewise;
+            }
+            register.content.push_str(&data.content);
+            if register.mode == VisualMode::Linewise
+                && !register.content.ends_with('\n')
+            {
+                register.content.push('\n');
+            }
+        } else {
+            *register = data;
+        }
+        self.unnamed = register.clone();
+    }
+
+    pub fn get(&self, selector: RegisterSelector) -> Option<&RegisterData> {
+        match selector {
+            RegisterSelector::Unnamed => Some(&self.unnamed),
+            RegisterSelector::Numbered(0) => Some(&self.last_yank),
+            RegisterSelector::Numbered(n) => self.numbered.get(n - 1),
+            RegisterSelector::Named(name) => match name.to_ascii_lowercase() {
+                c @ 'a'..='z' => Some(&self.named[c as usize - 'a' as usize]),
+                _ => None,
+            },
+            RegisterSelector::BlackHole => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Register, RegisterData, RegisterKind, RegisterSelector};
+    use crate::mode::VisualMode;
+
+    fn data(content: &str, mode: VisualMode) -> RegisterData {
+        RegisterData {
+            content: content.to_string(),
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_numbered_registers() {
+        let mut register = Register::default();
+        register.add(RegisterKind::Yank, data("a", VisualMode::Normal));
+        register.add(RegisterKind::Delete, data("b\n", VisualMode::Linewise));
+        register.add(RegisterKind::Delete, data("c\n", VisualMode::Linewise));
+
+        let get = |n| register.get(RegisterSelector::Numbered(n)).unwrap();
+        assert_eq!("a", get(0).content);
+        assert_eq!("c\n", get(1).content);
+        assert_eq!("b\n", get(2).content);
+        assert_eq!("", get(3).content);
+        assert_eq!(
+            "c\n",
+            register.get(RegisterSelector::Unnamed).unwrap().content
+        );
+    }
+
+    #[test]
+    fn test_named_registers() {
+        let mut register = Register::default();
+        let a = RegisterSelector::from_char('a').unwrap();
+        let upper_a = RegisterSelector::from_char('A').unwrap();
+
+        register.add_to(a, RegisterKind::Yank, data("foo", VisualMode::Normal));
+        register.add_to(
+            upper_a,
+            RegisterKind::Yank,
+            data("bar", VisualMode::Normal),
+        );
+        assert_eq!("foobar", register.get(a).unwrap().content);
+
+        register.add_to(
+            upper_a,
+            RegisterKind::Yank,
+            data("baz\n", VisualMode::Linewise),
+        );
+        let data = register.get(upper_a).unwrap();
+        assert_eq!("foobar\nbaz\n", data.content);
+        assert_eq!(VisualMode::Linewise, data.mode);
+
+        // Characterwise text appended to whole lines is a line of its own
+        register.add_to(
+            upper_a,
+            RegisterKind::Yank,
+            RegisterData {
+                content: "qux".to_string(),
+                mode: VisualMode::Normal,
+            },
+        );
+        assert_eq!("foobar\nbaz\nqux\n", register.get(a).unwrap().content);
+
+        assert_eq!(None, RegisterSelector::from_char('-'));
+        assert_eq!(
+            Some(RegisterSelector::BlackHole),
+            RegisterSelector::from_char('_')
+        );
+        assert_eq!(None, register.get(RegisterSelector::Named('-')).map(|_| ()));
+    }
+
+    #[test]
+    fn test_black_hole_register() {
+        let mut register = Register::default();
+        register.add(RegisterKind::Yank, data("a", VisualMode::Normal));
+        register.add_to(
+            RegisterSelector::BlackHole,
+            RegisterKind::Delete,
+            data("b\n", VisualMode::Linewise),
+        );
+        assert_eq!(
+            "a",
+            register.get(RegisterSelector::Unnamed).unwrap().content
+        );
+        assert_eq!(
+            "",
+            register.get(RegisterSelector::Numbered(1)).unwrap().content
+        );
+        assert!(register.get(RegisterSelector::BlackHole).is_none());
+    }
 }
@@ -0,0 +1,294 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use anyhow::Error;
+use itertools::Itertools;
+use regex::Regex;
+
+#[derive(Debug)]
+pub struct Snippet {
+    elements: Vec<SnippetElement>,
+}
+
+impl Snippet {
+    fn extract_elements(
+        s: &str,
+        pos: usize,
+        escs: Vec<&str>,
+        loose_escs: Vec<&str>,
+    ) -> (Vec<SnippetElement>, usize) {
+        let mut elements = Vec::new();
+        let mut pos = pos;
+        loop {
+            if s.len() == pos {
+                break;
+            } else if let Some((ele, end)) = Self::extract_tabstop(s, pos) {
+                elements.push(ele);
+                pos = end;
+            } else if let Some((ele, end)) = Self::extract_placeholder(s, pos) {
+                elements.push(ele);
+                pos = end;
+            } else if let Some((ele, end)) =
+                Self::extract_text(s, pos, escs.clone(), loose_escs.clone())
+            {
+                elements.push(ele);
+                pos = end;
+            } else {
+                break;
+            }
+        }
+        (elements, pos)
+    }
+
+    fn extract_tabstop(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
+        for re in &[
+            Regex::new(r#"^\$(\d+)"#).unwrap(),
+            Regex::new(r#"^\$\{(\d+)\}"#).unwrap(),
+        ] {
+            if let Some(caps) = re.captures(&s[pos..]) {
+                let end = pos + re.find(&s[pos..])?.end();
+                let m = caps.get(1)?;
+                let n = m.as_str().parse::<usize>().ok()?;
+                return Some((SnippetElement::Tabstop(n), end));
+            }
+        }
+
+        None
+    }
+
+    fn extract_placeholder(s: &str, pos: usize) -> Option<(SnippetElement, usize)> {
+        let re = Regex::new(r#"^\$\{(\d+):(.*?)\}"#).unwrap();
+        let end = pos + re.find(&s[pos..])?.end();
+
+        let caps = re.captures(&s[pos..])?;
+
+        let tab = caps.get(1)?.as_str().parse::<usize>().ok()?;
+
+        let m = caps.get(2)?;
+        let content = m.as_str();
+        if content.is_empty() {
+            return Some((
+                SnippetElement::PlaceHolder(
+                    tab,
+                    vec![SnippetElement::Text("".to_string())],
+                ),
+                end,
+            ));
+        }
+        let (els, pos) =
+            Self::extract_elements(s, pos + m.start(), vec!["$", "}", "\\"], vec![]);
+        Some((SnippetElement::PlaceHolder(tab, els), pos + 1))
+    }
+
+    fn extract_text(
+        s: &str,
+        pos: usize,
+        escs: Vec<&str>,
+        loose_escs: Vec<&str>,
+    ) -> Option<(SnippetElement, usize)> {
+        let mut s = &s[pos..];
+        let mut ele = "".to_string();
+        let mut end = pos;
+
+        while !s.is_empty() {
+            if let Some(esc) = s.get(..2) {
+                let mut new_escs = escs.clone();
+                new_escs.extend_from_slice(&loose_escs);
+
+                if new_escs
+                    .iter()
+                    .map(|e| format!("\\{}", e))
+                    .any(|x| x == *esc)
+                {
+                    ele += &s[1..2];
+                    end += 2;
+                    s = &s[2..];
+                    continue;
+                }
+            }
+            let len = s.chars().next().map_or(1, char::len_utf8);
+            if escs.contains(&&s[..len]) {
+                break;
+            }
+            ele += &s[..len];
+            end += len;
+            s = &s[len..];
+        }
+        if ele.is_empty() {
+            return None;
+        }
+        Some((SnippetElement::Text(ele), end))
+    }
+
+    pub fn text(&self) -> String {
+        self.elements.iter().map(|e| e.text()).join("")
+    }
+
+    /// Gives tab stops repeating a placeholder the text of that placeholder,
+    /// so that every occurrence of a stop starts out the same.
+    pub fn fill_repeated_tabstops(mut self) -> Self {
+        let mut defaults = HashMap::new();
+        Self::placeholder_texts(&self.elements, &mut defaults);
+        Self::fill_tabstops(&mut self.elements, &defaults);
+        self
+    }
+
+    fn placeholder_texts(
+        elements: &[SnippetElement],
+        texts: &mut HashMap<usize, String>,
+    ) {
+        for el in elements {
+            if let SnippetElement::PlaceHolder(tab, els) = el {
+                texts
+                    .entry(*tab)
+                    .or_insert_with(|| els.iter().map(|e| e.text()).join(""));
+                Self::placeholder_texts(els, texts);
+            }
+        }
+    }
+
+    fn fill_tabstops(
+        elements: &mut [SnippetElement],
+        texts: &HashMap<usize, String>,
+    ) {
+        for el in elements {
+            match el {
+                SnippetElement::PlaceHolder(_, els) => {
+                    Self::fill_tabstops(els, texts);
+                }
+                SnippetElement::Tabstop(tab) => {
+                    if let Some(text) = texts.get(tab) {
+                        *el = SnippetElement::PlaceHolder(
+                            *tab,
+                            vec![SnippetElement::Text(text.clone())],
+                        );
+                    }
+                }
+                SnippetElement::Text(_) => {}
+            }
+        }
+    }
+
+    pub fn tabs(&self, pos: usize) -> Vec<(usize, (usize, usize))> {
+        Self::elements_tabs(&self.elements, pos)
+    }
+
+    pub fn elements_tabs(
+        elements: &[SnippetElement],
+        start: usize,
+    ) -> Vec<(usize, (usize, usize))> {
+        let mut tabs = Vec::new();
+        let mut pos = start;
+        for el in elements {
+            match el {
+                SnippetElement::Text(t) => {
+                    pos += t.len();
+                }
+                SnippetElement::PlaceHolder(tab, els) => {
+                    let placeholder_tabs = Self::elements_tabs(els, pos);
+                    let end = pos + els.iter().map(|e| e.len()).sum::<usize>();
+                    tabs.push((*tab, (pos, end)));
+                    tabs.extend_from_slice(&placeholder_tabs);
+                    pos = end;
+                }
+                SnippetElement::Tabstop(tab) => {
+                    tabs.push((*tab, (pos, pos)));
+                }
+            }
+        }
+        tabs
+    }
+}
+
+impl FromStr for Snippet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (elements, _) = Self::extract_elements(s, 0, vec!["$", "\\"], vec!["}"]);
+        Ok(Snippet { elements })
+    }
+}
+
+impl Display for Snippet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = self.elements.iter().map(|e| e.to_string()).join("");
+        f.write_str(&text)
+    }
+}
+
+#[derive(Debug)]
+pub enum SnippetElement {
+    Text(String),
+    PlaceHolder(usize, Vec<SnippetElement>),
+    Tabstop(usize),
+}
+
+impl SnippetElement {
+    pub fn len(&self) -> usize {
+        match &self {
+            SnippetElement::Text(text) => text.len(),
+            SnippetElement::PlaceHolder(_, elements) => {
+                elements.iter().map(|e| e.len()).sum()
+            }
+            SnippetElement::Tabstop(_) => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn text(&self) -> String {
+        match &self {
+            SnippetElement::Text(t) => t.to_string(),
+            SnippetElement::PlaceHolder(_, elements) => {
+                elements.iter().map(|e| e.text()).join("")
+            }
+            SnippetElement::Tabstop(_) => "".to_string(),
+        }
+    }
+}
+
+impl Display for SnippetElement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            SnippetElement::Text(text) => f.write_str(text),
+            SnippetElement::PlaceHolder(tab, elements) => {
+                let elements = elements.iter().map(|e| e.to_string()).join("");
+                write!(f, "${{{}:{}}}", tab, elements)
+            }
+            SnippetElement::Tabstop(tab) => write!(f, "${}", tab),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snippet() {
+        let s = "start $1${2:second ${3:third}} $0";
+        let parsed = Snippet::from_str(s).unwrap();
+        assert_eq!(s, parsed.to_string());
+
+        let text = "start second third ";
+        assert_eq!(text, parsed.text());
+
+        assert_eq!(
+            vec![(1, (6, 6)), (2, (6, 18)), (3, (13, 18)), (0, (19, 19))],
+            parsed.tabs(0)
+        );
+    }
+
+    #[test]
+    fn test_snippet_repeated_tabstops() {
+        let parsed = Snippet::from_str("<${1:é}>\\$</$1>$0")
+            .unwrap()
+            .fill_repeated_tabstops();
+        assert_eq!("<é>$</é>", parsed.text());
+        assert_eq!(
+            vec![(1, (1, 3)), (1, (7, 9)), (0, (10, 10))],
+            parsed.tabs(0)
+        );
+    }
+}
@@ -24,6 +24,30 @@ thread_local! {
    static HIGHLIGHTS: RefCell<HashMap<LapceLanguage, crate::style::HighlightConfiguration>> = RefCell::new(HashMap::new());
 }
 
+/// Node kinds of the function, method and closure definitions across the
+/// supported languages.
+const FUNCTION_KINDS: &[&str] = &[
+    "anonymous_function_creation_expression",
+    "arrow_function",
+    "closure_expression",
+    "constructor_declaration",
+    "func_literal",
+    "function",
+    "function_declaration",
+    "function_definition",
+    "function_expression",
+    "function_item",
+    "generator_function",
+    "generator_function_declaration",
+    "lambda",
+    "lambda_expression",
+    "lambda_literal",
+    "method",
+    "method_declaration",
+    "method_definition",
+    "singleton_method",
+];
+
 #[derive(Clone)]
 pub struct Syntax {
     rev: u64,
@@ -304,6 +328,101 @@ impl Syntax {
         None
     }
 
+    /// Finds the range of the smallest syntax node that encloses `start..end`
+    /// and is larger than it.
+    pub fn find_enclosing_node(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, usize)> {
+        let tree = self.tree.as_ref()?;
+        let mut node = tree.root_node().descendant_for_byte_range(start, end);
+        while let Some(n) = node {
+            let (node_start, node_end) = (n.start_byte(), n.end_byte());
+            if node_start <= start
+                && end <= node_end
+                && (node_start, node_end) != (start, end)
+            {
+                return Some((node_start, node_end));
+            }
+            node = n.parent();
+        }
+        None
+    }
+
+    /// Checks whether the offset lies within a string literal or a comment.
+    pub fn is_inside_string_or_comment(&self, offset: usize) -> bool {
+        let tree = match self.tree.as_ref() {
+            Some(tree) => tree,
+            None => return false,
+        };
+        // Look at the character before the offset, so that a caret at the end
+        // of a line comment still counts as being inside of it.
+        let mut node = tree
+            .root_node()
+            .descendant_for_byte_range(offset.saturating_sub(1), offset);
+        while let Some(n) = node {
+            let kind = n.kind();
+            if kind.contains("comment") {
+                if n.start_byte() < offset && offset <= n.end_byte() {
+                    return true;
+                }
+            } else if kind.contains("string")
+                && n.start_byte() < offset
+                && offset < n.end_byte()
+            {
+                return true;
+            }
+            node = n.parent();
+        }
+        false
+    }
+
+    /// Finds the range of the innermost function, method, closure or block
+    /// that contains the range. A block that is the body of a function
+    /// stands for the whole function.
+    pub fn find_enclosing_scope(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> Option<(usize, usize)> {
+        let is_function = |kind: &str| FUNCTION_KINDS.contains(&kind);
+        let tree = self.tree.as_ref()?;
+        let mut node = tree.root_node().descendant_for_byte_range(start, end);
+        while let Some(n) = node {
+            let kind = n.kind();
+            if is_function(kind) {
+                return Some((n.start_byte(), n.end_byte()));
+            }
+            if kind == "block" || kind.ends_with("_block") {
+                let n = match n.parent() {
+                    Some(parent) if is_function(parent.kind()) => parent,
+                    _ => n,
+                };
+                return Some((n.start_byte(), n.end_byte()));
+            }
+            node = n.parent();
+        }
+        None
+    }
+
+    /// Finds the range of the string literal the offset lies in, including
+    /// its delimiters.
+    pub fn find_string(&self, offset: usize) -> Option<(usize, usize)> {
+        let tree = self.tree.as_ref()?;
+        let mut node = tree.root_node().descendant_for_byte_range(offset, offset);
+        let mut range = None;
+        while let Some(n) = node {
+            if n.kind().contains("string") {
+                range = Some((n.start_byte(), n.end_byte()));
+            } else if range.is_some() {
+                break;
+            }
+            node = n.parent();
+        }
+        range
+    }
+
     pub fn find_tag(
         &self,
         offset: usize,
@@ -45,11 +45,24 @@ impl<'a> WordCursor<'a> {
 
     /// Get previous boundary, and set the cursor at the boundary found.
     pub fn prev_boundary(&mut self) -> Option<usize> {
+        self.prev_boundary_by(get_word_property)
+    }
+
+    /// Like [`WordCursor::prev_boundary`], but for a WORD, which is any run
+    /// of non blank characters.
+    pub fn prev_big_word_boundary(&mut self) -> Option<usize> {
+        self.prev_boundary_by(get_big_word_property)
+    }
+
+    fn prev_boundary_by(
+        &mut self,
+        property: fn(char) -> WordProperty,
+    ) -> Option<usize> {
         if let Some(ch) = self.inner.prev_codepoint() {
-            let mut prop = get_word_property(ch);
+            let mut prop = property(ch);
             let mut candidate = self.inner.pos();
             while let Some(prev) = self.inner.prev_codepoint() {
-                let prop_prev = get_word_property(prev);
+                let prop_prev = property(prev);
                 if classify_boundary(prop_prev, prop).is_start() {
                     break;
                 }
@@ -77,11 +90,23 @@ impl<'a> WordCursor<'a> {
 
     /// Get next boundary, and set the cursor at the boundary found.
     pub fn next_boundary(&mut self) -> Option<usize> {
+        self.next_boundary_by(get_word_property)
+    }
+
+    /// Like [`WordCursor::next_boundary`], but for a WORD.
+    pub fn next_big_word_boundary(&mut self) -> Option<usize> {
+        self.next_boundary_by(get_big_word_property)
+    }
+
+    fn next_boundary_by(
+        &mut self,
+        property: fn(char) -> WordProperty,
+    ) -> Option<usize> {
         if let Some(ch) = self.inner.next_codepoint() {
-            let mut prop = get_word_property(ch);
+            let mut prop = property(ch);
             let mut candidate = self.inner.pos();
             while let Some(next) = self.inner.next_codepoint() {
-                let prop_next = get_word_property(next);
+                let prop_next = property(next);
                 if classify_boundary(prop, prop_next).is_start() {
                     break;
                 }
@@ -94,13 +119,111 @@ impl<'a> WordCursor<'a> {
         None
     }
 
+    /// Get the start of the text deleted by a backward word deletion from
+    /// the cursor: the previous word along with any whitespace between it
+    /// and the cursor. A line break is deleted on its own, and so is the
+    /// whitespace at the start of a line.
+    pub fn prev_deletion_boundary(&mut self) -> usize {
+        let ch = match self.inner.prev_codepoint() {
+            Some(ch) => ch,
+            None => return self.inner.pos(),
+        };
+        match get_word_property(ch) {
+            WordProperty::Lf => {
+                let pos = self.inner.pos();
+                if self.inner.prev_codepoint() != Some('\r') {
+                    self.inner.set(pos);
+                }
+            }
+            WordProperty::Cr => {}
+            WordProperty::Space => {
+                self.skip_backward(|prop| prop == WordProperty::Space);
+                let pos = self.inner.pos();
+                let prev = self.inner.prev_codepoint().map(get_word_property);
+                self.inner.set(pos);
+                if let Some(
+                    prop @ (WordProperty::Other | WordProperty::Punctuation),
+                ) = prev
+                {
+                    self.skip_backward(|p| p == prop);
+                }
+            }
+            prop => self.skip_backward(|p| p == prop),
+        }
+        self.inner.pos()
+    }
+
+    /// Get the end of the text deleted by a forward word deletion from the
+    /// cursor: the next word along with the whitespace following it. A line
+    /// break is deleted on its own, and whitespace under the cursor is
+    /// deleted up to the next word.
+    pub fn next_deletion_boundary(&mut self) -> usize {
+        let ch = match self.inner.next_codepoint() {
+            Some(ch) => ch,
+            None => return self.inner.pos(),
+        };
+        match get_word_property(ch) {
+            WordProperty::Cr => {
+                let pos = self.inner.pos();
+                if self.inner.next_codepoint() != Some('\n') {
+                    self.inner.set(pos);
+                }
+            }
+            WordProperty::Lf => {}
+            WordProperty::Space => {
+                self.skip_forward(|prop| prop == WordProperty::Space)
+            }
+            prop => {
+                self.skip_forward(|p| p == prop);
+                self.skip_forward(|p| p == WordProperty::Space);
+            }
+        }
+        self.inner.pos()
+    }
+
+    /// Move backward while the codepoints before the cursor satisfy `f`.
+    fn skip_backward(&mut self, f: impl Fn(WordProperty) -> bool) {
+        let mut candidate = self.inner.pos();
+        while let Some(prev) = self.inner.prev_codepoint() {
+            if !f(get_word_property(prev)) {
+                break;
+            }
+            candidate = self.inner.pos();
+        }
+        self.inner.set(candidate);
+    }
+
+    /// Move forward while the codepoints after the cursor satisfy `f`.
+    fn skip_forward(&mut self, f: impl Fn(WordProperty) -> bool) {
+        let mut candidate = self.inner.pos();
+        while let Some(next) = self.inner.next_codepoint() {
+            if !f(get_word_property(next)) {
+                break;
+            }
+            candidate = self.inner.pos();
+        }
+        self.inner.set(candidate);
+    }
+
     pub fn end_boundary(&mut self) -> Option<usize> {
+        self.end_boundary_by(get_word_property)
+    }
+
+    /// Like [`WordCursor::end_boundary`], but for a WORD.
+    pub fn big_word_end_boundary(&mut self) -> Option<usize> {
+        self.end_boundary_by(get_big_word_property)
+    }
+
+    fn end_boundary_by(
+        &mut self,
+        property: fn(char) -> WordProperty,
+    ) -> Option<usize> {
         self.inner.next_codepoint();
         if let Some(ch) = self.inner.next_codepoint() {
-            let mut prop = get_word_property(ch);
+            let mut prop = property(ch);
             let mut candidate = self.inner.pos();
             while let Some(next) = self.inner.next_codepoint() {
-                let prop_next = get_word_property(next);
+                let prop_next = property(next);
                 if classify_boundary(prop, prop_next).is_end() {
                     break;
                 }
@@ -279,6 +402,15 @@ pub fn get_word_property(codepoint: char) -> WordProperty {
     WordProperty::Other
 }
 
+/// The property of a character within a WORD, where punctuation is as much
+/// part of it as letters are, like for `W` in vim.
+pub fn get_big_word_property(codepoint: char) -> WordProperty {
+    match get_word_property(codepoint) {
+        WordProperty::Punctuation => WordProperty::Other,
+        prop => prop,
+    }
+}
+
 fn classify_boundary_initial(
     prev: WordProperty,
     next: WordProperty,
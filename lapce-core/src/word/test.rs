@@ -83,3 +83,51 @@ fn next_boundary_failure_to_be_fixed_2() {
     // assert_eq!(cursor.next_boundary(), Some(6));
     // assert_eq!(cursor.next_boundary(), None);
 }
+
+#[test]
+fn test_prev_deletion_boundary() {
+    let buffer = Buffer::new("foo.bar  baz\n  qux\r\n");
+    let prev =
+        |offset| WordCursor::new(buffer.text(), offset).prev_deletion_boundary();
+    assert_eq!(prev(0), 0);
+    // Trailing whitespace goes along with the word before it
+    assert_eq!(prev(9), 4);
+    assert_eq!(prev(12), 9);
+    assert_eq!(prev(4), 3);
+    assert_eq!(prev(3), 0);
+    assert_eq!(prev(13), 12);
+    // Leading whitespace doesn't take the line break with it
+    assert_eq!(prev(15), 13);
+    assert_eq!(prev(20), 18);
+    assert_eq!(prev(18), 15);
+}
+
+#[test]
+fn test_next_deletion_boundary() {
+    let buffer = Buffer::new("foo.bar  baz\n  qux\r\n");
+    let next =
+        |offset| WordCursor::new(buffer.text(), offset).next_deletion_boundary();
+    assert_eq!(next(0), 3);
+    assert_eq!(next(3), 4);
+    // Following whitespace goes along with the word
+    assert_eq!(next(4), 9);
+    assert_eq!(next(7), 9);
+    assert_eq!(next(12), 13);
+    assert_eq!(next(13), 15);
+    assert_eq!(next(18), 20);
+    assert_eq!(next(20), 20);
+}
+
+#[test]
+fn test_deletion_boundary_multibyte() {
+    let buffer = Buffer::new("añb, ü");
+    let mut cursor = WordCursor::new(buffer.text(), buffer.len());
+    assert_eq!(cursor.prev_deletion_boundary(), 6);
+    assert_eq!(cursor.prev_deletion_boundary(), 4);
+    assert_eq!(cursor.prev_deletion_boundary(), 0);
+
+    let mut cursor = WordCursor::new(buffer.text(), 0);
+    assert_eq!(cursor.next_deletion_boundary(), 4);
+    assert_eq!(cursor.next_deletion_boundary(), 6);
+    assert_eq!(cursor.next_deletion_boundary(), buffer.len());
+}
@@ -11,6 +11,7 @@ use druid::{
     Color, ExtEventSink, FontFamily, Size, Target,
 };
 use indexmap::IndexMap;
+use lapce_core::editor::{AutoPairOptions, EditOptions};
 use lapce_proxy::plugin::PluginCatalog;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
@@ -159,12 +160,34 @@ pub struct EditorConfig {
         desc = "Whether it should format the document on save (if there is an available formatter)"
     )]
     pub format_on_save: bool,
+    #[field_names(
+        desc = "If backspace inside an empty pair only removes the closing character when it was just inserted automatically"
+    )]
+    pub delete_only_auto_inserted_pairs: bool,
+    #[field_names(
+        desc = "How long (in ms) a pause in typing has to be to start a new undo group"
+    )]
+    pub undo_group_threshold: u64,
 }
 
 impl EditorConfig {
     pub fn font_family(&self) -> FontFamily {
         FontFamily::new_unchecked(self.font_family.clone())
     }
+
+    pub fn auto_pair_options(&self) -> AutoPairOptions {
+        AutoPairOptions {
+            delete_only_auto_inserted_pairs: self.delete_only_auto_inserted_pairs,
+            ..Default::default()
+        }
+    }
+
+    pub fn edit_options(&self) -> EditOptions {
+        EditOptions {
+            auto_pairs: self.auto_pair_options(),
+            tab_width: self.tab_width,
+        }
+    }
 }
 
 #[derive(FieldNames, Debug, Clone, Deserialize, Serialize, Default)]
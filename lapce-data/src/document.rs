@@ -7,6 +7,7 @@ use std::{
         atomic::{self},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use druid::{
@@ -19,7 +20,7 @@ use lapce_core::{
     buffer::{Buffer, DiffLines, InvalLines},
     command::{EditCommand, MultiSelectionCommand},
     cursor::{ColPosition, Cursor, CursorMode},
-    editor::{EditType, Editor},
+    editor::{EditOptions, EditType, Editor},
     language::LapceLanguage,
     mode::{Mode, MotionMode},
     movement::{LinePosition, Movement},
@@ -625,10 +626,20 @@ impl Document {
         &mut self,
         cursor: &mut Cursor,
         s: &str,
+        config: &Config,
     ) -> Vec<(RopeDelta, InvalLines)> {
         let old_cursor = cursor.mode.clone();
-        let deltas =
-            Editor::insert(cursor, &mut self.buffer, s, self.syntax.as_ref());
+        self.buffer.set_undo_group_threshold(Duration::from_millis(
+            config.editor.undo_group_threshold,
+        ));
+        let deltas = Editor::insert_at(
+            cursor,
+            &mut self.buffer,
+            s,
+            self.syntax.as_ref(),
+            &config.editor.auto_pair_options(),
+            Instant::now(),
+        );
         self.buffer_mut().set_cursor_before(old_cursor);
         self.buffer_mut().set_cursor_after(cursor.mode.clone());
         self.apply_deltas(&deltas);
@@ -651,10 +662,10 @@ impl Document {
         cmd: &EditCommand,
         modal: bool,
         register: &mut Register,
+        options: &EditOptions,
     ) -> Vec<(RopeDelta, InvalLines)> {
         let mut clipboard = SystemClipboard {};
-        let old_cursor = cursor.mode.clone();
-        let deltas = Editor::do_edit(
+        let deltas = Editor::do_edit_with_options(
             cursor,
             &mut self.buffer,
             cmd,
@@ -662,9 +673,8 @@ impl Document {
             &mut clipboard,
             modal,
             register,
+            options,
         );
-        self.buffer_mut().set_cursor_before(old_cursor);
-        self.buffer_mut().set_cursor_after(cursor.mode.clone());
         self.apply_deltas(&deltas);
         deltas
     }
@@ -790,49 +800,15 @@ impl Document {
                 }
             }
             SelectNextCurrent => {
-                if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
-                    if !selection.is_empty() {
-                        let mut had_caret = false;
-                        for region in selection.regions_mut() {
-                            if region.is_caret() {
-                                had_caret = true;
-                                let (start, end) =
-                                    self.buffer.select_word(region.start());
-                                region.start = start;
-                                region.end = end;
-                            }
-                        }
-                        if !had_caret {
-                            let r = selection.last_inserted().unwrap();
-                            let search_str =
-                                self.buffer.slice_to_cow(r.min()..r.max());
-                            let mut find = Find::new(0);
-                            find.set_find(&search_str, false, false, false);
-                            let mut offset = r.max();
-                            let mut seen = HashSet::new();
-                            while let Some((start, end)) =
-                                find.next(self.buffer.text(), offset, false, true)
-                            {
-                                if !selection
-                                    .regions()
-                                    .iter()
-                                    .any(|r| r.min() == start && r.max() == end)
-                                {
-                                    selection.add_region(SelRegion::new(
-                                        start, end, None,
-                                    ));
-                                    break;
-                                }
-                                if seen.contains(&end) {
-                                    break;
-                                }
-                                offset = end;
-                                seen.insert(offset);
-                            }
-                        }
-                    }
-                    cursor.set_insert(selection);
-                }
+                let mut find = Find::new(0);
+                Editor::select_next_occurrence(
+                    cursor,
+                    &self.buffer,
+                    |search_str, offset| {
+                        find.set_find(search_str, false, false, false);
+                        find.next(self.buffer.text(), offset, false, false)
+                    },
+                );
             }
             SelectSkipCurrent => {
                 if let CursorMode::Insert(mut selection) = cursor.mode.clone() {
@@ -908,7 +884,7 @@ impl Document {
     }
 
     pub fn do_paste(&mut self, cursor: &mut Cursor, data: &RegisterData) {
-        let deltas = Editor::do_paste(cursor, &mut self.buffer, data);
+        let deltas = Editor::do_paste(cursor, &mut self.buffer, data, false);
         self.apply_deltas(&deltas)
     }
 
@@ -1058,10 +1034,15 @@ impl Document {
     ) -> usize {
         match *horiz {
             ColPosition::Col(x) => {
-                let text_layout =
-                    self.get_text_layout(text, line, font_size, config);
-                let n = text_layout.hit_test_point(Point::new(x, 0.0)).idx;
-                n.min(self.buffer.line_end_col(line, caret))
+                let char_width = config.char_width(text, font_size as f64);
+                let col = (x / char_width).round() as usize;
+                let offset = self.buffer.offset_of_visual_col(
+                    line,
+                    col,
+                    config.editor.tab_width,
+                    caret,
+                );
+                offset - self.buffer.offset_of_line(line)
             }
             ColPosition::End => self.buffer.line_end_col(line, caret),
             ColPosition::Start => 0,
@@ -1071,6 +1052,22 @@ impl Document {
         }
     }
 
+    /// The horizontal position of `offset` to keep across vertical moves. It
+    /// comes from the visual column, so that wide and zero width graphemes
+    /// line up with the columns of other lines.
+    fn horiz_of_offset(
+        &self,
+        text: &mut PietText,
+        offset: usize,
+        font_size: usize,
+        config: &Config,
+    ) -> ColPosition {
+        let col = self
+            .buffer
+            .visual_col_of_offset(offset, config.editor.tab_width);
+        ColPosition::Col(col as f64 * config.char_width(text, font_size as f64))
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn move_region(
         &self,
@@ -1112,6 +1109,9 @@ impl Document {
         register: &mut Register,
         config: &Config,
     ) {
+        if let Movement::FindChar(search) = movement {
+            cursor.last_char_search = Some(*search);
+        }
         match cursor.mode {
             CursorMode::Normal(offset) => {
                 let (new_offset, horiz) = self.move_offset(
@@ -1136,9 +1136,9 @@ impl Document {
                         config,
                     );
                     let (start, end) = match movement {
-                        Movement::EndOfLine | Movement::WordEndForward => {
-                            (offset, moved_new_offset)
-                        }
+                        Movement::EndOfLine
+                        | Movement::WordEndForward
+                        | Movement::BigWordEndForward => (offset, moved_new_offset),
                         Movement::MatchPairs => {
                             if new_offset > offset {
                                 (offset, moved_new_offset)
@@ -1146,6 +1146,11 @@ impl Document {
                                 (moved_new_offset, new_offset)
                             }
                         }
+                        Movement::FindChar(search)
+                            if search.forward && new_offset > offset =>
+                        {
+                            (offset, moved_new_offset)
+                        }
                         _ => (offset, new_offset),
                     };
                     let deltas = Editor::execute_motion_mode(
@@ -1272,9 +1277,7 @@ impl Document {
                 };
 
                 let horiz = horiz.cloned().unwrap_or_else(|| {
-                    ColPosition::Col(
-                        self.point_of_offset(text, offset, font_size, config).x,
-                    )
+                    self.horiz_of_offset(text, offset, font_size, config)
                 });
                 let col = self.line_horiz_col(
                     text,
@@ -1294,9 +1297,7 @@ impl Document {
                 let line = (line + count).min(last_line);
 
                 let horiz = horiz.cloned().unwrap_or_else(|| {
-                    ColPosition::Col(
-                        self.point_of_offset(text, offset, font_size, config).x,
-                    )
+                    self.horiz_of_offset(text, offset, font_size, config)
                 });
                 let col = self.line_horiz_col(
                     text,
@@ -1353,9 +1354,7 @@ impl Document {
                     LinePosition::Last => self.buffer.last_line(),
                 };
                 let horiz = horiz.cloned().unwrap_or_else(|| {
-                    ColPosition::Col(
-                        self.point_of_offset(text, offset, font_size, config).x,
-                    )
+                    self.horiz_of_offset(text, offset, font_size, config)
                 });
                 let col = self.line_horiz_col(
                     text,
@@ -1393,6 +1392,40 @@ impl Document {
                 let new_offset = self.buffer.move_n_words_backward(offset, count);
                 (new_offset, None)
             }
+            Movement::BigWordEndForward => {
+                let new_offset = self.buffer.move_n_big_wordends_forward(
+                    offset,
+                    count,
+                    mode == Mode::Insert,
+                );
+                (new_offset, None)
+            }
+            Movement::BigWordForward => {
+                let new_offset = self.buffer.move_n_big_words_forward(offset, count);
+                (new_offset, None)
+            }
+            Movement::BigWordBackward => {
+                let new_offset =
+                    self.buffer.move_n_big_words_backward(offset, count);
+                (new_offset, None)
+            }
+            Movement::ParagraphForward => {
+                let new_offset =
+                    self.buffer.move_n_paragraphs_forward(offset, count);
+                let new_offset = if mode == Mode::Insert {
+                    new_offset
+                } else {
+                    self.buffer
+                        .offset_line_end(new_offset, false)
+                        .min(new_offset)
+                };
+                (new_offset, None)
+            }
+            Movement::ParagraphBackward => {
+                let new_offset =
+                    self.buffer.move_n_paragraphs_backward(offset, count);
+                (new_offset, None)
+            }
             Movement::NextUnmatched(c) => {
                 if let Some(syntax) = self.syntax.as_ref() {
                     let new_offset = syntax
@@ -1419,17 +1452,19 @@ impl Document {
                     (new_offset, None)
                 }
             }
+            Movement::FindChar(search) => {
+                let new_offset = self
+                    .buffer
+                    .find_char_on_line(offset, search, count)
+                    .unwrap_or(offset);
+                (new_offset, None)
+            }
             Movement::MatchPairs => {
-                if let Some(syntax) = self.syntax.as_ref() {
-                    let new_offset =
-                        syntax.find_matching_pair(offset).unwrap_or(offset);
-                    (new_offset, None)
-                } else {
-                    let new_offset = WordCursor::new(self.buffer.text(), offset)
-                        .match_pairs()
-                        .unwrap_or(offset);
-                    (new_offset, None)
-                }
+                let new_offset = self
+                    .buffer
+                    .matching_bracket_offset(offset, self.syntax.as_ref())
+                    .unwrap_or(offset);
+                (new_offset, None)
             }
         }
     }
@@ -2,7 +2,7 @@ use crate::command::LapceCommand;
 use crate::command::LAPCE_COMMAND;
 use crate::command::LAPCE_SAVE_FILE_AS;
 use crate::command::{CommandExecuted, CommandKind};
-use crate::completion::{CompletionData, CompletionStatus, Snippet};
+use crate::completion::{CompletionData, CompletionStatus};
 use crate::config::Config;
 use crate::data::{
     EditorDiagnostic, InlineFindDirection, LapceEditorData, LapceMainSplitData,
@@ -34,11 +34,12 @@ use druid::{
 };
 use druid::{ExtEventSink, MouseEvent};
 use indexmap::IndexMap;
-use lapce_core::buffer::{DiffLines, InvalLines};
+use lapce_core::buffer::{Buffer, DiffLines, InvalLines};
 use lapce_core::command::{
     EditCommand, FocusCommand, MotionModeCommand, MultiSelectionCommand,
 };
 use lapce_core::mode::{Mode, MotionMode};
+use lapce_core::snippet::Snippet;
 pub use lapce_core::syntax::Syntax;
 use lsp_types::CodeActionOrCommand;
 use lsp_types::CompletionTextEdit;
@@ -1170,6 +1171,12 @@ impl LapceEditorBufferData {
         if movement.is_jump() && movement != &self.editor.last_movement_new {
             Arc::make_mut(&mut self.editor).save_jump_location(&self.doc);
         }
+        if movement.is_jump() {
+            let offset = self.editor.cursor.offset();
+            Arc::make_mut(&mut self.doc)
+                .buffer_mut()
+                .set_mark(Buffer::JUMP_MARK, offset);
+        }
         Arc::make_mut(&mut self.editor).last_movement_new = movement.clone();
 
         let register = Arc::make_mut(&mut self.main_split.register);
@@ -1208,17 +1215,26 @@ impl LapceEditorBufferData {
         cmd: &EditCommand,
     ) -> CommandExecuted {
         let modal = self.config.lapce.modal && !self.editor.content.is_input();
+        let options = self.config.editor.edit_options();
         let doc = Arc::make_mut(&mut self.doc);
         let register = Arc::make_mut(&mut self.main_split.register);
         let cursor = &mut Arc::make_mut(&mut self.editor).cursor;
-        let yank_data =
-            if let lapce_core::cursor::CursorMode::Visual { .. } = &cursor.mode {
+        // These commands record their own register data
+        let yank_data = match &cursor.mode {
+            lapce_core::cursor::CursorMode::Visual { .. }
+                if !matches!(
+                    cmd,
+                    EditCommand::DeleteLine
+                        | EditCommand::DeleteToLineEnd
+                        | EditCommand::DeleteToLineStart
+                ) =>
+            {
                 Some(cursor.yank(doc.buffer()))
-            } else {
-                None
-            };
+            }
+            _ => None,
+        };
 
-        let deltas = doc.do_edit(cursor, cmd, modal, register);
+        let deltas = doc.do_edit(cursor, cmd, modal, register, &options);
 
         if !deltas.is_empty() {
             if let Some(data) = yank_data {
@@ -1948,7 +1964,7 @@ impl KeyPressFocus for LapceEditorBufferData {
             self.initiate_diagnostics_offset();
             let doc = Arc::make_mut(&mut self.doc);
             let cursor = &mut Arc::make_mut(&mut self.editor).cursor;
-            let deltas = doc.do_insert(cursor, c);
+            let deltas = doc.do_insert(cursor, c, &self.config);
 
             self.update_completion(ctx, false);
             self.cancel_hover();
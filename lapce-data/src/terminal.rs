@@ -298,6 +298,15 @@ impl KeyPressFocus for LapceTerminalViewData {
                     Movement::WordBackward => {
                         term.vi_motion(ViMotion::SemanticLeft);
                     }
+                    Movement::BigWordForward => {
+                        term.vi_motion(ViMotion::WordRight);
+                    }
+                    Movement::BigWordEndForward => {
+                        term.vi_motion(ViMotion::WordRightEnd);
+                    }
+                    Movement::BigWordBackward => {
+                        term.vi_motion(ViMotion::WordLeft);
+                    }
                     Movement::Line(line) => {
                         match line {
                             LinePosition::First => {